@@ -0,0 +1,881 @@
+use crate::backend::disassembler::{CSR_NAMES, REG_NAMES};
+use crate::backend::loader::Program;
+use crate::backend::util::types::{Byte, Word, funct3, funct7, funct12, opcode};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// Where `.text` starts, same as `tests/link.ld`
+pub const TEXT_BASE: u32 = 0x40000000;
+/// Where `.data` starts, same as `tests/link.ld`
+pub const DATA_BASE: u32 = 0x50000000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    /// Zero based
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line + 1, self.message)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Section {
+    Text,
+    Data,
+}
+
+enum Statement {
+    Section(Section),
+    Inst {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Word(Vec<String>),
+    Byte(Vec<String>),
+    Asciz(Vec<u8>),
+}
+
+impl Statement {
+    fn get_size(&self) -> Result<u32, String> {
+        Ok(match self {
+            Statement::Section(_) => 0,
+            Statement::Inst { mnemonic, operands } => match mnemonic.as_str() {
+                // the only pseudo-instruction whose length depends on its operands
+                "li" => encode_inst(mnemonic, operands, 0, &HashMap::new())?.len() as u32 * 4,
+                "la" | "call" => 8,
+                _ => 4,
+            },
+            Statement::Word(values) => values.len() as u32 * 4,
+            Statement::Byte(values) => values.len() as u32,
+            Statement::Asciz(bytes) => bytes.len() as u32,
+        })
+    }
+
+    /// Instructions and words are aligned, labels in front of them move along
+    fn get_align(&self) -> u32 {
+        match self {
+            Statement::Inst { .. } | Statement::Word(_) => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// Assembles RV32IM and Zicsr in two passes, the first one places labels
+///
+/// The entry is `_start` if it is defined, otherwise the start of `.text`
+///
+/// Supports the `li`, `la`, `call`, `ret`, `j`, `mv` and `nop` pseudo-instructions,
+/// and the `.text`, `.data`, `.word`, `.byte` and `.asciz` directives.
+/// Branch and jump targets are labels or absolute addresses.
+/// Every line that fails is reported
+pub fn assemble(source: &str) -> Result<Program, Vec<AsmError>> {
+    let mut errors = Vec::new();
+    let mut labels = HashMap::new();
+    let mut pending_labels = Vec::new();
+    let mut statements = Vec::new();
+    let mut section = Section::Text;
+    let mut addrs = [TEXT_BASE, DATA_BASE];
+
+    for (line, text) in source.lines().enumerate() {
+        let (line_labels, statement) = match parse_line(text) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                errors.push(AsmError { line, message });
+                continue;
+            }
+        };
+        for label in line_labels {
+            if labels.contains_key(&label) || pending_labels.contains(&label) {
+                errors.push(AsmError {
+                    line,
+                    message: format!("{} is already defined", label),
+                });
+            } else {
+                pending_labels.push(label);
+            }
+        }
+
+        let Some(statement) = statement else {
+            continue;
+        };
+        let size = match statement.get_size() {
+            Ok(size) => size,
+            Err(message) => {
+                errors.push(AsmError { line, message });
+                continue;
+            }
+        };
+        let addr = &mut addrs[section as usize];
+        if let Statement::Section(new_section) = statement {
+            labels.extend(pending_labels.drain(..).map(|label| (label, *addr)));
+            section = new_section;
+            continue;
+        }
+
+        let align = statement.get_align();
+        *addr = addr.next_multiple_of(align);
+        labels.extend(pending_labels.drain(..).map(|label| (label, *addr)));
+        statements.push((line, *addr, statement));
+        *addr += size;
+    }
+    let addr = addrs[section as usize];
+    labels.extend(pending_labels.drain(..).map(|label| (label, addr)));
+
+    let mut mem = BTreeMap::new();
+    for (line, addr, statement) in statements {
+        let bytes = match statement {
+            Statement::Inst { mnemonic, operands } => {
+                encode_inst(&mnemonic, &operands, addr, &labels).map(|insts| {
+                    insts
+                        .into_iter()
+                        .flat_map(|inst| inst.to_le_bytes())
+                        .collect::<Vec<_>>()
+                })
+            }
+            Statement::Word(values) => values
+                .iter()
+                .map(|value| resolve(value, &labels).map(|word| word.to_le_bytes()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|words| words.concat()),
+            Statement::Byte(values) => values
+                .iter()
+                .map(|value| match parse_imm(value)? {
+                    byte @ -0x80..=0xFF => Ok(byte as u8),
+                    byte => Err(format!("{} does not fit in a byte", byte)),
+                })
+                .collect(),
+            Statement::Asciz(bytes) => Ok(bytes),
+            Statement::Section(_) => unreachable!(),
+        };
+        match bytes {
+            Ok(bytes) => {
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    mem.insert(Word::from(addr + i as u32), Byte::from(byte));
+                }
+            }
+            Err(message) => errors.push(AsmError { line, message }),
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| error.line);
+        return Err(errors);
+    }
+    Ok(Program {
+        mem,
+        entry: labels.get("_start").copied().unwrap_or(TEXT_BASE),
+        label: labels
+            .into_iter()
+            .map(|(label, addr)| (Word::from(addr), label))
+            .collect(),
+    })
+}
+
+/// The labels defined on a line and what follows them
+fn parse_line(text: &str) -> Result<(Vec<String>, Option<Statement>), String> {
+    let mut rest = strip_comment(text).trim();
+    let mut labels = Vec::new();
+    while let Some((label, after)) = rest.split_once(':')
+        && is_label(label.trim())
+    {
+        labels.push(label.trim().to_string());
+        rest = after.trim();
+    }
+    if rest.is_empty() {
+        return Ok((labels, None));
+    }
+
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let name = name.to_lowercase();
+    let args = args.trim();
+    let statement = match name.as_str() {
+        ".text" => Some(Statement::Section(Section::Text)),
+        ".data" => Some(Statement::Section(Section::Data)),
+        ".globl" | ".global" => None,
+        ".word" | ".byte" if args.is_empty() => {
+            return Err(format!("{} needs at least one value", name));
+        }
+        ".word" => Some(Statement::Word(split_operands(args))),
+        ".byte" => Some(Statement::Byte(split_operands(args))),
+        ".asciz" => Some(Statement::Asciz(parse_string(args)?)),
+        _ if name.starts_with('.') => return Err(format!("unknown directive {}", name)),
+        _ => Some(Statement::Inst {
+            mnemonic: name,
+            operands: split_operands(args),
+        }),
+    };
+    Ok((labels, statement))
+}
+
+/// Drops everything from a `#` that is not inside a string
+fn strip_comment(text: &str) -> &str {
+    let mut is_in_string = false;
+    let mut is_escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if is_escaped => is_escaped = false,
+            '\\' if is_in_string => is_escaped = true,
+            '"' => is_in_string = !is_in_string,
+            '#' if !is_in_string => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn is_label(label: &str) -> bool {
+    label
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+fn split_operands(args: &str) -> Vec<String> {
+    if args.is_empty() {
+        return Vec::new();
+    }
+    args.split(',')
+        .map(|operand| operand.trim().to_string())
+        .collect()
+}
+
+/// A quoted string with its escapes resolved and a null terminator
+fn parse_string(args: &str) -> Result<Vec<u8>, String> {
+    let inner = args
+        .strip_prefix('"')
+        .and_then(|args| args.strip_suffix('"'))
+        .ok_or(format!("expected a quoted string, got {}", args))?;
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => b'\0',
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            c => return Err(format!("unknown escape \\{}", c.unwrap_or(' '))),
+        });
+    }
+    bytes.push(0);
+    Ok(bytes)
+}
+
+/// Decimal, `0x` hex or `0b` binary with an optional minus
+fn parse_imm(operand: &str) -> Result<i64, String> {
+    let (is_negative, digits) = match operand.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, operand),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2)
+    } else {
+        digits.parse::<i64>()
+    }
+    .map_err(|_| format!("{} is not a number", operand))?;
+    Ok(if is_negative { -value } else { value })
+}
+
+fn parse_reg(operand: &str) -> Result<u32, String> {
+    let operand = operand.to_lowercase();
+    if operand == "fp" {
+        return Ok(8);
+    }
+    if let Some(idx) = operand.strip_prefix('x')
+        && let Ok(idx) = idx.parse::<u32>()
+        && idx < 32
+    {
+        return Ok(idx);
+    }
+    REG_NAMES
+        .iter()
+        .position(|name| *name == operand)
+        .map(|idx| idx as u32)
+        .ok_or(format!("{} is not a register", operand))
+}
+
+/// `imm(reg)` or `(reg)`
+fn parse_mem(operand: &str) -> Result<(i64, u32), String> {
+    let (imm, reg) = operand
+        .strip_suffix(')')
+        .and_then(|operand| operand.split_once('('))
+        .ok_or(format!("expected imm(reg), got {}", operand))?;
+    let imm = if imm.trim().is_empty() {
+        0
+    } else {
+        parse_imm(imm.trim())?
+    };
+    Ok((imm, parse_reg(reg.trim())?))
+}
+
+fn parse_csr(operand: &str) -> Result<u32, String> {
+    let operand = operand.to_lowercase();
+    match CSR_NAMES.iter().find(|(_, name)| *name == operand) {
+        Some((addr, _)) => Ok(*addr as u32),
+        None => match parse_imm(&operand)? {
+            addr @ 0..=0xFFF => Ok(addr as u32),
+            addr => Err(format!("{} is not a csr address", addr)),
+        },
+    }
+}
+
+/// A label or an absolute address
+fn resolve(operand: &str, labels: &HashMap<String, u32>) -> Result<u32, String> {
+    if let Some(addr) = labels.get(operand) {
+        return Ok(*addr);
+    }
+    if is_label(operand) {
+        return Err(format!("{} is not defined", operand));
+    }
+    match parse_imm(operand)? {
+        addr @ -0x80000000..=0xFFFFFFFF => Ok(addr as u32),
+        addr => Err(format!("{} does not fit in a word", addr)),
+    }
+}
+
+fn check_signed(imm: i64, num_bits: u32) -> Result<u32, String> {
+    let limit = 1i64 << (num_bits - 1);
+    if (-limit..limit).contains(&imm) {
+        Ok(imm as u32)
+    } else {
+        Err(format!("{} does not fit in {} bits", imm, num_bits))
+    }
+}
+
+/// The `lui`/`auipc` and `addi` parts of a 32 bit immediate
+fn split_imm(imm: u32) -> (i64, i64) {
+    let upper = imm.wrapping_add(0x800) >> 12;
+    let lower = imm.wrapping_sub(upper << 12) as i32;
+    (upper as i64, lower as i64)
+}
+
+fn encode_r(opcode: u8, funct3: u8, funct7: u8, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    ((funct7 as u32) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | ((funct3 as u32) << 12)
+        | (rd << 7)
+        | opcode as u32
+}
+
+fn encode_i(opcode: u8, funct3: u8, rd: u32, rs1: u32, imm: i64) -> Result<u32, String> {
+    let imm = check_signed(imm, 12)?;
+    Ok(((imm & 0xFFF) << 20) | (rs1 << 15) | ((funct3 as u32) << 12) | (rd << 7) | opcode as u32)
+}
+
+fn encode_s(funct3: u8, rs1: u32, rs2: u32, imm: i64) -> Result<u32, String> {
+    let imm = check_signed(imm, 12)?;
+    Ok((((imm >> 5) & 0x7F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | ((funct3 as u32) << 12)
+        | ((imm & 0x1F) << 7)
+        | opcode::STORE as u32)
+}
+
+fn encode_b(funct3: u8, rs1: u32, rs2: u32, offset: i64) -> Result<u32, String> {
+    if offset & 0x1 != 0 {
+        return Err(format!("branch offset {} is odd", offset));
+    }
+    let imm = check_signed(offset, 13)?;
+    Ok((((imm >> 12) & 0x1) << 31)
+        | (((imm >> 5) & 0x3F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | ((funct3 as u32) << 12)
+        | (((imm >> 1) & 0xF) << 8)
+        | (((imm >> 11) & 0x1) << 7)
+        | opcode::BR as u32)
+}
+
+fn encode_u(opcode: u8, rd: u32, imm: i64) -> Result<u32, String> {
+    if !(0..=0xFFFFF).contains(&imm) {
+        return Err(format!("{} does not fit in 20 bits", imm));
+    }
+    Ok(((imm as u32) << 12) | (rd << 7) | opcode as u32)
+}
+
+fn encode_j(rd: u32, offset: i64) -> Result<u32, String> {
+    if offset & 0x1 != 0 {
+        return Err(format!("jump offset {} is odd", offset));
+    }
+    let imm = check_signed(offset, 21)?;
+    Ok((((imm >> 20) & 0x1) << 31)
+        | (((imm >> 1) & 0x3FF) << 21)
+        | (((imm >> 11) & 0x1) << 20)
+        | (((imm >> 12) & 0xFF) << 12)
+        | (rd << 7)
+        | opcode::JAL as u32)
+}
+
+const R_TYPE: [(&str, u8, u8); 18] = [
+    ("add", funct3::arith::ADD, 0x00),
+    ("sub", funct3::arith::ADD, 0x20),
+    ("sll", funct3::arith::SLL, 0x00),
+    ("slt", funct3::arith::SLT, 0x00),
+    ("sltu", funct3::arith::SLTU, 0x00),
+    ("xor", funct3::arith::XOR, 0x00),
+    ("srl", funct3::arith::SR, 0x00),
+    ("sra", funct3::arith::SR, 0x20),
+    ("or", funct3::arith::OR, 0x00),
+    ("and", funct3::arith::AND, 0x00),
+    ("mul", funct3::muldiv::MUL, funct7::MULDIV),
+    ("mulh", funct3::muldiv::MULH, funct7::MULDIV),
+    ("mulhsu", funct3::muldiv::MULHSU, funct7::MULDIV),
+    ("mulhu", funct3::muldiv::MULHU, funct7::MULDIV),
+    ("div", funct3::muldiv::DIV, funct7::MULDIV),
+    ("divu", funct3::muldiv::DIVU, funct7::MULDIV),
+    ("rem", funct3::muldiv::REM, funct7::MULDIV),
+    ("remu", funct3::muldiv::REMU, funct7::MULDIV),
+];
+
+const I_TYPE: [(&str, u8); 6] = [
+    ("addi", funct3::arith::ADD),
+    ("slti", funct3::arith::SLT),
+    ("sltiu", funct3::arith::SLTU),
+    ("xori", funct3::arith::XOR),
+    ("ori", funct3::arith::OR),
+    ("andi", funct3::arith::AND),
+];
+
+const SHIFT: [(&str, u8, u8); 3] = [
+    ("slli", funct3::arith::SLL, 0x00),
+    ("srli", funct3::arith::SR, 0x00),
+    ("srai", funct3::arith::SR, 0x20),
+];
+
+const LOAD: [(&str, u8); 5] = [
+    ("lb", funct3::load::LB),
+    ("lh", funct3::load::LH),
+    ("lw", funct3::load::LW),
+    ("lbu", funct3::load::LBU),
+    ("lhu", funct3::load::LHU),
+];
+
+const STORE: [(&str, u8); 3] = [
+    ("sb", funct3::store::SB),
+    ("sh", funct3::store::SH),
+    ("sw", funct3::store::SW),
+];
+
+const BRANCH: [(&str, u8); 6] = [
+    ("beq", funct3::branch::BEQ),
+    ("bne", funct3::branch::BNE),
+    ("blt", funct3::branch::BLT),
+    ("bge", funct3::branch::BGE),
+    ("bltu", funct3::branch::BLTU),
+    ("bgeu", funct3::branch::BGEU),
+];
+
+const CSR: [(&str, u8); 6] = [
+    ("csrrw", funct3::system::CSRRW),
+    ("csrrs", funct3::system::CSRRS),
+    ("csrrc", funct3::system::CSRRC),
+    ("csrrwi", funct3::system::CSRRWI),
+    ("csrrsi", funct3::system::CSRRSI),
+    ("csrrci", funct3::system::CSRRCI),
+];
+
+const PRIV: [(&str, u16); 4] = [
+    ("ecall", funct12::ECALL),
+    ("ebreak", funct12::EBREAK),
+    ("mret", funct12::MRET),
+    ("wfi", funct12::WFI),
+];
+
+fn find<T: Copy>(table: &[(&str, T)], mnemonic: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(name, _)| *name == mnemonic)
+        .map(|(_, value)| *value)
+}
+
+fn expect_operands(operands: &[String], num_operands: usize) -> Result<(), String> {
+    if operands.len() == num_operands {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {} operands, got {}",
+            num_operands,
+            operands.len()
+        ))
+    }
+}
+
+/// The instructions `mnemonic` expands to at `pc`
+fn encode_inst(
+    mnemonic: &str,
+    operands: &[String],
+    pc: u32,
+    labels: &HashMap<String, u32>,
+) -> Result<Vec<u32>, String> {
+    let offset_to = |operand: &str| -> Result<i64, String> {
+        Ok(resolve(operand, labels)?.wrapping_sub(pc) as i32 as i64)
+    };
+
+    if let Some((funct3, funct7)) = R_TYPE
+        .iter()
+        .find(|(name, _, _)| *name == mnemonic)
+        .map(|(_, funct3, funct7)| (*funct3, *funct7))
+    {
+        expect_operands(operands, 3)?;
+        return Ok(vec![encode_r(
+            opcode::REG,
+            funct3,
+            funct7,
+            parse_reg(&operands[0])?,
+            parse_reg(&operands[1])?,
+            parse_reg(&operands[2])?,
+        )]);
+    }
+    if let Some(funct3) = find(&I_TYPE, mnemonic) {
+        expect_operands(operands, 3)?;
+        return Ok(vec![encode_i(
+            opcode::IMM,
+            funct3,
+            parse_reg(&operands[0])?,
+            parse_reg(&operands[1])?,
+            parse_imm(&operands[2])?,
+        )?]);
+    }
+    if let Some((funct3, funct7)) = SHIFT
+        .iter()
+        .find(|(name, _, _)| *name == mnemonic)
+        .map(|(_, funct3, funct7)| (*funct3, *funct7))
+    {
+        expect_operands(operands, 3)?;
+        let shamt = match parse_imm(&operands[2])? {
+            shamt @ 0..=31 => shamt as u32,
+            shamt => return Err(format!("shift amount {} is not within 0 to 31", shamt)),
+        };
+        return Ok(vec![encode_r(
+            opcode::IMM,
+            funct3,
+            funct7,
+            parse_reg(&operands[0])?,
+            parse_reg(&operands[1])?,
+            shamt,
+        )]);
+    }
+    if let Some(funct3) = find(&LOAD, mnemonic) {
+        expect_operands(operands, 2)?;
+        let (imm, rs1) = parse_mem(&operands[1])?;
+        return Ok(vec![encode_i(
+            opcode::LOAD,
+            funct3,
+            parse_reg(&operands[0])?,
+            rs1,
+            imm,
+        )?]);
+    }
+    if let Some(funct3) = find(&STORE, mnemonic) {
+        expect_operands(operands, 2)?;
+        let (imm, rs1) = parse_mem(&operands[1])?;
+        return Ok(vec![encode_s(funct3, rs1, parse_reg(&operands[0])?, imm)?]);
+    }
+    if let Some(funct3) = find(&BRANCH, mnemonic) {
+        expect_operands(operands, 3)?;
+        return Ok(vec![encode_b(
+            funct3,
+            parse_reg(&operands[0])?,
+            parse_reg(&operands[1])?,
+            offset_to(&operands[2])?,
+        )?]);
+    }
+    if let Some(funct3) = find(&CSR, mnemonic) {
+        expect_operands(operands, 3)?;
+        // the immediate forms put a 5 bit immediate where rs1 goes
+        let rs1 = if funct3 & 0b100 != 0 {
+            match parse_imm(&operands[2])? {
+                uimm @ 0..=31 => uimm as u32,
+                uimm => return Err(format!("{} is not within 0 to 31", uimm)),
+            }
+        } else {
+            parse_reg(&operands[2])?
+        };
+        return Ok(vec![
+            (parse_csr(&operands[1])? << 20)
+                | (rs1 << 15)
+                | ((funct3 as u32) << 12)
+                | (parse_reg(&operands[0])? << 7)
+                | opcode::SYSTEM as u32,
+        ]);
+    }
+    if let Some(funct12) = find(&PRIV, mnemonic) {
+        expect_operands(operands, 0)?;
+        return Ok(vec![((funct12 as u32) << 20) | opcode::SYSTEM as u32]);
+    }
+
+    Ok(match mnemonic {
+        "lui" | "auipc" => {
+            expect_operands(operands, 2)?;
+            let opcode = if mnemonic == "lui" {
+                opcode::LUI
+            } else {
+                opcode::AUIPC
+            };
+            vec![encode_u(
+                opcode,
+                parse_reg(&operands[0])?,
+                parse_imm(&operands[1])?,
+            )?]
+        }
+        "jal" => match operands.len() {
+            1 => vec![encode_j(1, offset_to(&operands[0])?)?],
+            _ => {
+                expect_operands(operands, 2)?;
+                vec![encode_j(
+                    parse_reg(&operands[0])?,
+                    offset_to(&operands[1])?,
+                )?]
+            }
+        },
+        "jalr" => match operands.len() {
+            1 => vec![encode_i(opcode::JALR, 0, 1, parse_reg(&operands[0])?, 0)?],
+            2 => {
+                let (imm, rs1) = parse_mem(&operands[1])?;
+                vec![encode_i(
+                    opcode::JALR,
+                    0,
+                    parse_reg(&operands[0])?,
+                    rs1,
+                    imm,
+                )?]
+            }
+            _ => {
+                expect_operands(operands, 3)?;
+                vec![encode_i(
+                    opcode::JALR,
+                    0,
+                    parse_reg(&operands[0])?,
+                    parse_reg(&operands[1])?,
+                    parse_imm(&operands[2])?,
+                )?]
+            }
+        },
+        "fence" => vec![0x0FF0000F],
+        "nop" => {
+            expect_operands(operands, 0)?;
+            vec![encode_i(opcode::IMM, funct3::arith::ADD, 0, 0, 0)?]
+        }
+        "mv" => {
+            expect_operands(operands, 2)?;
+            vec![encode_i(
+                opcode::IMM,
+                funct3::arith::ADD,
+                parse_reg(&operands[0])?,
+                parse_reg(&operands[1])?,
+                0,
+            )?]
+        }
+        "li" => {
+            expect_operands(operands, 2)?;
+            let rd = parse_reg(&operands[0])?;
+            let imm = match parse_imm(&operands[1])? {
+                imm @ -0x80000000..=0xFFFFFFFF => imm as u32,
+                imm => return Err(format!("{} does not fit in a word", imm)),
+            };
+            if (-0x800..0x800).contains(&(imm as i32)) {
+                vec![encode_i(
+                    opcode::IMM,
+                    funct3::arith::ADD,
+                    rd,
+                    0,
+                    imm as i32 as i64,
+                )?]
+            } else {
+                let (upper, lower) = split_imm(imm);
+                let mut insts = vec![encode_u(opcode::LUI, rd, upper)?];
+                if lower != 0 {
+                    insts.push(encode_i(opcode::IMM, funct3::arith::ADD, rd, rd, lower)?);
+                }
+                insts
+            }
+        }
+        "la" => {
+            expect_operands(operands, 2)?;
+            let rd = parse_reg(&operands[0])?;
+            let (upper, lower) = split_imm(offset_to(&operands[1])? as u32);
+            vec![
+                encode_u(opcode::AUIPC, rd, upper)?,
+                encode_i(opcode::IMM, funct3::arith::ADD, rd, rd, lower)?,
+            ]
+        }
+        "call" => {
+            expect_operands(operands, 1)?;
+            let (upper, lower) = split_imm(offset_to(&operands[0])? as u32);
+            vec![
+                encode_u(opcode::AUIPC, 1, upper)?,
+                encode_i(opcode::JALR, 0, 1, 1, lower)?,
+            ]
+        }
+        "ret" => {
+            expect_operands(operands, 0)?;
+            vec![encode_i(opcode::JALR, 0, 0, 1, 0)?]
+        }
+        "j" => {
+            expect_operands(operands, 1)?;
+            vec![encode_j(0, offset_to(&operands[0])?)?]
+        }
+        _ => return Err(format!("unknown instruction {}", mnemonic)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::disassembler::disassemble;
+
+    fn disassemble_program(program: &Program, start: u32, num_insts: u32) -> Vec<String> {
+        (0..num_insts)
+            .map(|i| {
+                let addr = start + i * 4;
+                let mut inst = 0;
+                for j in 0..4 {
+                    let byte = Into::<Option<u8>>::into(program.mem[&Word::from(addr + j)]);
+                    inst |= (byte.unwrap() as u32) << (j * 8);
+                }
+                disassemble(inst, addr)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_assemble_round_trip() {
+        let source = [
+            "addi sp, sp, -16",
+            "sw ra, 12(sp)",
+            "lw ra, 12(sp)",
+            "sub a0, a0, a1",
+            "mulhsu a0, a0, a1",
+            "srai a0, a0, 31",
+            "lui t0, 0x40000",
+            "auipc t1, 0xfffff",
+            "jalr zero, 0(ra)",
+            "jal ra, 0x40000008",
+            "beq a0, a1, 0x3ffffffc",
+            "bgeu a0, a1, 0x40001000",
+            "sb s11, -2048(t6)",
+            "csrrw zero, mtvec, t0",
+            "csrrsi a0, 0x7c0, 31",
+            "ecall",
+            "mret",
+            "fence",
+        ];
+        let program = assemble(&source.join("\n")).unwrap();
+        assert_eq!(
+            disassemble_program(&program, TEXT_BASE, source.len() as u32),
+            source
+        );
+    }
+
+    #[test]
+    fn test_assemble_pseudo_instructions() {
+        let source = "
+_start:
+    nop
+    li a0, 42
+    li a1, 0x12345678
+    li a2, 0x1000
+    mv a3, a0
+here:
+    la a4, here
+    call func
+    j _start
+func:
+    ret
+";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.entry, TEXT_BASE);
+        assert_eq!(program.label[&Word::from(TEXT_BASE + 0x2C)], "func");
+        assert_eq!(disassemble_program(&program, TEXT_BASE, 12), [
+            "addi zero, zero, 0",
+            "addi a0, zero, 42",
+            "lui a1, 0x12345",
+            "addi a1, a1, 1656",
+            "lui a2, 0x1",
+            "addi a3, a0, 0",
+            "auipc a4, 0x0",
+            "addi a4, a4, 0",
+            "auipc ra, 0x0",
+            "jalr ra, 12(ra)",
+            "jal zero, 0x40000000",
+            "jalr zero, 0(ra)",
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_data() {
+        let source = r#"
+.data
+message: .asciz "hi\n" # not a # comment
+table:
+    .word message, -1
+.text
+    .globl _start
+_start:
+    la a0, table
+"#;
+        let program = assemble(source).unwrap();
+        assert_eq!(program.entry, TEXT_BASE);
+        assert_eq!(program.label[&Word::from(DATA_BASE)], "message");
+        // .word is aligned, the label moves with it
+        assert_eq!(program.label[&Word::from(DATA_BASE + 4)], "table");
+        let bytes = (DATA_BASE..DATA_BASE + 12)
+            .map(|addr| {
+                program
+                    .mem
+                    .get(&Word::from(addr))
+                    .and_then(|byte| Into::<Option<u8>>::into(*byte))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(bytes, [
+            Some(b'h'),
+            Some(b'i'),
+            Some(b'\n'),
+            Some(0),
+            Some(0x00),
+            Some(0x00),
+            Some(0x00),
+            Some(0x50),
+            Some(0xFF),
+            Some(0xFF),
+            Some(0xFF),
+            Some(0xFF),
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let source = "
+    addi a0, a0, 2048
+    foo a0
+    lw a0, a1
+    beq a0, a1, missing
+    add a0, a1, x32
+ok: nop
+ok: nop
+";
+        let errors = assemble(source).unwrap_err();
+        assert_eq!(errors.iter().map(|error| error.line).collect::<Vec<_>>(), [
+            1, 2, 3, 4, 5, 7
+        ]);
+        assert_eq!(errors[1].to_string(), "line 3: unknown instruction foo");
+    }
+}
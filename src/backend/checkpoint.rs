@@ -0,0 +1,359 @@
+use crate::backend::component::regfile::Registers;
+use crate::backend::history::Snapshot;
+use crate::backend::util::byte::Bytes;
+use crate::backend::util::types::States;
+use std::collections::{BTreeMap, VecDeque};
+use strum::IntoEnumIterator;
+
+/// Identifies a checkpoint file, followed by `VERSION`
+pub const MAGIC: &[u8; 8] = b"RSIMCKPT";
+pub const VERSION: u32 = 5;
+
+/// A value that can be written to and read back from a checkpoint
+///
+/// Everything is little endian, collections are prefixed with their length
+pub trait Checkpoint: Sized {
+    fn save(&self, writer: &mut CheckpointWriter);
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String>;
+}
+
+pub struct CheckpointWriter {
+    data: Vec<u8>,
+}
+
+impl CheckpointWriter {
+    pub fn new() -> Self {
+        let mut writer = Self { data: Vec::new() };
+        writer.write_bytes(MAGIC);
+        VERSION.save(&mut writer);
+        writer
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for CheckpointWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CheckpointReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CheckpointReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        let mut reader = Self { data };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err("Not a checkpoint file".to_string());
+        }
+        let version = u32::load(&mut reader)?;
+        if version != VERSION {
+            return Err(format!("Unsupported checkpoint version {}", version));
+        }
+        Ok(reader)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("Unexpected end of checkpoint".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    fn read_len(&mut self) -> Result<usize, String> {
+        let len = u32::load(self)? as usize;
+        // every element takes at least a byte, this stops a corrupted length from allocating
+        if len > self.data.len() {
+            return Err("Unexpected end of checkpoint".to_string());
+        }
+        Ok(len)
+    }
+}
+
+macro_rules! impl_checkpoint_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Checkpoint for $t {
+                fn save(&self, writer: &mut CheckpointWriter) {
+                    writer.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+                    Ok(Self::from_le_bytes(reader.read_array()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_checkpoint_for_int!(u8, u32, u64, u128);
+
+impl Checkpoint for bool {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (*self as u8).save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        match u8::load(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("Invalid bool {}", value)),
+        }
+    }
+}
+
+impl<T: Checkpoint> Checkpoint for Option<T> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.is_some().save(writer);
+        if let Some(value) = self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        if bool::load(reader)? {
+            Ok(Some(T::load(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Checkpoint + Copy + Default, const N: usize> Checkpoint for [T; N] {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        for value in self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let mut array = [T::default(); N];
+        for value in array.iter_mut() {
+            *value = T::load(reader)?;
+        }
+        Ok(array)
+    }
+}
+
+impl<T: Checkpoint> Checkpoint for Vec<T> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (self.len() as u32).save(writer);
+        for value in self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let len = reader.read_len()?;
+        (0..len).map(|_| T::load(reader)).collect()
+    }
+}
+
+impl<T: Checkpoint> Checkpoint for VecDeque<T> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (self.len() as u32).save(writer);
+        for value in self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Vec::<T>::load(reader)?.into())
+    }
+}
+
+impl<A: Checkpoint, B: Checkpoint> Checkpoint for (A, B) {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.0.save(writer);
+        self.1.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok((A::load(reader)?, B::load(reader)?))
+    }
+}
+
+impl<K: Checkpoint + Ord, V: Checkpoint> Checkpoint for BTreeMap<K, V> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (self.len() as u32).save(writer);
+        for (key, value) in self {
+            key.save(writer);
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let len = reader.read_len()?;
+        (0..len)
+            .map(|_| Ok((K::load(reader)?, V::load(reader)?)))
+            .collect()
+    }
+}
+
+impl Checkpoint for String {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.as_bytes().to_vec().save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        String::from_utf8(Vec::load(reader)?).map_err(|err| err.to_string())
+    }
+}
+
+impl<const T: usize> Checkpoint for Bytes<T> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.data.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Bytes {
+            data: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+impl Checkpoint for States {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (*self as u32).save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let discriminant = u32::load(reader)?;
+        States::iter()
+            .find(|&state| state as u32 == discriminant)
+            .ok_or_else(|| format!("Invalid state {}", discriminant))
+    }
+}
+
+impl Checkpoint for Registers {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.data.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            data: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+/// The undo log is not saved, memory is saved separately as of the snapshot
+impl Checkpoint for Snapshot {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.cycles_ran.save(writer);
+        self.instructions_ran.save(writer);
+        self.is_settled.save(writer);
+        self.state.save(writer);
+        self.pc.save(writer);
+        self.ir.save(writer);
+        self.registers.save(writer);
+        self.mstatus.save(writer);
+        self.mie.save(writer);
+        self.mip.save(writer);
+        self.mtvec.save(writer);
+        self.mscratch.save(writer);
+        self.mepc.save(writer);
+        self.mcause.save(writer);
+        self.mtval.save(writer);
+        self.mcycle.save(writer);
+        self.minstret.save(writer);
+        self.keyboard_mmio_ctl.save(writer);
+        self.vga_mmio_ctl.save(writer);
+        self.clint_mmio_ctl.save(writer);
+        self.htif_mmio_ctl.save(writer);
+        self.icache.save(writer);
+        self.dcache.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            cycles_ran: Checkpoint::load(reader)?,
+            instructions_ran: Checkpoint::load(reader)?,
+            is_settled: Checkpoint::load(reader)?,
+            state: Checkpoint::load(reader)?,
+            pc: Checkpoint::load(reader)?,
+            ir: Checkpoint::load(reader)?,
+            registers: Checkpoint::load(reader)?,
+            mstatus: Checkpoint::load(reader)?,
+            mie: Checkpoint::load(reader)?,
+            mip: Checkpoint::load(reader)?,
+            mtvec: Checkpoint::load(reader)?,
+            mscratch: Checkpoint::load(reader)?,
+            mepc: Checkpoint::load(reader)?,
+            mcause: Checkpoint::load(reader)?,
+            mtval: Checkpoint::load(reader)?,
+            mcycle: Checkpoint::load(reader)?,
+            minstret: Checkpoint::load(reader)?,
+            keyboard_mmio_ctl: Checkpoint::load(reader)?,
+            vga_mmio_ctl: Checkpoint::load(reader)?,
+            clint_mmio_ctl: Checkpoint::load(reader)?,
+            htif_mmio_ctl: Checkpoint::load(reader)?,
+            icache: Checkpoint::load(reader)?,
+            dcache: Checkpoint::load(reader)?,
+            mem_undo_log: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::util::types::{Byte, Word};
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut memory = BTreeMap::new();
+        memory.insert(Word::from(0x40000000u32), Byte::from(0x13u8));
+        memory.insert(Word::from(0x40000001u32), Byte::unknown());
+        let mut label = BTreeMap::new();
+        label.insert(Word::from(0x40000000u32), "_start".to_string());
+
+        let mut writer = CheckpointWriter::new();
+        memory.save(&mut writer);
+        label.save(&mut writer);
+        States::Fetch.save(&mut writer);
+        let data = writer.into_inner();
+
+        let mut reader = CheckpointReader::new(&data).unwrap();
+        assert_eq!(BTreeMap::<Word, Byte>::load(&mut reader).unwrap(), memory);
+        assert_eq!(BTreeMap::<Word, String>::load(&mut reader).unwrap(), label);
+        assert_eq!(States::load(&mut reader).unwrap(), States::Fetch);
+        assert!(u8::load(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_states() {
+        let mut writer = CheckpointWriter::new();
+        States::MulDiv.save(&mut writer);
+        99u32.save(&mut writer);
+        let data = writer.into_inner();
+
+        let mut reader = CheckpointReader::new(&data).unwrap();
+        assert_eq!(States::load(&mut reader).unwrap(), States::MulDiv);
+        assert!(States::load(&mut reader).is_err());
+        assert_eq!(&data[data.len() - 8..data.len() - 4], 11u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_checkpoint_bad_header() {
+        assert!(CheckpointReader::new(b"RSIMCKP").is_err());
+        assert!(CheckpointReader::new(b"NOTACKPT\x01\x00\x00\x00").is_err());
+        let mut newer_version = MAGIC.to_vec();
+        newer_version.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(CheckpointReader::new(&newer_version).is_err());
+    }
+}
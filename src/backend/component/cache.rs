@@ -0,0 +1,1117 @@
+use crate::backend::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
+use crate::backend::component::mem_ctl::{AccessType, MemCtl};
+use crate::backend::util::types::{Byte, Word, mux_sel};
+use crossbeam_channel::{Sender, unbounded};
+use rsim_core::component::Component;
+use rsim_core::rx::Rx;
+use rsim_core::sim_manager::SimManager;
+use rsim_core::tx::Tx;
+use rsim_core::types::ComponentId;
+use rsim_core::types::EventId;
+use rsim_core::types::Input;
+use rsim_core::types::Output;
+use rsim_macro::ComponentAttribute;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// Largest cache allowed, more than enough to see the effects while keeping the contents tab usable
+pub const MAX_SIZE: u32 = 0x10000;
+pub const MAX_LINE_SIZE: u32 = 0x100;
+
+#[derive(Display, EnumIter, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheType {
+    #[strum(to_string = "Instruction")]
+    Instruction,
+    #[strum(to_string = "Data")]
+    Data,
+}
+
+/// The discriminants are saved in checkpoints, new policies take new numbers
+#[derive(Display, EnumIter, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ReplacementPolicy {
+    #[default]
+    #[strum(to_string = "LRU")]
+    Lru = 0,
+    #[strum(to_string = "FIFO")]
+    Fifo = 1,
+    Random = 2,
+}
+
+/// The discriminants are saved in checkpoints, new policies take new numbers
+#[derive(Display, EnumIter, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum WritePolicy {
+    /// Stores only go to the line and mark it dirty, memory gets them when the line is evicted
+    #[default]
+    #[strum(to_string = "Write-back")]
+    WriteBack = 0,
+    /// Stores go to memory as well, a store that misses does not fill a line
+    #[strum(to_string = "Write-through")]
+    WriteThrough = 1,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub is_enabled: bool,
+    /// In bytes
+    pub size: u32,
+    /// Ways per set
+    pub associativity: u32,
+    /// In bytes
+    pub line_size: u32,
+    pub replacement_policy: ReplacementPolicy,
+    pub write_policy: WritePolicy,
+    /// Cycles memory takes to start a line fill, a write back or a word written through,
+    /// on top of moving the words
+    pub miss_penalty: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            is_enabled: false,
+            size: 0x400,
+            associativity: 2,
+            line_size: 16,
+            replacement_policy: Default::default(),
+            write_policy: Default::default(),
+            miss_penalty: 10,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// 0 if the config is invalid
+    pub fn get_num_sets(&self) -> u32 {
+        self.associativity
+            .checked_mul(self.line_size)
+            .and_then(|set_size| self.size.checked_div(set_size))
+            .unwrap_or(0)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.line_size.is_power_of_two() || !(4..=MAX_LINE_SIZE).contains(&self.line_size) {
+            return Err(format!(
+                "line size must be a power of two from 4 to {}",
+                MAX_LINE_SIZE
+            ));
+        }
+        if self.size == 0 || self.size > MAX_SIZE {
+            return Err(format!("size must be from 1 to {} bytes", MAX_SIZE));
+        }
+        if self.associativity == 0 {
+            return Err("associativity must be at least 1".to_string());
+        }
+        // also keeps associativity * line size from overflowing
+        if self.associativity > self.size / self.line_size {
+            return Err(format!(
+                "associativity must be at most size / line size ({})",
+                self.size / self.line_size
+            ));
+        }
+        let set_size = self.associativity * self.line_size;
+        if self.size % set_size != 0 || !(self.size / set_size).is_power_of_two() {
+            return Err(format!(
+                "size must be a power of two multiple of associativity * line size ({})",
+                set_size
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parses `size=1024,ways=2,line=16,policy=lru,write=back,penalty=10` into an enabled config,
+    /// what is left out keeps its default
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = CacheConfig {
+            is_enabled: true,
+            ..Default::default()
+        };
+        for field in text.split(',').filter(|field| !field.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found {}", field))?;
+            let parse_u32 = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid {}: {}", key, e))
+            };
+            match key {
+                "size" => config.size = parse_u32(value)?,
+                "ways" => config.associativity = parse_u32(value)?,
+                "line" => config.line_size = parse_u32(value)?,
+                "penalty" => config.miss_penalty = parse_u32(value)?,
+                "policy" => {
+                    config.replacement_policy = match value {
+                        "lru" => ReplacementPolicy::Lru,
+                        "fifo" => ReplacementPolicy::Fifo,
+                        "random" => ReplacementPolicy::Random,
+                        _ => {
+                            return Err(format!(
+                                "invalid policy: expected lru, fifo or random, found {}",
+                                value
+                            ));
+                        }
+                    }
+                }
+                "write" => {
+                    config.write_policy = match value {
+                        "back" => WritePolicy::WriteBack,
+                        "through" => WritePolicy::WriteThrough,
+                        _ => {
+                            return Err(format!(
+                                "invalid write: expected back or through, found {}",
+                                value
+                            ));
+                        }
+                    }
+                }
+                _ => return Err(format!("unknown cache option {}", key)),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheLine {
+    pub is_valid: bool,
+    /// Holds stores that memory does not have yet
+    pub is_dirty: bool,
+    pub tag: u32,
+    /// `line_size` bytes starting at the address of the line
+    pub data: Vec<Byte>,
+    /// When the line was filled, for FIFO
+    filled_at: u64,
+    /// When the line was last accessed, for LRU
+    used_at: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub read_misses: u64,
+    pub write_misses: u64,
+    /// Dirty lines written to memory on eviction
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    pub fn get_accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.read_misses + self.write_misses
+    }
+
+    pub fn get_hits(&self) -> u64 {
+        self.get_accesses() - self.get_misses()
+    }
+
+    /// In percent, None before the first access
+    pub fn get_hit_rate(&self) -> Option<f64> {
+        (self.get_accesses() != 0)
+            .then(|| self.get_hits() as f64 * 100.0 / self.get_accesses() as f64)
+    }
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} accesses, {} hits, {} misses",
+            self.get_accesses(),
+            self.get_hits(),
+            self.get_misses()
+        )?;
+        if let Some(hit_rate) = self.get_hit_rate() {
+            write!(f, " ({:.2}% hit rate)", hit_rate)?;
+        }
+        write!(f, ", {} writebacks", self.writebacks)
+    }
+}
+
+/// The lines of a cache and the policies that pick them, `CacheCtl` moves the data over its ports
+///
+/// Nothing here talks to memory, a line that is evicted or invalidated while dirty is handed back
+/// to be written to memory by the caller
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cache {
+    config: CacheConfig,
+    /// `sets[set_idx][way]`
+    sets: Vec<Vec<CacheLine>>,
+    stats: CacheStats,
+    /// Counts accesses, orders the lines for LRU and FIFO
+    clock: u64,
+    /// Xorshift state for random replacement, starts the same so that runs can be repeated
+    rng_state: u32,
+}
+
+impl Cache {
+    const RNG_SEED: u32 = 0x2545F491;
+
+    pub fn new(config: CacheConfig) -> Self {
+        let mut cache = Self {
+            config,
+            sets: Vec::new(),
+            stats: Default::default(),
+            clock: 0,
+            rng_state: Self::RNG_SEED,
+        };
+        cache.reset();
+        cache
+    }
+
+    pub fn get_config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// Starts over cold with `config`, nothing changes if it is invalid
+    ///
+    /// Dirty lines are dropped, `flush` them first
+    pub fn set_config(&mut self, config: CacheConfig) -> Result<(), String> {
+        config.validate()?;
+        self.config = config;
+        self.reset();
+        Ok(())
+    }
+
+    /// Invalidates every line and clears the stats
+    pub fn reset(&mut self) {
+        let line = CacheLine {
+            data: vec![Byte::unknown(); self.config.line_size as usize],
+            ..Default::default()
+        };
+        self.sets = vec![
+            vec![line; self.config.associativity as usize];
+            self.config.get_num_sets() as usize
+        ];
+        self.stats = Default::default();
+        self.clock = 0;
+        self.rng_state = Self::RNG_SEED;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_enabled
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn get_sets(&self) -> &[Vec<CacheLine>] {
+        &self.sets
+    }
+
+    fn get_offset_bits(&self) -> u32 {
+        self.config.line_size.trailing_zeros()
+    }
+
+    fn get_set_bits(&self) -> u32 {
+        self.config.get_num_sets().trailing_zeros()
+    }
+
+    /// `(tag, set_idx)` of `addr`
+    fn split(&self, addr: u32) -> (u32, usize) {
+        let set_idx = (addr >> self.get_offset_bits()) & (self.config.get_num_sets() - 1);
+        let tag = addr >> (self.get_offset_bits() + self.get_set_bits());
+        (tag, set_idx as usize)
+    }
+
+    /// Where `addr` is in its line
+    fn get_offset(&self, addr: u32) -> usize {
+        (addr & (self.config.line_size - 1)) as usize
+    }
+
+    /// The address of the first byte of `line` in set `set_idx`
+    pub fn get_line_addr(&self, set_idx: usize, line: &CacheLine) -> u32 {
+        (line.tag << (self.get_offset_bits() + self.get_set_bits()))
+            | ((set_idx as u32) << self.get_offset_bits())
+    }
+
+    /// `(set_idx, way)` of the valid line holding `addr`
+    fn find(&self, addr: u32) -> Option<(usize, usize)> {
+        let (tag, set_idx) = self.split(addr);
+        self.sets[set_idx]
+            .iter()
+            .position(|line| line.is_valid && line.tag == tag)
+            .map(|way| (set_idx, way))
+    }
+
+    /// The valid line holding `addr`
+    pub fn get_line(&self, addr: u32) -> Option<&CacheLine> {
+        self.find(addr)
+            .map(|(set_idx, way)| &self.sets[set_idx][way])
+    }
+
+    /// Counts an access to `addr` and returns whether it hits, a miss does not fill a line by itself
+    ///
+    /// Accesses never cross a line since they are aligned words at most
+    pub fn access(&mut self, addr: u32, is_write: bool) -> bool {
+        self.clock += 1;
+        if is_write {
+            self.stats.writes += 1;
+        } else {
+            self.stats.reads += 1;
+        }
+        match self.find(addr) {
+            Some((set_idx, way)) => {
+                self.sets[set_idx][way].used_at = self.clock;
+                true
+            }
+            None => {
+                if is_write {
+                    self.stats.write_misses += 1;
+                } else {
+                    self.stats.read_misses += 1;
+                }
+                false
+            }
+        }
+    }
+
+    /// Takes a line for `addr` with unknown data to be filled,
+    /// returns the address and data of the line it evicts if that has to be written back
+    pub fn allocate(&mut self, addr: u32) -> Option<(u32, Vec<Byte>)> {
+        let (tag, set_idx) = self.split(addr);
+        let way = self.get_victim(set_idx);
+        let write_back = self.take_dirty(set_idx, way);
+        let line = &mut self.sets[set_idx][way];
+        line.is_valid = true;
+        line.tag = tag;
+        line.data.fill(Byte::unknown());
+        line.filled_at = self.clock;
+        line.used_at = self.clock;
+        write_back
+    }
+
+    /// Drops the line holding `addr`, returns its address and data if it has to be written back
+    pub fn invalidate(&mut self, addr: u32) -> Option<(u32, Vec<Byte>)> {
+        let (set_idx, way) = self.find(addr)?;
+        let write_back = self.take_dirty(set_idx, way);
+        self.sets[set_idx][way].is_valid = false;
+        write_back
+    }
+
+    /// Drops every line, returns the addresses and data of the ones that have to be written back
+    pub fn flush(&mut self) -> Vec<(u32, Vec<Byte>)> {
+        let mut write_backs = Vec::new();
+        for set_idx in 0..self.sets.len() {
+            for way in 0..self.sets[set_idx].len() {
+                write_backs.extend(self.take_dirty(set_idx, way));
+                self.sets[set_idx][way].is_valid = false;
+            }
+        }
+        write_backs
+    }
+
+    /// The address and data of a valid dirty line, which counts as written back and is clean after
+    fn take_dirty(&mut self, set_idx: usize, way: usize) -> Option<(u32, Vec<Byte>)> {
+        let line = &self.sets[set_idx][way];
+        if !line.is_valid || !line.is_dirty {
+            return None;
+        }
+        let write_back = (self.get_line_addr(set_idx, line), line.data.clone());
+        self.sets[set_idx][way].is_dirty = false;
+        self.stats.writebacks += 1;
+        Some(write_back)
+    }
+
+    /// Reads the bytes in `rmask` of the word containing `addr` like `MemCtl::read_word`,
+    /// unknown on a miss
+    pub fn read(&self, addr: u32, rmask: u8) -> Word {
+        let mut word = Word::unknown();
+        if let Some(line) = self.get_line(addr) {
+            let offset = self.get_offset(addr & !0x3);
+            for i in (0..4).filter(|i| (rmask >> i) & 0x1 == 0x1) {
+                word[i] = line.data[offset + i].into();
+            }
+        }
+        word
+    }
+
+    /// Writes the bytes in `wmask` of the word containing `addr` like `MemCtl::write_word`,
+    /// nothing changes on a miss
+    pub fn write(&mut self, addr: u32, wmask: u8, wdata: Word, is_dirty: bool) {
+        let offset = self.get_offset(addr & !0x3);
+        let Some((set_idx, way)) = self.find(addr) else {
+            return;
+        };
+        let wdata = wdata << Word::from(8 * (addr & 0x3));
+        let line = &mut self.sets[set_idx][way];
+        for i in (0..4).filter(|i| (wmask >> i) & 0x1 == 0x1) {
+            line.data[offset + i] = wdata[i].map(Byte::from).unwrap_or(Byte::unknown());
+        }
+        line.is_dirty |= is_dirty;
+    }
+
+    /// The byte at `addr`, None on a miss
+    pub fn peek_byte(&self, addr: u32) -> Option<Byte> {
+        self.get_line(addr)
+            .map(|line| line.data[self.get_offset(addr)])
+    }
+
+    /// Changes the byte at `addr` if a line holds it, for a write that went around the cache
+    pub fn poke_byte(&mut self, addr: u32, data: Byte) {
+        let offset = self.get_offset(addr);
+        if let Some((set_idx, way)) = self.find(addr) {
+            self.sets[set_idx][way].data[offset] = data;
+        }
+    }
+
+    /// The way to fill in set `set_idx`, an invalid one if there is any
+    fn get_victim(&mut self, set_idx: usize) -> usize {
+        let set = &self.sets[set_idx];
+        if let Some(way) = set.iter().position(|line| !line.is_valid) {
+            return way;
+        }
+        match self.config.replacement_policy {
+            ReplacementPolicy::Lru => (0..set.len()).min_by_key(|way| set[*way].used_at),
+            ReplacementPolicy::Fifo => (0..set.len()).min_by_key(|way| set[*way].filled_at),
+            ReplacementPolicy::Random => {
+                self.rng_state ^= self.rng_state << 13;
+                self.rng_state ^= self.rng_state >> 17;
+                self.rng_state ^= self.rng_state << 5;
+                Some(self.rng_state as usize % set.len())
+            }
+        }
+        .unwrap()
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+/// A request on the cpu ports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Request {
+    access_type: AccessType,
+    addr: u32,
+    rmask: u8,
+    wmask: u8,
+    wdata: Word,
+}
+
+/// A word moved over the mem ports, a read of the whole word if `wmask` is 0
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct MemOp {
+    addr: u32,
+    wdata: Word,
+    wmask: u8,
+}
+
+/// What happens to the request on the cpu ports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Route {
+    /// Served from a line, filling it first on a miss
+    Serve,
+    /// Goes to memory, but the line holding it has to be written back or dropped first
+    Flush,
+    PassThrough,
+}
+
+/// A cache between the cpu ports and the mem ports, which take the same requests as `MemCtl`
+///
+/// Only fetches go through the instruction cache and only loads and stores through the data cache,
+/// everything else passes through as if there was a wire, so do accesses to devices or watched
+/// memory and accesses the memory map does not allow, which `MemCtl` then answers.
+///
+/// A hit is answered on the next clock like `MemCtl` would, a miss first fills the line a word at a
+/// time over the mem ports, after writing back the dirty line it evicts.
+#[ComponentAttribute({
+"port": {
+    "input": [
+        ["cpu_addr", "Word"],
+        ["cpu_wdata", "Word"],
+        ["cpu_rmask", "Byte"],
+        ["cpu_wmask", "Byte"],
+        ["mem_addr_mux_sel", "Byte"],
+        ["mem_rdata", "Word"],
+        ["mem_resp", "Byte"],
+        ["mem_fault", "Byte"]
+    ],
+    "output": [
+        ["cpu_rdata", "Word"],
+        ["cpu_resp", "Byte"],
+        ["cpu_fault", "Byte"],
+        ["mem_addr", "Word"],
+        ["mem_wdata", "Word"],
+        ["mem_rmask", "Byte"],
+        ["mem_wmask", "Byte"]
+    ],
+    "clock": true
+}
+})]
+pub struct CacheCtl {
+    pub cache: Cache,
+    pub cache_type: CacheType,
+    /// Knows which lines can be cached, see `MemCtl::is_cacheable`
+    mem_ctl: Arc<Mutex<MemCtl>>,
+    /// The request being served, it is answered once `mem_ops` are done
+    request: Option<Request>,
+    /// The words still to move, the first one is on the mem ports
+    mem_ops: VecDeque<MemOp>,
+    /// Cycles left before `mem_ops` start moving, the miss penalty
+    stall_cycles: u32,
+    /// The last data sent back on `cpu_rdata`
+    rdata: Word,
+    is_busy: bool,
+}
+
+impl CacheCtl {
+    pub fn new(
+        component_id: ComponentId,
+        sim_manager: Arc<SimManager>,
+        ack_sender: Sender<EventId>,
+        cache_type: CacheType,
+        mem_ctl: Arc<Mutex<MemCtl>>,
+        cpu_addr: Rx<Word>,
+        cpu_wdata: Rx<Word>,
+        cpu_rmask: Rx<Byte>,
+        cpu_wmask: Rx<Byte>,
+        mem_addr_mux_sel: Rx<Byte>,
+        mem_rdata: Rx<Word>,
+        mem_resp: Rx<Byte>,
+        mem_fault: Rx<Byte>,
+        cpu_rdata: Tx<Word>,
+        cpu_resp: Tx<Byte>,
+        cpu_fault: Tx<Byte>,
+        mem_addr: Tx<Word>,
+        mem_wdata: Tx<Word>,
+        mem_rmask: Tx<Byte>,
+        mem_wmask: Tx<Byte>,
+    ) -> Self {
+        let clock_channel = unbounded();
+
+        CacheCtl {
+            cache: Default::default(),
+            cache_type,
+            mem_ctl,
+            request: None,
+            mem_ops: Default::default(),
+            stall_cycles: 0,
+            rdata: Word::unknown(),
+            is_busy: false,
+            component_id,
+            sim_manager,
+            ack_sender,
+            clock_sender: clock_channel.0,
+            clock_receiver: clock_channel.1,
+            cpu_addr,
+            cpu_wdata,
+            cpu_rmask,
+            cpu_wmask,
+            mem_addr_mux_sel,
+            mem_rdata,
+            mem_resp,
+            mem_fault,
+            cpu_rdata,
+            cpu_resp,
+            cpu_fault,
+            mem_addr,
+            mem_wdata,
+            mem_rmask,
+            mem_wmask,
+        }
+    }
+
+    fn init_impl(&mut self) {}
+
+    fn reset_impl(&mut self) {
+        // the config is kept
+        self.cache.reset();
+        self.request = None;
+        self.mem_ops.clear();
+        self.stall_cycles = 0;
+        self.rdata = Word::unknown();
+        self.is_busy = false;
+    }
+
+    fn poll_impl(&mut self) {}
+
+    fn on_clock(&mut self) {
+        if self.is_busy {
+            self.is_busy = false;
+        } else if self.stall_cycles > 0 {
+            self.stall_cycles -= 1;
+        } else if let Some(mem_op) = self.mem_ops.front().copied() {
+            if self.mem_resp.get_value().is_something_nonzero() {
+                if mem_op.wmask == 0 {
+                    self.cache
+                        .write(mem_op.addr, 0xF, self.mem_rdata.get_value(), false);
+                }
+                self.mem_ops.pop_front();
+                // the cpu may have given up on the request, like a fetch interrupted while waiting
+                if self.mem_ops.is_empty()
+                    && let Some(request) = self.request.take()
+                    && self.get_request() == Some(request)
+                {
+                    self.complete(request);
+                }
+            }
+        } else if let Some(request) = self.get_request() {
+            match self.get_route(&request) {
+                Route::Serve => self.serve(request),
+                Route::Flush => {
+                    if let Some((line_addr, data)) = self.cache.invalidate(request.addr) {
+                        self.queue_write_back(line_addr, &data);
+                    }
+                }
+                Route::PassThrough => {}
+            }
+        }
+        self.drive();
+    }
+
+    fn on_comb(&mut self) {
+        self.drive();
+    }
+
+    /// The request on the cpu ports, None if there is none or its address is unknown
+    ///
+    /// Control only selects the pc as the address in its fetch state, every other read is a load
+    fn get_request(&self) -> Option<Request> {
+        let addr = Into::<Option<u32>>::into(self.cpu_addr.get_value())?;
+        let rmask = Into::<Option<u8>>::into(self.cpu_rmask.get_value()).unwrap_or(0);
+        let wmask = Into::<Option<u8>>::into(self.cpu_wmask.get_value()).unwrap_or(0);
+        let access_type = if wmask != 0 {
+            AccessType::Store
+        } else if rmask == 0 {
+            return None;
+        } else if self.mem_addr_mux_sel.get_value() == Byte::from(mux_sel::mem_addr::PC_OUT) {
+            AccessType::Fetch
+        } else {
+            AccessType::Load
+        };
+        Some(Request {
+            access_type,
+            addr,
+            rmask,
+            wmask,
+            wdata: self.cpu_wdata.get_value(),
+        })
+    }
+
+    fn get_route(&self, request: &Request) -> Route {
+        let is_own = match self.cache_type {
+            CacheType::Instruction => request.access_type == AccessType::Fetch,
+            CacheType::Data => request.access_type != AccessType::Fetch,
+        };
+        if self.cache.is_enabled()
+            && is_own
+            && self.mem_ctl.lock().unwrap().is_cacheable(
+                request.access_type,
+                request.addr,
+                self.cache.get_config().line_size,
+            )
+        {
+            return Route::Serve;
+        }
+        // memory has to have the latest data, and must not be changed behind a line
+        match self.cache.get_line(request.addr) {
+            Some(line) if line.is_dirty || request.access_type == AccessType::Store => Route::Flush,
+            _ => Route::PassThrough,
+        }
+    }
+
+    /// Looks `request` up, on a miss the line is filled first unless the store is written through
+    fn serve(&mut self, request: Request) {
+        let config = self.cache.get_config();
+        let is_write = request.access_type == AccessType::Store;
+        let is_hit = self.cache.access(request.addr, is_write);
+        if is_write && config.write_policy == WritePolicy::WriteThrough {
+            self.stall_cycles += config.miss_penalty;
+            self.mem_ops.push_back(MemOp {
+                addr: request.addr,
+                wdata: request.wdata,
+                wmask: request.wmask,
+            });
+        } else if !is_hit {
+            if let Some((line_addr, data)) = self.cache.allocate(request.addr) {
+                self.queue_write_back(line_addr, &data);
+            }
+            let line_addr = request.addr & !(config.line_size - 1);
+            self.stall_cycles += config.miss_penalty;
+            self.mem_ops
+                .extend((0..config.line_size).step_by(4).map(|offset| MemOp {
+                    addr: line_addr + offset,
+                    wdata: Word::unknown(),
+                    wmask: 0,
+                }));
+        }
+
+        if self.mem_ops.is_empty() {
+            self.complete(request);
+        } else {
+            self.request = Some(request);
+        }
+    }
+
+    /// Answers `request` from the line, a store that was written through only updates it on a hit
+    fn complete(&mut self, request: Request) {
+        if request.access_type == AccessType::Store {
+            let is_write_back = self.cache.get_config().write_policy == WritePolicy::WriteBack;
+            self.cache
+                .write(request.addr, request.wmask, request.wdata, is_write_back);
+        } else {
+            self.rdata = self.cache.read(request.addr, request.rmask);
+        }
+        self.is_busy = true;
+    }
+
+    /// Bytes the line does not know are left as they are in memory
+    fn queue_write_back(&mut self, line_addr: u32, data: &[Byte]) {
+        let len = self.mem_ops.len();
+        for (offset, chunk) in (0..).step_by(4).zip(data.chunks(4)) {
+            let mut wdata = Word::unknown();
+            let mut wmask = 0;
+            for (i, byte) in chunk.iter().enumerate() {
+                wdata[i] = (*byte).into();
+                if wdata[i].is_some() {
+                    wmask |= 1 << i;
+                }
+            }
+            if wmask != 0 {
+                self.mem_ops.push_back(MemOp {
+                    addr: line_addr + offset,
+                    wdata,
+                    wmask,
+                });
+            }
+        }
+        if self.mem_ops.len() != len {
+            self.stall_cycles += self.cache.get_config().miss_penalty;
+        }
+    }
+
+    /// Connects the cpu ports to the mem ports, unless the cache is busy with the request
+    fn drive(&mut self) {
+        let is_serving = self.is_busy
+            || !self.mem_ops.is_empty()
+            || self
+                .get_request()
+                .is_some_and(|request| self.get_route(&request) != Route::PassThrough);
+        if !is_serving {
+            self.mem_addr.send(self.cpu_addr.get_value(), 0);
+            self.mem_wdata.send(self.cpu_wdata.get_value(), 0);
+            self.mem_rmask.send(self.cpu_rmask.get_value(), 0);
+            self.mem_wmask.send(self.cpu_wmask.get_value(), 0);
+            self.cpu_rdata.send(self.mem_rdata.get_value(), 0);
+            self.cpu_resp.send(self.mem_resp.get_value(), 0);
+            self.cpu_fault.send(self.mem_fault.get_value(), 0);
+            return;
+        }
+
+        match self.mem_ops.front().filter(|_| self.stall_cycles == 0) {
+            Some(mem_op) => {
+                let rmask = if mem_op.wmask == 0 { 0xFu8 } else { 0x0 };
+                self.mem_addr.send(Word::from(mem_op.addr), 0);
+                self.mem_wdata.send(mem_op.wdata, 0);
+                self.mem_rmask.send(Byte::from(rmask), 0);
+                self.mem_wmask.send(Byte::from(mem_op.wmask), 0);
+            }
+            None => {
+                self.mem_rmask.send(Byte::from(0u8), 0);
+                self.mem_wmask.send(Byte::from(0u8), 0);
+            }
+        }
+        self.cpu_rdata.send(self.rdata, 0);
+        self.cpu_resp.send(Byte::from(self.is_busy as u8), 0);
+        self.cpu_fault.send(Byte::from(0u8), 0);
+    }
+}
+
+impl Debug for CacheCtl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CacheCtl: {{cache_type: {}, cpu_addr: {:?}, cpu_rmask: {:?}, cpu_wmask: {:?}, mem_resp: {:?}}}",
+            self.cache_type,
+            self.cpu_addr.get_value(),
+            self.cpu_rmask.get_value(),
+            self.cpu_wmask.get_value(),
+            self.mem_resp.get_value()
+        )
+    }
+}
+
+impl Checkpoint for ReplacementPolicy {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (*self as u32).save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let discriminant = u32::load(reader)?;
+        Self::iter()
+            .find(|&policy| policy as u32 == discriminant)
+            .ok_or_else(|| format!("Invalid replacement policy {}", discriminant))
+    }
+}
+
+impl Checkpoint for WritePolicy {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (*self as u32).save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let discriminant = u32::load(reader)?;
+        Self::iter()
+            .find(|&policy| policy as u32 == discriminant)
+            .ok_or_else(|| format!("Invalid write policy {}", discriminant))
+    }
+}
+
+impl Checkpoint for CacheLine {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.is_valid.save(writer);
+        self.is_dirty.save(writer);
+        self.tag.save(writer);
+        self.data.save(writer);
+        self.filled_at.save(writer);
+        self.used_at.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            is_valid: Checkpoint::load(reader)?,
+            is_dirty: Checkpoint::load(reader)?,
+            tag: Checkpoint::load(reader)?,
+            data: Checkpoint::load(reader)?,
+            filled_at: Checkpoint::load(reader)?,
+            used_at: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+impl Checkpoint for Cache {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.config.is_enabled.save(writer);
+        self.config.size.save(writer);
+        self.config.associativity.save(writer);
+        self.config.line_size.save(writer);
+        self.config.replacement_policy.save(writer);
+        self.config.write_policy.save(writer);
+        self.config.miss_penalty.save(writer);
+        self.sets.save(writer);
+        self.stats.reads.save(writer);
+        self.stats.writes.save(writer);
+        self.stats.read_misses.save(writer);
+        self.stats.write_misses.save(writer);
+        self.stats.writebacks.save(writer);
+        self.clock.save(writer);
+        self.rng_state.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let config = CacheConfig {
+            is_enabled: Checkpoint::load(reader)?,
+            size: Checkpoint::load(reader)?,
+            associativity: Checkpoint::load(reader)?,
+            line_size: Checkpoint::load(reader)?,
+            replacement_policy: Checkpoint::load(reader)?,
+            write_policy: Checkpoint::load(reader)?,
+            miss_penalty: Checkpoint::load(reader)?,
+        };
+        config.validate()?;
+        let sets: Vec<Vec<CacheLine>> = Checkpoint::load(reader)?;
+        if sets.len() != config.get_num_sets() as usize
+            || sets.iter().any(|set| {
+                set.len() != config.associativity as usize
+                    || set
+                        .iter()
+                        .any(|line| line.data.len() != config.line_size as usize)
+            })
+        {
+            return Err("Cache lines do not match the cache size".to_string());
+        }
+        Ok(Self {
+            config,
+            sets,
+            stats: CacheStats {
+                reads: Checkpoint::load(reader)?,
+                writes: Checkpoint::load(reader)?,
+                read_misses: Checkpoint::load(reader)?,
+                write_misses: Checkpoint::load(reader)?,
+                writebacks: Checkpoint::load(reader)?,
+            },
+            clock: Checkpoint::load(reader)?,
+            rng_state: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2 sets of 2 ways of 16 bytes
+    fn new_cache(replacement_policy: ReplacementPolicy) -> Cache {
+        Cache::new(CacheConfig {
+            is_enabled: true,
+            size: 64,
+            associativity: 2,
+            line_size: 16,
+            replacement_policy,
+            write_policy: Default::default(),
+            miss_penalty: 10,
+        })
+    }
+
+    /// Looks `addr` up like `CacheCtl` does, a missed line is filled from a memory where each word
+    /// holds its own address, returns whether it hit and the line to write back
+    fn access(cache: &mut Cache, addr: u32, is_write: bool) -> (bool, Option<(u32, Vec<Byte>)>) {
+        if cache.access(addr, is_write) {
+            return (true, None);
+        }
+        let write_back = cache.allocate(addr);
+        let line_addr = addr & !0xF;
+        for addr in (line_addr..line_addr + 16).step_by(4) {
+            cache.write(addr, 0xF, Word::from(addr), false);
+        }
+        (false, write_back)
+    }
+
+    #[test]
+    fn test_cache_hit_miss() {
+        let mut cache = new_cache(ReplacementPolicy::Lru);
+        assert!(!access(&mut cache, 0x1000, false).0);
+        assert!(access(&mut cache, 0x100C, false).0);
+        // same set, the other way
+        assert!(!access(&mut cache, 0x2004, false).0);
+        assert!(!access(&mut cache, 0x1010, false).0);
+        assert!(access(&mut cache, 0x2000, false).0);
+
+        let stats = cache.get_stats();
+        assert_eq!(stats.get_accesses(), 5);
+        assert_eq!(stats.get_misses(), 3);
+        assert_eq!(stats.get_hit_rate(), Some(40.0));
+        assert_eq!(cache.get_sets()[0][1].tag, 0x2000 >> 5);
+        assert_eq!(cache.get_line_addr(0, &cache.get_sets()[0][1]), 0x2000);
+        assert_eq!(cache.get_line_addr(1, &cache.get_sets()[1][0]), 0x1010);
+    }
+
+    #[test]
+    fn test_cache_replacement() {
+        let mut lru = new_cache(ReplacementPolicy::Lru);
+        let mut fifo = new_cache(ReplacementPolicy::Fifo);
+        for cache in [&mut lru, &mut fifo] {
+            access(cache, 0x1000, false);
+            access(cache, 0x2000, false);
+            access(cache, 0x1000, false);
+            access(cache, 0x3000, false);
+        }
+        // LRU evicts 0x2000 since 0x1000 was used after it, FIFO evicts 0x1000 since it came first
+        assert!(access(&mut lru, 0x1000, false).0);
+        assert!(!access(&mut lru, 0x2000, false).0);
+        assert!(access(&mut fifo, 0x2000, false).0);
+        assert!(!access(&mut fifo, 0x1000, false).0);
+
+        let mut random = new_cache(ReplacementPolicy::Random);
+        let mut same_random = new_cache(ReplacementPolicy::Random);
+        for addr in (0..64).map(|i| i * 0x1000) {
+            access(&mut random, addr, false);
+            access(&mut same_random, addr, false);
+        }
+        assert_eq!(random, same_random);
+    }
+
+    #[test]
+    fn test_cache_data() {
+        let mut cache = new_cache(ReplacementPolicy::Lru);
+        access(&mut cache, 0x1004, false);
+        assert_eq!(cache.read(0x1004, 0xF), Word::from(0x1004u32));
+        assert_eq!(cache.read(0x1009, 0x2).data, [None, Some(0x10), None, None]);
+        assert_eq!(cache.read(0x2000, 0xF), Word::unknown());
+
+        // sb to 0x1006, the data is shifted into its byte like memory does
+        access(&mut cache, 0x1006, true);
+        cache.write(0x1006, 0b0100, Word::from(0xABu32), true);
+        assert_eq!(cache.read(0x1004, 0xF), Word::from(0x00AB1004u32));
+        assert_eq!(cache.peek_byte(0x1006), Some(Byte::from(0xABu8)));
+        assert!(cache.get_line(0x1000).unwrap().is_dirty);
+        assert_eq!(cache.peek_byte(0x2000), None);
+
+        // evicts the dirty line, which is handed back to be written to memory
+        access(&mut cache, 0x2000, false);
+        let (_, write_back) = access(&mut cache, 0x3000, false);
+        let (line_addr, data) = write_back.unwrap();
+        assert_eq!(line_addr, 0x1000);
+        assert_eq!(data[6], Byte::from(0xABu8));
+        assert_eq!(data[8], Byte::from(0x08u8));
+        assert_eq!(cache.get_stats().writebacks, 1);
+
+        cache.write(0x2000, 0xF, Word::from(1u32), true);
+        assert_eq!(
+            cache.invalidate(0x2000).map(|(line_addr, _)| line_addr),
+            Some(0x2000)
+        );
+        assert_eq!(cache.invalidate(0x3000), None);
+        assert_eq!(cache.peek_byte(0x3000), None);
+
+        // a miss is left alone
+        cache.write(0x3000, 0xF, Word::from(1u32), true);
+        cache.poke_byte(0x3000, Byte::from(1u8));
+        assert!(cache.get_line(0x3000).is_none());
+
+        access(&mut cache, 0x4000, false);
+        cache.poke_byte(0x4000, Byte::from(0x55u8));
+        access(&mut cache, 0x5010, true);
+        cache.write(0x5010, 0x1, Word::from(0x66u32), true);
+        let write_backs = cache.flush();
+        assert_eq!(write_backs.len(), 1);
+        assert_eq!(write_backs[0].0, 0x5010);
+        assert_eq!(write_backs[0].1[0], Byte::from(0x66u8));
+        assert!(cache.get_line(0x4000).is_none());
+        assert_eq!(cache.get_stats().writebacks, 3);
+    }
+
+    #[test]
+    fn test_cache_config() {
+        let config =
+            CacheConfig::parse("size=4096,ways=4,line=32,policy=fifo,write=through").unwrap();
+        assert_eq!(config.get_num_sets(), 32);
+        assert_eq!(config.replacement_policy, ReplacementPolicy::Fifo);
+        assert_eq!(config.write_policy, WritePolicy::WriteThrough);
+        assert_eq!(config.miss_penalty, 10);
+        assert!(config.is_enabled);
+
+        assert!(CacheConfig::parse("size=96").is_err());
+        assert!(CacheConfig::parse("line=2").is_err());
+        assert!(CacheConfig::parse("ways=0").is_err());
+        assert!(CacheConfig::parse("ways=16777216,line=256").is_err());
+        assert!(CacheConfig::parse("size=1024,ways=128,line=16").is_err());
+        assert!(CacheConfig::parse("policy=mru").is_err());
+        assert!(CacheConfig::parse("size").is_err());
+
+        let mut cache = Cache::default();
+        assert!(!cache.is_enabled());
+        assert!(cache.set_config(config).is_ok());
+        assert_eq!(cache.get_sets().len(), 32);
+        assert_eq!(cache.get_sets()[0].len(), 4);
+        assert_eq!(cache.get_sets()[0][0].data.len(), 32);
+        assert!(cache.set_config(CacheConfig { size: 0, ..config }).is_err());
+        assert_eq!(cache.get_config(), config);
+    }
+
+    #[test]
+    fn test_cache_checkpoint() {
+        let mut cache = new_cache(ReplacementPolicy::Random);
+        access(&mut cache, 0x1000, true);
+        cache.write(0x1000, 0xF, Word::from(0x12345678u32), true);
+        access(&mut cache, 0x2010, false);
+
+        let mut writer = CheckpointWriter::new();
+        cache.save(&mut writer);
+        let data = writer.into_inner();
+        let mut reader = CheckpointReader::new(&data).unwrap();
+        assert_eq!(Cache::load(&mut reader).unwrap(), cache);
+    }
+}
@@ -21,7 +21,12 @@ use std::sync::Arc;
         ["cmp_out", "Word"],
         ["opcode", "Byte"],
         ["mem_addr_mux_out", "Word"],
-        ["mem_resp", "Byte"]
+        ["mem_resp", "Byte"],
+        ["mem_fault", "Byte"],
+        ["muldiv_resp", "Byte"],
+        ["i_imm", "Word"],
+        ["csr_illegal", "Byte"],
+        ["interrupt", "Word"]
     ],
     "output": [
         ["load_pc", "Byte"],
@@ -36,7 +41,15 @@ use std::sync::Arc;
         ["mem_addr_mux_sel", "Byte"],
         ["cmp_mux_sel", "Byte"],
         ["mem_rmask", "Byte"],
-        ["mem_wmask", "Byte"]
+        ["mem_wmask", "Byte"],
+        ["muldiv_start", "Byte"],
+        ["muldiv_op", "Byte"],
+        ["csr_op", "Byte"],
+        ["csr_wr", "Byte"],
+        ["mret", "Byte"],
+        ["trap", "Byte"],
+        ["trap_cause", "Word"],
+        ["trap_val", "Word"]
     ],
     "clock": true
 }
@@ -57,6 +70,11 @@ impl Control {
         opcode: Rx<Byte>,
         mem_addr_mux_out: Rx<Word>,
         mem_resp: Rx<Byte>,
+        mem_fault: Rx<Byte>,
+        muldiv_resp: Rx<Byte>,
+        i_imm: Rx<Word>,
+        csr_illegal: Rx<Byte>,
+        interrupt: Rx<Word>,
         load_pc: Tx<Byte>,
         load_ir: Tx<Byte>,
         load_regfile: Tx<Byte>,
@@ -70,6 +88,14 @@ impl Control {
         cmp_mux_sel: Tx<Byte>,
         mem_rmask: Tx<Byte>,
         mem_wmask: Tx<Byte>,
+        muldiv_start: Tx<Byte>,
+        muldiv_op: Tx<Byte>,
+        csr_op: Tx<Byte>,
+        csr_wr: Tx<Byte>,
+        mret: Tx<Byte>,
+        trap: Tx<Byte>,
+        trap_cause: Tx<Word>,
+        trap_val: Tx<Word>,
     ) -> Self {
         let clock_channel = unbounded();
 
@@ -87,6 +113,11 @@ impl Control {
             opcode,
             mem_addr_mux_out,
             mem_resp,
+            mem_fault,
+            muldiv_resp,
+            i_imm,
+            csr_illegal,
+            interrupt,
             load_pc,
             load_ir,
             load_regfile,
@@ -100,6 +131,14 @@ impl Control {
             cmp_mux_sel,
             mem_rmask,
             mem_wmask,
+            muldiv_start,
+            muldiv_op,
+            csr_op,
+            csr_wr,
+            mret,
+            trap,
+            trap_cause,
+            trap_val,
         }
     }
 
@@ -152,6 +191,10 @@ impl Control {
         self.load_regfile.send(Byte::from(0u8), 0);
         self.mem_wmask.send(Byte::from(0u8), 0);
         self.mem_rmask.send(Byte::from(0u8), 0);
+        self.muldiv_start.send(Byte::from(0u8), 0);
+        self.csr_wr.send(Byte::from(0u8), 0);
+        self.mret.send(Byte::from(0u8), 0);
+        self.trap.send(Byte::from(0u8), 0);
     }
 
     fn load_pc(&mut self, sel: u8) {
@@ -179,6 +222,126 @@ impl Control {
         self.cmp_op.send(Byte::from(cmp_op), 0);
     }
 
+    fn start_muldiv(&mut self, op: u8) {
+        self.muldiv_start.send(Byte::from(1u8), 0);
+        self.muldiv_op.send(Byte::from(op), 0);
+    }
+
+    fn write_csr(&mut self, op: u8) {
+        self.csr_wr.send(Byte::from(1u8), 0);
+        self.csr_op.send(Byte::from(op), 0);
+    }
+
+    fn raise_trap(&mut self, cause: u32, val: u32) {
+        self.trap.send(Byte::from(1u8), 0);
+        self.trap_cause.send(Word::from(cause), 0);
+        self.trap_val.send(Word::from(val), 0);
+        self.load_pc(mux_sel::pc::MTVEC);
+    }
+
+    /// Whether the instruction being decoded is one we implement
+    fn is_legal_instruction(&self) -> bool {
+        let (Some(opcode), Some(funct3), Some(funct7)) = (
+            Into::<Option<u8>>::into(self.opcode.get_value()),
+            Into::<Option<u8>>::into(self.funct3.get_value()),
+            Into::<Option<u8>>::into(self.funct7.get_value()),
+        ) else {
+            return false;
+        };
+        is_legal_instruction(opcode, funct3, funct7)
+    }
+
+    /// The trap taken in the current state as (mcause, mtval)
+    ///
+    /// Interrupts are only taken in `Fetch`, between two instructions.
+    /// Branch and jump targets as well as load and store addresses are observed on
+    /// `mem_addr_mux_out`, so those states route the alu through the memory address mux.
+    /// Access faults come back from memory together with `mem_resp`
+    pub fn get_trap(&self) -> Option<(u32, u32)> {
+        let addr = Into::<Option<u32>>::into(self.mem_addr_mux_out.get_value());
+        let funct3 = Into::<Option<u8>>::into(self.funct3.get_value());
+        let illegal = Some((trap_cause::ILLEGAL_INSTRUCTION, 0));
+        let access_fault = |cause: u32| {
+            if self.mem_resp.get_value().is_something_nonzero()
+                && self.mem_fault.get_value().is_something_nonzero()
+            {
+                Some((cause, addr.unwrap_or(0)))
+            } else {
+                None
+            }
+        };
+
+        match self.state {
+            Fetch => Into::<Option<u32>>::into(self.interrupt.get_value())
+                .filter(|cause| *cause != 0)
+                .map(|cause| (cause, 0))
+                .or_else(|| access_fault(trap_cause::INSTRUCTION_ACCESS_FAULT)),
+            Decode => {
+                if self.is_legal_instruction() {
+                    None
+                } else {
+                    illegal
+                }
+            }
+            Load | Store => {
+                let align_mask = match funct3.map(|funct3| funct3 & 0x3) {
+                    Some(0b01) => 0x1,
+                    Some(0b10) => 0x3,
+                    _ => 0x0,
+                };
+                addr.filter(|addr| addr & align_mask != 0)
+                    .map(|addr| {
+                        if self.state == Load {
+                            (trap_cause::LOAD_ADDRESS_MISALIGNED, addr)
+                        } else {
+                            (trap_cause::STORE_ADDRESS_MISALIGNED, addr)
+                        }
+                    })
+                    .or_else(|| {
+                        access_fault(if self.state == Load {
+                            trap_cause::LOAD_ACCESS_FAULT
+                        } else {
+                            trap_cause::STORE_ACCESS_FAULT
+                        })
+                    })
+            }
+            Jal | Jalr | Br => {
+                if self.state == Br && !self.cmp_out.get_value().is_something_nonzero() {
+                    return None;
+                }
+                addr.map(|addr| {
+                    if self.state == Jalr {
+                        addr & !0x1
+                    } else {
+                        addr
+                    }
+                })
+                .filter(|addr| addr & 0x3 != 0)
+                .map(|addr| (trap_cause::INSTRUCTION_ADDRESS_MISALIGNED, addr))
+            }
+            System => match funct3 {
+                Some(funct3::system::PRIV) => {
+                    match Into::<Option<u32>>::into(self.i_imm.get_value())
+                        .map(|imm| (imm & 0xFFF) as u16)
+                    {
+                        Some(funct12::ECALL) => Some((trap_cause::ECALL_FROM_M, 0)),
+                        Some(funct12::EBREAK) => Some((trap_cause::BREAKPOINT, 0)),
+                        Some(funct12::MRET) | Some(funct12::WFI) => None,
+                        _ => illegal,
+                    }
+                }
+                _ => {
+                    if self.csr_illegal.get_value().is_something_nonzero() {
+                        illegal
+                    } else {
+                        None
+                    }
+                }
+            },
+            _ => None,
+        }
+    }
+
     fn read_from_mem(&mut self) {
         self.mem_rmask.send(self.get_rmask(), 0);
     }
@@ -278,11 +441,44 @@ impl Control {
                 }
                 self.load_pc(mux_sel::pc::PC_PLUS4);
             }
+            MulDiv => {
+                if let Some(funct3) = Into::<Option<u8>>::into(self.funct3.get_value()) {
+                    self.start_muldiv(funct3);
+                }
+                if self.muldiv_resp.get_value().is_something_nonzero() {
+                    self.load_regfile(mux_sel::regfile::MULDIV_OUT);
+                    self.load_pc(mux_sel::pc::PC_PLUS4);
+                }
+            }
+            System => {
+                if let Some(funct3) = Into::<Option<u8>>::into(self.funct3.get_value()) {
+                    if funct3 == funct3::system::PRIV {
+                        if Into::<Option<u32>>::into(self.i_imm.get_value())
+                            .map(|imm| (imm & 0xFFF) as u16)
+                            == Some(funct12::MRET)
+                        {
+                            self.mret.send(Byte::from(1u8), 0);
+                            self.load_pc(mux_sel::pc::MEPC);
+                        } else {
+                            self.load_pc(mux_sel::pc::PC_PLUS4);
+                        }
+                    } else {
+                        self.load_regfile(mux_sel::regfile::CSR_OUT);
+                        self.write_csr(funct3);
+                        self.load_pc(mux_sel::pc::PC_PLUS4);
+                    }
+                }
+            }
+            Fence => {
+                self.load_pc(mux_sel::pc::PC_PLUS4);
+            }
             Lui => {
                 self.load_regfile(mux_sel::regfile::U_IMM);
                 self.load_pc(mux_sel::pc::PC_PLUS4);
             }
             Br => {
+                self.mem_addr_mux_sel
+                    .send(Byte::from(mux_sel::mem_addr::ALU_OUT), 0);
                 self.set_alu(mux_sel::alu1::PC_OUT, mux_sel::alu2::B_IMM, alu_op::ADD);
                 if let Some(funct3) = Into::<Option<u8>>::into(self.funct3.get_value()) {
                     self.set_cmp(mux_sel::cmp::RS2_OUT, funct3);
@@ -340,11 +536,15 @@ impl Control {
                 }
             }
             Jal => {
+                self.mem_addr_mux_sel
+                    .send(Byte::from(mux_sel::mem_addr::ALU_OUT), 0);
                 self.load_pc(mux_sel::pc::ALU_OUT);
                 self.set_alu(mux_sel::alu1::PC_OUT, mux_sel::alu2::J_IMM, alu_op::ADD);
                 self.load_regfile(mux_sel::regfile::PC_PLUS4);
             }
             Jalr => {
+                self.mem_addr_mux_sel
+                    .send(Byte::from(mux_sel::mem_addr::ALU_OUT), 0);
                 self.load_pc(mux_sel::pc::ALU_MOD2);
                 self.set_alu(mux_sel::alu1::RS1_OUT, mux_sel::alu2::I_IMM, alu_op::ADD);
                 self.load_regfile(mux_sel::regfile::PC_PLUS4);
@@ -355,6 +555,11 @@ impl Control {
     fn set_next_state(&mut self) {
         self.next_state = self.state;
 
+        if self.get_trap().is_some() {
+            self.next_state = Fetch;
+            return;
+        }
+
         self.next_state = match self.state {
             Fetch => {
                 if self.mem_resp.get_value().is_something_nonzero() {
@@ -372,7 +577,15 @@ impl Control {
                 Some(opcode::LOAD) => Load,
                 Some(opcode::STORE) => Store,
                 Some(opcode::IMM) => Imm,
-                Some(opcode::REG) => Reg,
+                Some(opcode::REG) => {
+                    if self.funct7.get_value() == Byte::from(funct7::MULDIV) {
+                        MulDiv
+                    } else {
+                        Reg
+                    }
+                }
+                Some(opcode::SYSTEM) => System,
+                Some(opcode::MISC_MEM) => Fence,
                 _ => Fetch,
             },
             Load => {
@@ -389,6 +602,13 @@ impl Control {
                     Store
                 }
             }
+            MulDiv => {
+                if self.muldiv_resp.get_value().is_something_nonzero() {
+                    Fetch
+                } else {
+                    MulDiv
+                }
+            }
             _ => Fetch,
         }
     }
@@ -396,10 +616,48 @@ impl Control {
     fn on_comb(&mut self) {
         self.set_default_control_signals();
         self.set_control_signal();
+        // a trapping instruction keeps its datapath routing but none of its side effects
+        if let Some((cause, val)) = self.get_trap() {
+            self.set_default_control_signals();
+            self.raise_trap(cause, val);
+        }
         self.set_next_state();
     }
 }
 
+/// Whether an instruction with these fields is one we implement
+pub fn is_legal_instruction(opcode: u8, funct3: u8, funct7: u8) -> bool {
+    match opcode {
+        opcode::LUI | opcode::AUIPC | opcode::JAL => true,
+        opcode::JALR | opcode::MISC_MEM => funct3 == 0,
+        opcode::BR => !matches!(funct3, 0b010 | 0b011),
+        opcode::LOAD => matches!(
+            funct3,
+            funct3::load::LB
+                | funct3::load::LH
+                | funct3::load::LW
+                | funct3::load::LBU
+                | funct3::load::LHU
+        ),
+        opcode::STORE => matches!(
+            funct3,
+            funct3::store::SB | funct3::store::SH | funct3::store::SW
+        ),
+        opcode::IMM => match funct3 {
+            funct3::arith::SLL => funct7 == 0,
+            funct3::arith::SR => funct7 & !0x20 == 0,
+            _ => true,
+        },
+        opcode::REG => match funct7 {
+            0x00 | funct7::MULDIV => true,
+            0x20 => matches!(funct3, funct3::arith::ADD | funct3::arith::SR),
+            _ => false,
+        },
+        opcode::SYSTEM => funct3 != 0b100,
+        _ => false,
+    }
+}
+
 impl Debug for Control {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -0,0 +1,379 @@
+use crate::backend::component::mem_ctl::{ClintMmioCtl, KeyboardMmioCtl};
+use crate::backend::util::types::*;
+use crossbeam_channel::{Sender, unbounded};
+use rsim_core::component::Component;
+use rsim_core::rx::Rx;
+use rsim_core::sim_manager::SimManager;
+use rsim_core::tx::Tx;
+use rsim_core::types::ComponentId;
+use rsim_core::types::EventId;
+use rsim_core::types::Input;
+use rsim_core::types::Output;
+use rsim_macro::ComponentAttribute;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+/// Machine-mode control and status registers for Zicsr
+///
+/// The csr number is taken from the low 12 bits of `i_imm`,
+/// `out` combinationally holds its current value and `wr` commits the
+/// read-modify-write selected by `op` (the instruction's funct3) on the clock edge.
+/// `illegal` is raised when `op` would access a missing csr or write a read-only one.
+/// `mcycle` counts every clock and `minstret` counts cycles with `retire` asserted.
+///
+/// `trap` latches `pc`, `trap_cause` and `trap_val` into mepc/mcause/mtval,
+/// `mret` restores mstatus.MIE, `mtvec_out` and `mepc_out` feed the pc mux.
+///
+/// mip.MTIP follows the clint and mip.MEIP the keyboard, both are latched on the clock edge,
+/// `interrupt` carries the mcause of the pending and enabled interrupt or zero if there is none.
+#[ComponentAttribute({
+"port": {
+    "input": [
+        ["i_imm", "Word"],
+        ["rs1_idx", "Byte"],
+        ["rs1_data", "Word"],
+        ["pc", "Word"],
+        ["op", "Byte"],
+        ["wr", "Byte"],
+        ["retire", "Byte"],
+        ["trap", "Byte"],
+        ["trap_cause", "Word"],
+        ["trap_val", "Word"],
+        ["mret", "Byte"]
+    ],
+    "output": [
+        ["out", "Word"],
+        ["mtvec_out", "Word"],
+        ["mepc_out", "Word"],
+        ["illegal", "Byte"],
+        ["interrupt", "Word"]
+    ],
+    "clock": true
+}
+})]
+pub struct CsrFile {
+    pub mstatus: u32,
+    pub mie: u32,
+    pub mip: u32,
+    pub mtvec: u32,
+    pub mscratch: u32,
+    pub mepc: u32,
+    pub mcause: u32,
+    pub mtval: u32,
+    pub mcycle: u64,
+    pub minstret: u64,
+    clint: Arc<Mutex<ClintMmioCtl>>,
+    keyboard: Arc<Mutex<KeyboardMmioCtl>>,
+}
+
+impl CsrFile {
+    /// RV32 with the I and M extensions
+    pub const MISA: u32 = 0x40001100;
+    pub const MHARTID: u32 = 0;
+    /// mstatus.MPP is hardwired to M-mode since there are no other privilege levels
+    pub const MSTATUS_MPP: u32 = 0x00001800;
+    pub const MSTATUS_MIE: u32 = 0x00000008;
+    pub const MSTATUS_MPIE: u32 = 0x00000080;
+    pub const MSTATUS_MASK: u32 = 0x00000088;
+    pub const MIE_MASK: u32 = 0x00000888;
+    pub const MIP_MTIP: u32 = 0x00000080;
+    pub const MIP_MEIP: u32 = 0x00000800;
+
+    pub fn new(
+        component_id: ComponentId,
+        sim_manager: Arc<SimManager>,
+        ack_sender: Sender<EventId>,
+        clint: Arc<Mutex<ClintMmioCtl>>,
+        keyboard: Arc<Mutex<KeyboardMmioCtl>>,
+        i_imm: Rx<Word>,
+        rs1_idx: Rx<Byte>,
+        rs1_data: Rx<Word>,
+        pc: Rx<Word>,
+        op: Rx<Byte>,
+        wr: Rx<Byte>,
+        retire: Rx<Byte>,
+        trap: Rx<Byte>,
+        trap_cause: Rx<Word>,
+        trap_val: Rx<Word>,
+        mret: Rx<Byte>,
+        out: Tx<Word>,
+        mtvec_out: Tx<Word>,
+        mepc_out: Tx<Word>,
+        illegal: Tx<Byte>,
+        interrupt: Tx<Word>,
+    ) -> Self {
+        let clock_channel = unbounded();
+
+        CsrFile {
+            mstatus: Self::MSTATUS_MPP,
+            mie: 0,
+            mip: 0,
+            mtvec: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            mcycle: 0,
+            minstret: 0,
+            clint,
+            keyboard,
+            component_id,
+            sim_manager,
+            ack_sender,
+            clock_sender: clock_channel.0,
+            clock_receiver: clock_channel.1,
+            i_imm,
+            rs1_idx,
+            rs1_data,
+            pc,
+            op,
+            wr,
+            retire,
+            trap,
+            trap_cause,
+            trap_val,
+            mret,
+            out,
+            mtvec_out,
+            mepc_out,
+            illegal,
+            interrupt,
+        }
+    }
+
+    fn init_impl(&mut self) {}
+
+    fn reset_impl(&mut self) {
+        self.mstatus = Self::MSTATUS_MPP;
+        self.mie = 0;
+        self.mip = 0;
+        self.mtvec = 0;
+        self.mscratch = 0;
+        self.mepc = 0;
+        self.mcause = 0;
+        self.mtval = 0;
+        self.mcycle = 0;
+        self.minstret = 0;
+    }
+
+    fn poll_impl(&mut self) {}
+
+    /// The csr number currently addressed by the instruction
+    pub fn get_addr(&self) -> Option<u16> {
+        Into::<Option<u32>>::into(self.i_imm.get_value()).map(|imm| (imm & 0xFFF) as u16)
+    }
+
+    /// Returns `None` if `addr` is not an implemented csr
+    pub fn read(&self, addr: u16) -> Option<u32> {
+        Some(match addr {
+            csr_addr::MSTATUS => self.mstatus,
+            csr_addr::MISA => Self::MISA,
+            csr_addr::MIE => self.mie,
+            csr_addr::MTVEC => self.mtvec,
+            csr_addr::MSCRATCH => self.mscratch,
+            csr_addr::MEPC => self.mepc,
+            csr_addr::MCAUSE => self.mcause,
+            csr_addr::MTVAL => self.mtval,
+            csr_addr::MIP => self.mip,
+            csr_addr::MCYCLE | csr_addr::CYCLE => self.mcycle as u32,
+            csr_addr::MCYCLEH | csr_addr::CYCLEH => (self.mcycle >> 32) as u32,
+            csr_addr::MINSTRET | csr_addr::INSTRET => self.minstret as u32,
+            csr_addr::MINSTRETH | csr_addr::INSTRETH => (self.minstret >> 32) as u32,
+            csr_addr::MVENDORID | csr_addr::MARCHID | csr_addr::MIMPID => 0,
+            csr_addr::MHARTID => Self::MHARTID,
+            _ => return None,
+        })
+    }
+
+    /// Whether `op` on the addressed csr is an illegal instruction
+    fn is_illegal(&self) -> bool {
+        let (Some(addr), Some(op), Some(rs1_idx)) = (
+            self.get_addr(),
+            Into::<Option<u8>>::into(self.op.get_value()),
+            Into::<Option<u8>>::into(self.rs1_idx.get_value()),
+        ) else {
+            return true;
+        };
+        self.is_illegal_op(addr, op, rs1_idx)
+    }
+
+    /// Whether `op` on `addr` is an illegal instruction,
+    /// the upper two bits of a csr number being set marks it as read-only
+    pub fn is_illegal_op(&self, addr: u16, op: u8, rs1_idx: u8) -> bool {
+        let writes = match op {
+            funct3::system::CSRRW | funct3::system::CSRRWI => true,
+            funct3::system::CSRRS
+            | funct3::system::CSRRSI
+            | funct3::system::CSRRC
+            | funct3::system::CSRRCI => rs1_idx != 0,
+            _ => return false,
+        };
+
+        self.read(addr).is_none() || (writes && addr >> 10 == 0b11)
+    }
+
+    /// The mcause of the highest priority pending and enabled interrupt, zero if none
+    pub fn get_interrupt_cause(&self) -> u32 {
+        if self.mstatus & Self::MSTATUS_MIE == 0 {
+            return 0;
+        }
+        let pending = self.mip & self.mie;
+        if pending & Self::MIP_MEIP != 0 {
+            trap_cause::MACHINE_EXTERNAL_INTERRUPT
+        } else if pending & Self::MIP_MTIP != 0 {
+            trap_cause::MACHINE_TIMER_INTERRUPT
+        } else {
+            0
+        }
+    }
+
+    /// Writes to read-only or unimplemented csrs are ignored
+    pub fn write(&mut self, addr: u16, value: u32) {
+        match addr {
+            csr_addr::MSTATUS => self.mstatus = (value & Self::MSTATUS_MASK) | Self::MSTATUS_MPP,
+            csr_addr::MIE => self.mie = value & Self::MIE_MASK,
+            csr_addr::MTVEC => self.mtvec = value & !0x3,
+            csr_addr::MSCRATCH => self.mscratch = value,
+            csr_addr::MEPC => self.mepc = value & !0x3,
+            csr_addr::MCAUSE => self.mcause = value,
+            csr_addr::MTVAL => self.mtval = value,
+            csr_addr::MCYCLE => self.mcycle = (self.mcycle & !0xFFFFFFFF) | value as u64,
+            csr_addr::MCYCLEH => self.mcycle = (self.mcycle & 0xFFFFFFFF) | ((value as u64) << 32),
+            csr_addr::MINSTRET => self.minstret = (self.minstret & !0xFFFFFFFF) | value as u64,
+            csr_addr::MINSTRETH => {
+                self.minstret = (self.minstret & 0xFFFFFFFF) | ((value as u64) << 32)
+            }
+            _ => {}
+        }
+    }
+
+    fn on_clock(&mut self) {
+        // counters advance first so an explicit write in the same cycle wins,
+        // an instruction that traps does not retire
+        self.mcycle = self.mcycle.wrapping_add(1);
+        if self.retire.get_value().is_something_nonzero()
+            && !self.trap.get_value().is_something_nonzero()
+        {
+            self.minstret = self.minstret.wrapping_add(1);
+        }
+        // the devices change outside the sim, so they are only sampled on the edge
+        self.update_mip();
+
+        if self.trap.get_value().is_something_nonzero() {
+            self.enter_trap(
+                Into::<Option<u32>>::into(self.pc.get_value()),
+                Into::<Option<u32>>::into(self.trap_cause.get_value()).unwrap_or(0),
+                Into::<Option<u32>>::into(self.trap_val.get_value()).unwrap_or(0),
+            );
+        } else if self.mret.get_value().is_something_nonzero() {
+            self.return_from_trap();
+        } else if self.wr.get_value().is_something_nonzero()
+            && let Some(addr) = self.get_addr()
+            && let (Some(op), Some(rs1_idx)) = (
+                Into::<Option<u8>>::into(self.op.get_value()),
+                Into::<Option<u8>>::into(self.rs1_idx.get_value()),
+            )
+        {
+            self.execute_op(
+                addr,
+                op,
+                rs1_idx,
+                Into::<Option<u32>>::into(self.rs1_data.get_value()),
+            );
+        }
+    }
+
+    /// Latches `pc`, `cause` and `val` and disables interrupts, mepc is kept if `pc` is unknown
+    pub fn enter_trap(&mut self, pc: Option<u32>, cause: u32, val: u32) {
+        if let Some(pc) = pc {
+            self.mepc = pc & !0x3;
+        }
+        self.mcause = cause;
+        self.mtval = val;
+        self.mstatus = if self.mstatus & Self::MSTATUS_MIE != 0 {
+            self.mstatus | Self::MSTATUS_MPIE
+        } else {
+            self.mstatus & !Self::MSTATUS_MPIE
+        } & !Self::MSTATUS_MIE;
+    }
+
+    /// mstatus.MIE goes back to what it was before the trap
+    pub fn return_from_trap(&mut self) {
+        self.mstatus = if self.mstatus & Self::MSTATUS_MPIE != 0 {
+            self.mstatus | Self::MSTATUS_MIE
+        } else {
+            self.mstatus & !Self::MSTATUS_MIE
+        } | Self::MSTATUS_MPIE;
+    }
+
+    /// The read-modify-write of a csr instruction, `rs1_data` is unused by the immediate forms
+    pub fn execute_op(&mut self, addr: u16, op: u8, rs1_idx: u8, rs1_data: Option<u32>) {
+        let Some(old) = self.read(addr) else {
+            return;
+        };
+        let operand = match op {
+            funct3::system::CSRRWI | funct3::system::CSRRSI | funct3::system::CSRRCI => {
+                Some(rs1_idx as u32)
+            }
+            _ => rs1_data,
+        };
+        // csrrs/csrrc with x0 (or a zero uimm) only read
+        let new = match op {
+            funct3::system::CSRRW | funct3::system::CSRRWI => operand,
+            funct3::system::CSRRS | funct3::system::CSRRSI if rs1_idx != 0 => {
+                operand.map(|operand| old | operand)
+            }
+            funct3::system::CSRRC | funct3::system::CSRRCI if rs1_idx != 0 => {
+                operand.map(|operand| old & !operand)
+            }
+            _ => None,
+        };
+        if let Some(new) = new {
+            self.write(addr, new);
+        }
+    }
+
+    /// mip.MTIP follows the clint and mip.MEIP the keyboard
+    pub fn update_mip(&mut self) {
+        if self.clint.lock().unwrap().is_interrupt_pending() {
+            self.mip |= Self::MIP_MTIP;
+        } else {
+            self.mip &= !Self::MIP_MTIP;
+        }
+        if self.keyboard.lock().unwrap().is_interrupt_pending() {
+            self.mip |= Self::MIP_MEIP;
+        } else {
+            self.mip &= !Self::MIP_MEIP;
+        }
+    }
+
+    fn on_comb(&mut self) {
+        self.out.send(
+            self.get_addr()
+                .and_then(|addr| self.read(addr))
+                .map_or(Word::unknown(), Word::from),
+            0,
+        );
+        self.mtvec_out.send(Word::from(self.mtvec), 0);
+        self.mepc_out.send(Word::from(self.mepc), 0);
+        self.illegal.send(Byte::from(self.is_illegal() as u8), 0);
+        self.interrupt
+            .send(Word::from(self.get_interrupt_cause()), 0);
+    }
+}
+
+impl Debug for CsrFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CsrFile: {{addr: {:?}, op: {:?}, wr: {:?}, trap: {:?}, mret: {:?}, mcycle: {}, minstret: {}}}",
+            self.get_addr(),
+            self.op.get_value(),
+            self.wr.get_value(),
+            self.trap.get_value(),
+            self.mret.get_value(),
+            self.mcycle,
+            self.minstret
+        )
+    }
+}
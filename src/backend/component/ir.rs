@@ -40,6 +40,22 @@ pub struct IR {
     pub data_inner: Word,
 }
 
+/// The fields of an instruction as `IR` drives them, immediates are sign extended
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InstFields {
+    pub funct3: u8,
+    pub funct7: u8,
+    pub opcode: u8,
+    pub i_imm: Word,
+    pub s_imm: Word,
+    pub b_imm: Word,
+    pub u_imm: Word,
+    pub j_imm: Word,
+    pub rs1: u8,
+    pub rs2: u8,
+    pub rd: u8,
+}
+
 impl IR {
     pub fn new(
         component_id: ComponentId,
@@ -108,49 +124,60 @@ impl IR {
 
     fn on_comb(&mut self) {
         if let Some(inst) = Into::<Option<u32>>::into(self.data_inner) {
-            self.funct3
-                .send(Byte::from(((inst >> 12) & 0b111) as u8), 0);
-            self.funct7
-                .send(Byte::from(((inst >> 25) & 0b1111111) as u8), 0);
-            self.opcode.send(Byte::from((inst & 0b1111111) as u8), 0);
-            self.i_imm.send(sign_extend(inst >> 20, 11), 0);
-            self.s_imm.send(
-                sign_extend(
-                    (((inst >> 25) & 0b1111111) << 5) | ((inst >> 7) & 0b11111),
-                    11,
-                ),
-                0,
-            );
-            self.b_imm.send(
-                sign_extend(
-                    (((inst >> 31) & 0b1) << 12)
-                        | (((inst >> 7) & 0b1) << 11)
-                        | (((inst >> 25) & 0b111111) << 5)
-                        | (((inst >> 8) & 0b1111) << 1),
-                    12,
-                ),
-                0,
-            );
-            self.u_imm
-                .send(Word::from(((inst >> 12) & 0xFFFFF) << 12), 0);
-            self.j_imm.send(
-                sign_extend(
-                    (((inst >> 31) & 0b1) << 20)
-                        | (((inst >> 12) & 0xFF) << 12)
-                        | (((inst >> 20) & 0b1) << 11)
-                        | (((inst >> 21) & 0x3FF) << 1),
-                    20,
-                ),
-                0,
-            );
-            self.rs1.send(Byte::from(((inst >> 15) & 0x1F) as u8), 0);
-            self.rs2.send(Byte::from(((inst >> 20) & 0x1F) as u8), 0);
-            self.rd.send(Byte::from(((inst >> 7) & 0x1F) as u8), 0);
+            let fields = Self::decode(inst);
+            self.funct3.send(Byte::from(fields.funct3), 0);
+            self.funct7.send(Byte::from(fields.funct7), 0);
+            self.opcode.send(Byte::from(fields.opcode), 0);
+            self.i_imm.send(fields.i_imm, 0);
+            self.s_imm.send(fields.s_imm, 0);
+            self.b_imm.send(fields.b_imm, 0);
+            self.u_imm.send(fields.u_imm, 0);
+            self.j_imm.send(fields.j_imm, 0);
+            self.rs1.send(Byte::from(fields.rs1), 0);
+            self.rs2.send(Byte::from(fields.rs2), 0);
+            self.rd.send(Byte::from(fields.rd), 0);
+        }
+    }
+
+    /// Splits an instruction into the fields driven on the outputs
+    pub fn decode(inst: u32) -> InstFields {
+        InstFields {
+            funct3: ((inst >> 12) & 0b111) as u8,
+            funct7: ((inst >> 25) & 0b1111111) as u8,
+            opcode: (inst & 0b1111111) as u8,
+            i_imm: sign_extend(inst >> 20, 11),
+            s_imm: sign_extend(
+                (((inst >> 25) & 0b1111111) << 5) | ((inst >> 7) & 0b11111),
+                11,
+            ),
+            b_imm: sign_extend(
+                (((inst >> 31) & 0b1) << 12)
+                    | (((inst >> 7) & 0b1) << 11)
+                    | (((inst >> 25) & 0b111111) << 5)
+                    | (((inst >> 8) & 0b1111) << 1),
+                12,
+            ),
+            u_imm: Word::from(((inst >> 12) & 0xFFFFF) << 12),
+            j_imm: sign_extend(
+                (((inst >> 31) & 0b1) << 20)
+                    | (((inst >> 12) & 0xFF) << 12)
+                    | (((inst >> 20) & 0b1) << 11)
+                    | (((inst >> 21) & 0x3FF) << 1),
+                20,
+            ),
+            rs1: ((inst >> 15) & 0x1F) as u8,
+            rs2: ((inst >> 20) & 0x1F) as u8,
+            rd: ((inst >> 7) & 0x1F) as u8,
         }
     }
 
     pub fn can_end(&self) -> bool {
-        self.data_inner == Word::from(0xF0002013u32) || self.data_inner == Word::from(0x00000063u32)
+        Self::is_end_instruction(self.data_inner)
+    }
+
+    /// The instructions a program spins on once it is done
+    pub fn is_end_instruction(inst: Word) -> bool {
+        inst == Word::from(0xF0002013u32) || inst == Word::from(0x00000063u32)
     }
 }
 
@@ -1,8 +1,16 @@
+use crate::backend::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
 use crate::backend::component::pc::Pc;
-use crate::backend::util::types::{Byte, Word};
+use crate::backend::history::MemUndoLog;
+use crate::backend::loader::Program;
+use crate::backend::util::paged_mem::PagedMem;
+use crate::backend::util::types::{Byte, Word, mux_sel, trap_cause};
 use crossbeam_channel::{Sender, unbounded};
 use elf::ElfBytes;
+use elf::abi::{
+    EM_RISCV, PF_R, PF_W, PF_X, PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS,
+};
 use elf::endian::LittleEndian;
+use elf::file::Class;
 use rsim_core::component::Component;
 use rsim_core::rx::Rx;
 use rsim_core::sim_manager::SimManager;
@@ -16,6 +24,203 @@ use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::ops::Range;
 use std::sync::{Arc, Mutex};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+#[derive(Display, Copy, Clone, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum WatchpointType {
+    Read,
+    Write,
+    Access,
+}
+
+/// Stops execution once an access of `watchpoint_type` touches any byte in `addr_range`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub addr_range: Range<u32>,
+    pub watchpoint_type: WatchpointType,
+}
+
+impl Watchpoint {
+    fn is_hit(&self, access_type: WatchpointType, addr: u32) -> bool {
+        (self.watchpoint_type == WatchpointType::Access || self.watchpoint_type == access_type)
+            && self.addr_range.contains(&addr)
+    }
+}
+
+/// The access that hit a watchpoint, values are of the whole word at `addr`
+///
+/// `old_value` and `new_value` are the same for reads, bytes that are not in memory are unknown
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub access_type: WatchpointType,
+    pub addr: u32,
+    pub old_value: Word,
+    pub new_value: Word,
+}
+
+/// What a region allows, like the `(rwx)` attributes of a linker script `MEMORY` region
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const RX: Permissions = Permissions {
+        read: true,
+        write: false,
+        execute: true,
+    };
+    pub const RWX: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+    };
+
+    fn allows(&self, access_type: AccessType) -> bool {
+        match access_type {
+            AccessType::Fetch => self.execute,
+            AccessType::Load => self.read,
+            AccessType::Store => self.write,
+        }
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemRegion {
+    pub name: String,
+    pub addr_range: Range<u32>,
+    pub permissions: Permissions,
+}
+
+impl MemRegion {
+    /// The `MEMORY` regions of `tests/link.ld`
+    pub fn linker_script_layout() -> Vec<MemRegion> {
+        vec![
+            MemRegion {
+                name: "ROM".to_string(),
+                addr_range: 0x40000000..0x50000000,
+                permissions: Permissions::RX,
+            },
+            MemRegion {
+                name: "RAM".to_string(),
+                addr_range: 0x50000000..0x60000000,
+                permissions: Permissions::RWX,
+            },
+        ]
+    }
+}
+
+/// Where the memory map comes from, accesses it does not allow raise access faults
+///
+/// The discriminants are saved in checkpoints, new types take new numbers
+#[derive(Display, Copy, Clone, Debug, Default, PartialEq, Eq, Hash, EnumIter)]
+#[repr(u32)]
+pub enum MemMapType {
+    /// Every address can be read, written and executed
+    #[default]
+    None = 0,
+    #[strum(to_string = "Linker Script")]
+    LinkerScript = 1,
+    /// The flags of the loaded ELF's `PT_LOAD` segments,
+    /// the linker script layout covers everything outside of them such as the stack
+    #[strum(to_string = "ELF Segments")]
+    ElfSegments = 2,
+}
+
+impl Checkpoint for MemMapType {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (*self as u32).save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let discriminant = u32::load(reader)?;
+        Self::iter()
+            .find(|&mem_map_type| mem_map_type as u32 == discriminant)
+            .ok_or_else(|| format!("Invalid memory map type {}", discriminant))
+    }
+}
+
+impl Checkpoint for MemRegion {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.name.save(writer);
+        self.addr_range.start.save(writer);
+        self.addr_range.end.save(writer);
+        self.permissions.read.save(writer);
+        self.permissions.write.save(writer);
+        self.permissions.execute.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            name: Checkpoint::load(reader)?,
+            addr_range: u32::load(reader)?..u32::load(reader)?,
+            permissions: Permissions {
+                read: Checkpoint::load(reader)?,
+                write: Checkpoint::load(reader)?,
+                execute: Checkpoint::load(reader)?,
+            },
+        })
+    }
+}
+
+#[derive(Display, Copy, Clone, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum AccessType {
+    Fetch,
+    Load,
+    Store,
+}
+
+/// An access the memory map does not allow, it never reaches memory or a device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessFault {
+    pub access_type: AccessType,
+    pub addr: u32,
+    /// The region `addr` is in, None if it is unmapped
+    pub region: Option<MemRegion>,
+}
+
+impl AccessFault {
+    /// The mcause of the trap it raises
+    pub fn get_cause(&self) -> u32 {
+        match self.access_type {
+            AccessType::Fetch => trap_cause::INSTRUCTION_ACCESS_FAULT,
+            AccessType::Load => trap_cause::LOAD_ACCESS_FAULT,
+            AccessType::Store => trap_cause::STORE_ACCESS_FAULT,
+        }
+    }
+}
+
+impl std::fmt::Display for AccessFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} access fault @ 0x{:08X}: ",
+            self.access_type, self.addr
+        )?;
+        match &self.region {
+            Some(region) => write!(
+                f,
+                "{} (0x{:08X}..0x{:08X}, {}) does not allow it",
+                region.name, region.addr_range.start, region.addr_range.end, region.permissions
+            ),
+            None => write!(f, "unmapped"),
+        }
+    }
+}
 
 #[ComponentAttribute({
 "port": {
@@ -23,20 +228,32 @@ use std::sync::{Arc, Mutex};
         ["cpu_addr", "Word"],
         ["cpu_wdata", "Word"],
         ["cpu_rmask", "Byte"],
-        ["cpu_wmask", "Byte"]
+        ["cpu_wmask", "Byte"],
+        ["mem_addr_mux_sel", "Byte"]
     ],
     "output": [
         ["cpu_rdata", "Word"],
-        ["cpu_resp", "Byte"]
+        ["cpu_resp", "Byte"],
+        ["cpu_fault", "Byte"]
     ],
     "clock": true
 }
 })]
 #[allow(dead_code)]
 pub struct MemCtl {
-    pub backend_mem: BTreeMap<Word, Byte>,
+    pub backend_mem: PagedMem,
     pub label: BTreeMap<Word, String>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub watchpoint_hit: Option<WatchpointHit>,
+    /// Writes to `backend_mem` since the last snapshot
+    pub mem_undo_log: MemUndoLog,
+    pub mem_map_type: MemMapType,
+    /// The regions of the loaded ELF's `PT_LOAD` segments, see `MemMapType::ElfSegments`
+    pub elf_regions: Vec<MemRegion>,
+    /// The last access the memory map did not allow
+    pub access_fault: Option<AccessFault>,
     pc: Arc<Mutex<Pc>>,
+    htif: Arc<Mutex<HtifMmioCtl>>,
     mmio_ctl: HashMap<Range<u32>, Arc<Mutex<dyn MmioCtl>>>,
     is_busy: bool,
 }
@@ -47,19 +264,29 @@ impl MemCtl {
         sim_manager: Arc<SimManager>,
         ack_sender: Sender<EventId>,
         pc: Arc<Mutex<Pc>>,
+        htif: Arc<Mutex<HtifMmioCtl>>,
         cpu_addr: Rx<Word>,
         cpu_wdata: Rx<Word>,
         cpu_rmask: Rx<Byte>,
         cpu_wmask: Rx<Byte>,
+        mem_addr_mux_sel: Rx<Byte>,
         cpu_rdata: Tx<Word>,
         cpu_resp: Tx<Byte>,
+        cpu_fault: Tx<Byte>,
     ) -> Self {
         let clock_channel = unbounded();
 
         MemCtl {
             backend_mem: Default::default(),
             label: Default::default(),
+            watchpoints: Default::default(),
+            watchpoint_hit: None,
+            mem_undo_log: Default::default(),
+            mem_map_type: Default::default(),
+            elf_regions: Default::default(),
+            access_fault: None,
             pc,
+            htif,
             mmio_ctl: Default::default(),
             is_busy: false,
             component_id,
@@ -71,8 +298,10 @@ impl MemCtl {
             cpu_wdata,
             cpu_rmask,
             cpu_wmask,
+            mem_addr_mux_sel,
             cpu_rdata,
             cpu_resp,
+            cpu_fault,
         }
     }
 
@@ -81,7 +310,11 @@ impl MemCtl {
     fn reset_impl(&mut self) {
         self.backend_mem.clear();
         self.label.clear();
+        self.elf_regions.clear();
         self.mmio_ctl.clear();
+        self.watchpoint_hit = None;
+        self.mem_undo_log.clear();
+        self.access_fault = None;
     }
 
     fn poll_impl(&mut self) {}
@@ -97,96 +330,434 @@ impl MemCtl {
             if let Some(wmask) = Into::<Option<u8>>::into(self.cpu_wmask.get_value())
                 && wmask != 0
             {
+                let mut is_fault = false;
                 if let Some(raw_addr_idx) = Into::<Option<u32>>::into(self.cpu_addr.get_value()) {
-                    for i in 0..4 {
-                        if (wmask >> i) & 0x1 == 0x1 {
-                            let addr_idx = (raw_addr_idx & 0xFFFFFFFCu32) + i as u32;
-                            let data = (self.cpu_wdata.get_value()
-                                << Word::from(8 * (raw_addr_idx & 0x3)))[i]
-                                .map(Byte::from)
-                                .unwrap_or(Byte::unknown());
-
-                            let mut written_to_mmio = false;
-                            for (addr_range, mmio_ctl) in self.mmio_ctl.iter_mut() {
-                                if addr_range.contains(&addr_idx) {
-                                    mmio_ctl.lock().unwrap().write(Word::from(addr_idx), data);
-                                    written_to_mmio = true;
-                                }
-                            }
-
-                            if !written_to_mmio {
-                                self.backend_mem.insert(Word::from(addr_idx), data);
-                            }
-                        }
+                    is_fault = self.check_access(AccessType::Store, raw_addr_idx).is_err();
+                    if !is_fault {
+                        self.write_word(raw_addr_idx, wmask, self.cpu_wdata.get_value());
                     }
                 }
                 self.cpu_resp.send(Byte::from(1u8), 0);
+                self.cpu_fault.send(Byte::from(is_fault as u8), 0);
                 self.is_busy = true;
             } else if let Some(rmask) = Into::<Option<u8>>::into(self.cpu_rmask.get_value())
                 && rmask != 0
             {
-                let mut ret = Word::unknown();
-                for i in 0..4 {
-                    if (rmask >> i) & 0x1 == 0x1 {
-                        let addr_idx = (self.cpu_addr.get_value() & Word::from(0xFFFFFFFCu32))
-                            + Word::from(i as u32);
-                        if self.backend_mem.contains_key(&addr_idx) {
-                            ret[i] = self.backend_mem[&addr_idx].into();
+                let is_fetch = self.is_fetch();
+                let access_type = if is_fetch {
+                    AccessType::Fetch
+                } else {
+                    AccessType::Load
+                };
+                let mut is_fault = false;
+                let ret = match Into::<Option<u32>>::into(self.cpu_addr.get_value()) {
+                    Some(addr) => {
+                        is_fault = self.check_access(access_type, addr).is_err();
+                        if is_fault {
+                            Word::unknown()
                         } else {
-                            for (addr_range, mmio_ctl) in self.mmio_ctl.iter_mut() {
-                                if let Some(addr_idx_u32) = addr_idx.into() {
-                                    if addr_range.contains(&addr_idx_u32) {
-                                        ret[i] = mmio_ctl.lock().unwrap().read(addr_idx).into();
-                                    }
-                                }
-                            }
+                            self.read_word(addr, rmask, is_fetch)
                         }
                     }
-                }
+                    None => Word::unknown(),
+                };
                 self.cpu_rdata.send(ret, 0);
                 self.cpu_resp.send(Byte::from(1u8), 0);
+                self.cpu_fault.send(Byte::from(is_fault as u8), 0);
                 self.is_busy = true;
             }
         } else {
             self.cpu_resp.send(Byte::from(0u8), 0);
+            self.cpu_fault.send(Byte::from(0u8), 0);
             self.is_busy = false;
         }
     }
 
-    fn on_comb(&mut self) {}
+    /// Control only selects the pc as the address in its fetch state, every other read is a load
+    fn is_fetch(&self) -> bool {
+        self.mem_addr_mux_sel.get_value() == Byte::from(mux_sel::mem_addr::PC_OUT)
+    }
+
+    /// The regions accesses are checked against, empty if everything is allowed
+    pub fn get_mem_map(&self) -> Vec<MemRegion> {
+        match self.mem_map_type {
+            MemMapType::None => Vec::new(),
+            MemMapType::LinkerScript => MemRegion::linker_script_layout(),
+            MemMapType::ElfSegments => {
+                let mut mem_map = self.elf_regions.clone();
+                mem_map.extend(MemRegion::linker_script_layout());
+                mem_map
+            }
+        }
+    }
+
+    /// The fault an access to `addr` raises, None if the memory map allows it
+    ///
+    /// Devices can always be read and written, the first region containing `addr` decides the rest
+    pub fn get_access_fault(&self, access_type: AccessType, addr: u32) -> Option<AccessFault> {
+        if self.mem_map_type == MemMapType::None
+            || (access_type != AccessType::Fetch
+                && self
+                    .mmio_ctl
+                    .keys()
+                    .any(|addr_range| addr_range.contains(&addr)))
+        {
+            return None;
+        }
+        let region = self
+            .get_mem_map()
+            .into_iter()
+            .find(|region| region.addr_range.contains(&addr));
+        if region
+            .as_ref()
+            .is_some_and(|region| region.permissions.allows(access_type))
+        {
+            None
+        } else {
+            Some(AccessFault {
+                access_type,
+                addr,
+                region,
+            })
+        }
+    }
+
+    /// Whether a cache with `line_size` byte lines can serve an access to `addr`
+    ///
+    /// The cache moves the whole line without going through the checks here, so it has to be memory
+    /// without devices or watchpoints that the memory map allows the access and the fill to
+    pub fn is_cacheable(&self, access_type: AccessType, addr: u32, line_size: u32) -> bool {
+        let line_addr = addr & !(line_size - 1);
+        let line = line_addr as u64..line_addr as u64 + line_size as u64;
+        let overlaps_line = |addr_range: &Range<u32>| {
+            (addr_range.start as u64) < line.end && line.start < addr_range.end as u64
+        };
+        if self.mmio_ctl.keys().any(overlaps_line)
+            || self
+                .watchpoints
+                .iter()
+                .any(|watchpoint| overlaps_line(&watchpoint.addr_range))
+        {
+            return false;
+        }
+        let access_types: &[AccessType] = match access_type {
+            AccessType::Store => &[AccessType::Load, AccessType::Store],
+            _ => &[access_type],
+        };
+        (0..line_size).step_by(4).all(|offset| {
+            access_types.iter().all(|access_type| {
+                self.get_access_fault(*access_type, line_addr + offset)
+                    .is_none()
+            })
+        })
+    }
+
+    /// Same as `get_access_fault`, but a fault is kept in `access_fault`
+    pub fn check_access(&mut self, access_type: AccessType, addr: u32) -> Result<(), AccessFault> {
+        match self.get_access_fault(access_type, addr) {
+            Some(access_fault) => {
+                self.access_fault = Some(access_fault.clone());
+                Err(access_fault)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the last access fault cannot be handled, because the trap handler at `mtvec`
+    /// cannot be fetched from either and every trap into it would fault again
+    pub fn has_unhandled_access_fault(&self, mtvec: u32) -> bool {
+        self.access_fault.is_some()
+            && self
+                .get_access_fault(AccessType::Fetch, mtvec & !0x3)
+                .is_some()
+    }
 
-    pub fn load_elf(&mut self, data: &[u8]) {
-        ElfBytes::<LittleEndian>::minimal_parse(data)
-            .map(|elf_bytes| {
-                // symbol table
-                if let Ok(Some((symbol_table, string_table))) = elf_bytes.symbol_table() {
-                    for symbol in symbol_table.iter() {
-                        if symbol.st_name != 0 {
-                            if let Ok(symbol_name) = string_table.get(symbol.st_name as usize) {
-                                self.label.insert(
-                                    Word::from(symbol.st_value as u32),
-                                    symbol_name.to_string(),
-                                );
-                            }
+    /// Reads the bytes in `rmask` of the word containing `addr`, memory takes priority over mmio
+    ///
+    /// Fetches do not trigger watchpoints
+    pub fn read_word(&mut self, addr: u32, rmask: u8, is_fetch: bool) -> Word {
+        let aligned_addr = addr & 0xFFFFFFFCu32;
+        let mut ret = Word::unknown();
+        for i in 0..4 {
+            if (rmask >> i) & 0x1 == 0x1 {
+                let addr_idx = Word::from(aligned_addr + i as u32);
+                if self.backend_mem.contains(aligned_addr + i as u32) {
+                    ret[i] = self.backend_mem.get(aligned_addr + i as u32).into();
+                } else {
+                    for (addr_range, mmio_ctl) in self.mmio_ctl.iter_mut() {
+                        if addr_range.contains(&(aligned_addr + i as u32)) {
+                            ret[i] = mmio_ctl.lock().unwrap().read(addr_idx).into();
                         }
                     }
                 }
-                // sections
-                if let Some(section_table) = elf_bytes.section_headers() {
-                    for section_header in section_table.iter() {
-                        if let Ok((section_data, _)) = elf_bytes.section_data(&section_header) {
-                            let addr = Word::from(section_header.sh_addr as u32);
-                            for i in 0..section_data.len() as u32 {
-                                self.backend_mem
-                                    .insert(addr + Word::from(i), section_data[i as usize].into());
-                            }
+            }
+        }
+        if !is_fetch {
+            self.check_watchpoints(WatchpointType::Read, aligned_addr, rmask, ret, ret);
+        }
+        ret
+    }
+
+    /// Writes the bytes in `wmask` of the word containing `addr`,
+    /// `wdata` is shifted by the byte offset of `addr` like the cpu does
+    pub fn write_word(&mut self, addr: u32, wmask: u8, wdata: Word) {
+        let aligned_addr = addr & 0xFFFFFFFCu32;
+        let old_value = self.peek_word(aligned_addr);
+        for i in 0..4 {
+            if (wmask >> i) & 0x1 == 0x1 {
+                let addr_idx = aligned_addr + i as u32;
+                let data = (wdata << Word::from(8 * (addr & 0x3)))[i]
+                    .map(Byte::from)
+                    .unwrap_or(Byte::unknown());
+                self.poke_byte(addr_idx, data);
+            }
+        }
+        let new_value = self.peek_word(aligned_addr);
+        self.check_watchpoints(
+            WatchpointType::Write,
+            aligned_addr,
+            wmask,
+            old_value,
+            new_value,
+        );
+    }
+
+    /// Writes a byte as the cpu would, to the device if it is mmio, without triggering watchpoints
+    ///
+    /// Memory keeps the old byte in the undo log so that rewinding takes the write back
+    pub fn poke_byte(&mut self, addr: u32, data: Byte) {
+        let mut written_to_mmio = false;
+        for (addr_range, mmio_ctl) in self.mmio_ctl.iter_mut() {
+            if addr_range.contains(&addr) {
+                mmio_ctl.lock().unwrap().write(Word::from(addr), data);
+                written_to_mmio = true;
+            }
+        }
+
+        if !written_to_mmio {
+            let old_data = self.backend_mem.insert(addr, data);
+            self.mem_undo_log
+                .entry(Word::from(addr))
+                .or_insert(old_data);
+        }
+    }
+
+    fn on_comb(&mut self) {}
+
+    /// Reads a byte as the cpu would, mmio included but without its side effects
+    pub fn peek_byte(&self, addr: u32) -> Byte {
+        if self.backend_mem.contains(addr) {
+            return self.backend_mem.get(addr);
+        }
+        self.mmio_ctl
+            .iter()
+            .find(|(addr_range, _)| addr_range.contains(&addr))
+            .map_or(Byte::unknown(), |(_, mmio_ctl)| {
+                mmio_ctl.lock().unwrap().peek(Word::from(addr))
+            })
+    }
+
+    /// Reads a word without going through mmio, which may have side effects
+    fn peek_word(&self, aligned_addr: u32) -> Word {
+        let mut word = Word::unknown();
+        for (i, byte) in self
+            .backend_mem
+            .read_bytes(aligned_addr, 4)
+            .iter()
+            .enumerate()
+        {
+            word[i] = (*byte).into();
+        }
+        word
+    }
+
+    fn check_watchpoints(
+        &mut self,
+        access_type: WatchpointType,
+        aligned_addr: u32,
+        mask: u8,
+        old_value: Word,
+        new_value: Word,
+    ) {
+        let is_hit = (0..4).filter(|i| (mask >> i) & 0x1 == 0x1).any(|i| {
+            self.watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.is_hit(access_type, aligned_addr + i))
+        });
+        if is_hit {
+            self.watchpoint_hit = Some(WatchpointHit {
+                access_type,
+                addr: aligned_addr,
+                old_value,
+                new_value,
+            });
+        }
+    }
+
+    /// Routes `tohost`/`fromhost` to the htif, replacing the ones from a previously loaded ELF
+    fn install_htif(&mut self, tohost_addr: Option<u32>, fromhost_addr: Option<u32>) {
+        let mut htif = self.htif.lock().unwrap();
+        for addr in [htif.get_tohost_addr(), htif.get_fromhost_addr()]
+            .into_iter()
+            .flatten()
+        {
+            self.mmio_ctl.remove(&(addr..addr + 8));
+        }
+
+        htif.reset();
+        htif.set_addrs(tohost_addr, fromhost_addr);
+        for addr in [tohost_addr, fromhost_addr].into_iter().flatten() {
+            self.mmio_ctl.insert(addr..addr + 8, self.htif.clone());
+            // reads hit backend_mem before mmio, drop what the ELF section put there
+            for i in 0..8 {
+                self.backend_mem.remove(addr + i);
+            }
+        }
+    }
+
+    /// Loads the `PT_LOAD` segments of an ELF, zero filling the part of a segment
+    /// that is only in memory like `.bss`, and points the pc at the entry
+    ///
+    /// Their flags become the regions of `MemMapType::ElfSegments`.
+    /// An ELF without program headers has its allocated sections loaded instead.
+    pub fn load_elf(&mut self, elf: &Elf) {
+        let mut tohost_addr = None;
+        let mut fromhost_addr = None;
+        // symbol table
+        if let Ok(Some((symbol_table, string_table))) = elf.elf_bytes.symbol_table() {
+            for symbol in symbol_table.iter() {
+                if symbol.st_name != 0 {
+                    if let Ok(symbol_name) = string_table.get(symbol.st_name as usize) {
+                        match symbol_name {
+                            "tohost" => tohost_addr = Some(symbol.st_value as u32),
+                            "fromhost" => fromhost_addr = Some(symbol.st_value as u32),
+                            _ => {}
                         }
+                        self.label
+                            .insert(Word::from(symbol.st_value as u32), symbol_name.to_string());
                     }
                 }
-                // entry
-                self.pc.lock().unwrap().data_inner = Word::from(elf_bytes.ehdr.e_entry as u32);
+            }
+        }
+        for (addr, chunk_data, mem_size) in &elf.chunks {
+            let mut data = chunk_data.to_vec();
+            data.resize(data.len().max(*mem_size as usize), 0);
+            self.backend_mem.write_bytes(*addr, &data);
+        }
+        self.install_htif(tohost_addr, fromhost_addr);
+        self.elf_regions = elf.elf_regions.clone();
+        // entry
+        self.pc.lock().unwrap().data_inner = Word::from(elf.elf_bytes.ehdr.e_entry as u32);
+    }
+
+    /// Like `load_elf`, for a program from the built-in assembler
+    pub fn load_program(&mut self, program: &Program) {
+        self.backend_mem
+            .extend(program.mem.iter().map(|(addr, byte)| (*addr, *byte)));
+        self.label.extend(program.label.clone());
+        let get_label_addr = |name: &str| {
+            program
+                .label
+                .iter()
+                .find(|(_, label)| *label == name)
+                .and_then(|(addr, _)| (*addr).into())
+        };
+        self.install_htif(get_label_addr("tohost"), get_label_addr("fromhost"));
+        self.elf_regions.clear();
+        self.pc.lock().unwrap().data_inner = Word::from(program.entry);
+    }
+
+    /// `addr..addr + size`, cut short at the end of the address space
+    fn get_region_range(addr: u64, size: u64) -> Range<u32> {
+        let start = addr.min(u32::MAX as u64);
+        start as u32..(start + size).min(u32::MAX as u64) as u32
+    }
+}
+
+/// An ELF with its segments read, or its sections if it has no program headers,
+/// see `MemCtl::load_elf`
+pub struct Elf<'a> {
+    elf_bytes: ElfBytes<'a, LittleEndian>,
+    /// (address, data in the file, size in memory)
+    chunks: Vec<(u32, &'a [u8], u32)>,
+    elf_regions: Vec<MemRegion>,
+}
+
+impl<'a> Elf<'a> {
+    /// Fails if `data` is not a 32 bit RISC-V ELF, or if a segment or section cannot be read
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let elf_bytes = ElfBytes::<LittleEndian>::minimal_parse(data)
+            .map_err(|e| format!("failed to parse ELF: {}", e))?;
+        if elf_bytes.ehdr.class != Class::ELF32 || elf_bytes.ehdr.e_machine != EM_RISCV {
+            return Err("not a 32 bit RISC-V ELF".to_string());
+        }
+
+        let mut chunks = Vec::new();
+        let mut elf_regions = Vec::new();
+        let segments = elf_bytes
+            .segments()
+            .map(|segments| {
+                segments
+                    .iter()
+                    .filter(|segment| segment.p_type == PT_LOAD)
+                    .collect::<Vec<_>>()
             })
-            .unwrap_or_else(|_| println!("Failed to parse ELF file"));
+            .unwrap_or_default();
+        if !segments.is_empty() {
+            for segment in segments {
+                let segment_data = elf_bytes
+                    .segment_data(&segment)
+                    .map_err(|e| format!("failed to read segment: {}", e))?;
+                chunks.push((segment.p_vaddr as u32, segment_data, segment.p_memsz as u32));
+                elf_regions.push(MemRegion {
+                    name: format!("segment {}", elf_regions.len()),
+                    addr_range: MemCtl::get_region_range(segment.p_vaddr, segment.p_memsz),
+                    permissions: Permissions {
+                        read: segment.p_flags & PF_R != 0,
+                        write: segment.p_flags & PF_W != 0,
+                        execute: segment.p_flags & PF_X != 0,
+                    },
+                });
+            }
+        } else if let Some(section_table) = elf_bytes.section_headers() {
+            for section_header in section_table
+                .iter()
+                .filter(|section_header| section_header.sh_flags & SHF_ALLOC as u64 != 0)
+            {
+                let section_data: &[u8] = if section_header.sh_type == SHT_NOBITS {
+                    &[]
+                } else {
+                    elf_bytes
+                        .section_data(&section_header)
+                        .map_err(|e| format!("failed to read section: {}", e))?
+                        .0
+                };
+                chunks.push((
+                    section_header.sh_addr as u32,
+                    section_data,
+                    section_header.sh_size as u32,
+                ));
+                elf_regions.push(MemRegion {
+                    name: format!("section {}", elf_regions.len()),
+                    addr_range: MemCtl::get_region_range(
+                        section_header.sh_addr,
+                        section_header.sh_size,
+                    ),
+                    permissions: Permissions {
+                        read: true,
+                        write: section_header.sh_flags & SHF_WRITE as u64 != 0,
+                        execute: section_header.sh_flags & SHF_EXECINSTR as u64 != 0,
+                    },
+                });
+            }
+        }
+
+        elf_regions.retain(|region| !region.addr_range.is_empty());
+        Ok(Self {
+            elf_bytes,
+            chunks,
+            elf_regions,
+        })
     }
 }
 
@@ -202,28 +773,44 @@ impl Debug for MemCtl {
 }
 
 pub trait MmioCtl: Send + Sync + 'static {
-    fn read(&mut self, addr: Word) -> Byte;
+    /// A read by the cpu, which may change the device like popping a FIFO does
+    fn read(&mut self, addr: Word) -> Byte {
+        self.peek(addr)
+    }
+    /// What `read` would return, without changing anything
+    fn peek(&self, addr: Word) -> Byte;
     fn write(&mut self, addr: Word, data: Byte);
     fn reset(&mut self);
 }
 
+/// Keyboard input FIFO
+///
+/// Writing 1 to `INTERRUPT_ENABLE_ADDR` raises mip.MEIP for as long as the FIFO is non-empty.
+#[derive(Clone)]
 pub struct KeyboardMmioCtl {
     buffer: VecDeque<u8>,
+    interrupt_enable: bool,
 }
 
 impl KeyboardMmioCtl {
     pub const STATUS_ADDR: u32 = 0x000A0000;
     pub const DATA_ADDR: u32 = 0x000A0001;
+    pub const INTERRUPT_ENABLE_ADDR: u32 = 0x000A0002;
 
     pub fn new() -> Self {
         Self {
             buffer: VecDeque::new(),
+            interrupt_enable: false,
         }
     }
 
     pub fn append_to_buffer(&mut self, data: u8) {
         self.buffer.push_back(data);
     }
+
+    pub fn is_interrupt_pending(&self) -> bool {
+        self.interrupt_enable && !self.buffer.is_empty()
+    }
 }
 
 impl Default for KeyboardMmioCtl {
@@ -234,6 +821,14 @@ impl Default for KeyboardMmioCtl {
 
 impl MmioCtl for KeyboardMmioCtl {
     fn read(&mut self, addr: Word) -> Byte {
+        let data = self.peek(addr);
+        if Into::<Option<u32>>::into(addr) == Some(Self::DATA_ADDR) {
+            self.buffer.pop_front();
+        }
+        data
+    }
+
+    fn peek(&self, addr: Word) -> Byte {
         if let Some(addr) = Into::<Option<u32>>::into(addr) {
             if addr == Self::STATUS_ADDR {
                 if !self.buffer.is_empty() {
@@ -242,11 +837,12 @@ impl MmioCtl for KeyboardMmioCtl {
                     Byte::from(0u8)
                 }
             } else if addr == Self::DATA_ADDR {
-                if !self.buffer.is_empty() {
-                    Byte::from(self.buffer.pop_front().unwrap())
-                } else {
-                    Byte::unknown()
+                match self.buffer.front() {
+                    Some(data) => Byte::from(*data),
+                    None => Byte::unknown(),
                 }
+            } else if addr == Self::INTERRUPT_ENABLE_ADDR {
+                Byte::from(self.interrupt_enable as u8)
             } else {
                 Byte::unknown()
             }
@@ -255,18 +851,326 @@ impl MmioCtl for KeyboardMmioCtl {
         }
     }
 
-    fn write(&mut self, _addr: Word, _data: Byte) {
-        // absolutely nothing happens
+    fn write(&mut self, addr: Word, data: Byte) {
+        if Into::<Option<u32>>::into(addr) == Some(Self::INTERRUPT_ENABLE_ADDR) {
+            self.interrupt_enable = data.is_something_nonzero();
+        }
     }
 
     fn reset(&mut self) {
         self.buffer.clear();
+        self.interrupt_enable = false;
+    }
+}
+
+impl Checkpoint for KeyboardMmioCtl {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.buffer.save(writer);
+        self.interrupt_enable.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            buffer: Checkpoint::load(reader)?,
+            interrupt_enable: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+/// A CLINT-style machine timer
+///
+/// `mtime` advances once per simulated cycle through `tick`,
+/// the timer interrupt is pending while `mtime >= mtimecmp`.
+#[derive(Clone)]
+pub struct ClintMmioCtl {
+    mtime: u64,
+    mtimecmp: u64,
+}
+
+impl ClintMmioCtl {
+    pub const BASE_ADDR: u32 = 0x0200_0000;
+    pub const MTIMECMP_ADDR: u32 = Self::BASE_ADDR + 0x4000;
+    pub const MTIME_ADDR: u32 = Self::BASE_ADDR + 0xBFF8;
+
+    pub fn new() -> Self {
+        Self {
+            mtime: 0,
+            mtimecmp: u64::MAX,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    pub fn get_mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn get_mtimecmp(&self) -> u64 {
+        self.mtimecmp
+    }
+
+    pub fn is_interrupt_pending(&self) -> bool {
+        self.mtime >= self.mtimecmp
+    }
+}
+
+impl Default for ClintMmioCtl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioCtl for ClintMmioCtl {
+    fn peek(&self, addr: Word) -> Byte {
+        match Into::<Option<u32>>::into(addr) {
+            Some(addr) if (Self::MTIMECMP_ADDR..Self::MTIMECMP_ADDR + 8).contains(&addr) => {
+                Byte::from((self.mtimecmp >> (8 * (addr - Self::MTIMECMP_ADDR))) as u8)
+            }
+            Some(addr) if (Self::MTIME_ADDR..Self::MTIME_ADDR + 8).contains(&addr) => {
+                Byte::from((self.mtime >> (8 * (addr - Self::MTIME_ADDR))) as u8)
+            }
+            _ => Byte::unknown(),
+        }
+    }
+
+    fn write(&mut self, addr: Word, data: Byte) {
+        if let (Some(addr), Some(data)) = (
+            Into::<Option<u32>>::into(addr),
+            Into::<Option<u8>>::into(data),
+        ) {
+            let set_byte = |reg: u64, shift: u32| -> u64 {
+                (reg & !(0xFFu64 << (8 * shift))) | ((data as u64) << (8 * shift))
+            };
+            if (Self::MTIMECMP_ADDR..Self::MTIMECMP_ADDR + 8).contains(&addr) {
+                self.mtimecmp = set_byte(self.mtimecmp, addr - Self::MTIMECMP_ADDR);
+            } else if (Self::MTIME_ADDR..Self::MTIME_ADDR + 8).contains(&addr) {
+                self.mtime = set_byte(self.mtime, addr - Self::MTIME_ADDR);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.mtime = 0;
+        self.mtimecmp = u64::MAX;
+    }
+}
+
+impl Checkpoint for ClintMmioCtl {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.mtime.save(writer);
+        self.mtimecmp.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            mtime: Checkpoint::load(reader)?,
+            mtimecmp: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+/// Host-target interface, the spike/riscv-tests way of talking to the host
+///
+/// `tohost` and `fromhost` are 64-bit, their addresses come from the ELF symbol table.
+/// A command is executed once the upper word of `tohost` is written,
+/// which is the last store of the `sw` pair that rv32 programs use.
+#[derive(Clone)]
+pub struct HtifMmioCtl {
+    tohost_addr: Option<u32>,
+    fromhost_addr: Option<u32>,
+    tohost: u64,
+    fromhost: u64,
+    exit_code: Option<u32>,
+    is_waiting_for_input: bool,
+    input_buffer: VecDeque<u8>,
+    output_buffer: Vec<u8>,
+    /// The latest `tohost` that was not a command this understands
+    unsupported_command: Option<u64>,
+}
+
+impl HtifMmioCtl {
+    pub const DEVICE_SYSCALL: u8 = 0;
+    pub const DEVICE_CONSOLE: u8 = 1;
+    pub const CONSOLE_GETCHAR: u8 = 0;
+    pub const CONSOLE_PUTCHAR: u8 = 1;
+
+    pub fn new() -> Self {
+        Self {
+            tohost_addr: None,
+            fromhost_addr: None,
+            tohost: 0,
+            fromhost: 0,
+            exit_code: None,
+            is_waiting_for_input: false,
+            input_buffer: VecDeque::new(),
+            output_buffer: Vec::new(),
+            unsupported_command: None,
+        }
+    }
+
+    pub fn set_addrs(&mut self, tohost_addr: Option<u32>, fromhost_addr: Option<u32>) {
+        self.tohost_addr = tohost_addr;
+        self.fromhost_addr = fromhost_addr;
+    }
+
+    pub fn get_tohost_addr(&self) -> Option<u32> {
+        self.tohost_addr
+    }
+
+    pub fn get_fromhost_addr(&self) -> Option<u32> {
+        self.fromhost_addr
+    }
+
+    /// The exit code the program reported through `tohost`, `None` if it has not exited
+    pub fn get_exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+
+    /// Whether a getchar is waiting for `append_to_buffer`
+    pub fn is_waiting_for_input(&self) -> bool {
+        self.is_waiting_for_input
+    }
+
+    pub fn append_to_buffer(&mut self, data: u8) {
+        self.input_buffer.push_back(data);
+        self.respond_to_getchar();
+    }
+
+    /// Drains everything the program has putchar-ed so far
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output_buffer)
+    }
+
+    /// The latest command that was ignored since the last call, for the frontends to report
+    pub fn take_unsupported_command(&mut self) -> Option<u64> {
+        self.unsupported_command.take()
+    }
+
+    fn respond(&mut self, device: u8, command: u8, payload: u64) {
+        self.fromhost = ((device as u64) << 56) | ((command as u64) << 48) | payload;
+    }
+
+    fn respond_to_getchar(&mut self) {
+        if self.is_waiting_for_input
+            && let Some(data) = self.input_buffer.pop_front()
+        {
+            self.is_waiting_for_input = false;
+            self.respond(
+                Self::DEVICE_CONSOLE,
+                Self::CONSOLE_GETCHAR,
+                0x100 | data as u64,
+            );
+        }
+    }
+
+    fn execute(&mut self) {
+        let device = (self.tohost >> 56) as u8;
+        let command = (self.tohost >> 48) as u8;
+        let payload = self.tohost & 0xFFFF_FFFF_FFFF;
+
+        match (device, command) {
+            (Self::DEVICE_SYSCALL, 0) if payload & 0x1 == 0x1 => {
+                self.exit_code = Some((payload >> 1) as u32);
+            }
+            (Self::DEVICE_CONSOLE, Self::CONSOLE_PUTCHAR) => {
+                self.output_buffer.push(payload as u8);
+                self.respond(device, command, 0x100 | (payload & 0xFF));
+            }
+            (Self::DEVICE_CONSOLE, Self::CONSOLE_GETCHAR) => {
+                self.is_waiting_for_input = true;
+                self.respond_to_getchar();
+            }
+            _ => self.unsupported_command = Some(self.tohost),
+        }
+        // the host acknowledges by clearing tohost
+        self.tohost = 0;
+    }
+}
+
+impl Default for HtifMmioCtl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioCtl for HtifMmioCtl {
+    fn peek(&self, addr: Word) -> Byte {
+        if let Some(addr) = Into::<Option<u32>>::into(addr) {
+            if let Some(tohost_addr) = self.tohost_addr
+                && (tohost_addr..tohost_addr + 8).contains(&addr)
+            {
+                return Byte::from((self.tohost >> (8 * (addr - tohost_addr))) as u8);
+            } else if let Some(fromhost_addr) = self.fromhost_addr
+                && (fromhost_addr..fromhost_addr + 8).contains(&addr)
+            {
+                return Byte::from((self.fromhost >> (8 * (addr - fromhost_addr))) as u8);
+            }
+        }
+        Byte::unknown()
+    }
+
+    fn write(&mut self, addr: Word, data: Byte) {
+        if let (Some(addr), Some(data)) = (
+            Into::<Option<u32>>::into(addr),
+            Into::<Option<u8>>::into(data),
+        ) {
+            let set_byte = |reg: u64, shift: u32| -> u64 {
+                (reg & !(0xFFu64 << (8 * shift))) | ((data as u64) << (8 * shift))
+            };
+            if let Some(tohost_addr) = self.tohost_addr
+                && (tohost_addr..tohost_addr + 8).contains(&addr)
+            {
+                self.tohost = set_byte(self.tohost, addr - tohost_addr);
+                if addr == tohost_addr + 7 {
+                    self.execute();
+                }
+            } else if let Some(fromhost_addr) = self.fromhost_addr
+                && (fromhost_addr..fromhost_addr + 8).contains(&addr)
+            {
+                self.fromhost = set_byte(self.fromhost, addr - fromhost_addr);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Checkpoint for HtifMmioCtl {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.tohost_addr.save(writer);
+        self.fromhost_addr.save(writer);
+        self.tohost.save(writer);
+        self.fromhost.save(writer);
+        self.exit_code.save(writer);
+        self.is_waiting_for_input.save(writer);
+        self.input_buffer.save(writer);
+        self.output_buffer.save(writer);
+        self.unsupported_command.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            tohost_addr: Checkpoint::load(reader)?,
+            fromhost_addr: Checkpoint::load(reader)?,
+            tohost: Checkpoint::load(reader)?,
+            fromhost: Checkpoint::load(reader)?,
+            exit_code: Checkpoint::load(reader)?,
+            is_waiting_for_input: Checkpoint::load(reader)?,
+            input_buffer: Checkpoint::load(reader)?,
+            output_buffer: Checkpoint::load(reader)?,
+            unsupported_command: Checkpoint::load(reader)?,
+        })
     }
 }
 
 const NUM_ROWS: usize = 25;
 const NUM_COLS: usize = 80;
 
+#[derive(Clone)]
 pub struct VgaMmioCtl {
     buffer: [u8; NUM_ROWS * NUM_COLS * 2],
 }
@@ -301,7 +1205,7 @@ impl Default for VgaMmioCtl {
 }
 
 impl MmioCtl for VgaMmioCtl {
-    fn read(&mut self, addr: Word) -> Byte {
+    fn peek(&self, addr: Word) -> Byte {
         if let Some(addr) = Into::<Option<u32>>::into(addr) {
             let buffer_idx = addr - Self::BASE_ADDR;
             if buffer_idx <= Self::NUM_BYTES as u32 {
@@ -337,3 +1241,90 @@ impl MmioCtl for VgaMmioCtl {
         self.buffer = buffer;
     }
 }
+
+impl Checkpoint for VgaMmioCtl {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        self.buffer.save(writer);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        Ok(Self {
+            buffer: Checkpoint::load(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOHOST: u32 = 0x1000;
+    const FROMHOST: u32 = 0x1040;
+
+    fn write_u64(htif: &mut HtifMmioCtl, addr: u32, value: u64) {
+        for i in 0..8 {
+            htif.write(Word::from(addr + i), Byte::from((value >> (8 * i)) as u8));
+        }
+    }
+
+    fn read_u64(htif: &mut HtifMmioCtl, addr: u32) -> u64 {
+        (0..8).fold(0, |value, i| {
+            let byte = Into::<Option<u8>>::into(htif.read(Word::from(addr + i))).unwrap();
+            value | ((byte as u64) << (8 * i))
+        })
+    }
+
+    fn new_htif() -> HtifMmioCtl {
+        let mut htif = HtifMmioCtl::new();
+        htif.set_addrs(Some(TOHOST), Some(FROMHOST));
+        htif
+    }
+
+    #[test]
+    fn test_htif_exit() {
+        let mut htif = new_htif();
+        write_u64(&mut htif, TOHOST, (42 << 1) | 1);
+        assert_eq!(htif.get_exit_code(), Some(42));
+        assert_eq!(read_u64(&mut htif, TOHOST), 0);
+    }
+
+    #[test]
+    fn test_htif_console() {
+        let mut htif = new_htif();
+        write_u64(&mut htif, TOHOST, 0x0101_0000_0000_0041);
+        assert_eq!(htif.take_output(), b"A");
+        assert_eq!(read_u64(&mut htif, FROMHOST), 0x0101_0000_0000_0141);
+        assert_eq!(htif.get_exit_code(), None);
+
+        write_u64(&mut htif, FROMHOST, 0);
+        write_u64(&mut htif, TOHOST, 0x0100_0000_0000_0000);
+        assert!(htif.is_waiting_for_input());
+        assert_eq!(read_u64(&mut htif, FROMHOST), 0);
+        htif.append_to_buffer(b'z');
+        assert!(!htif.is_waiting_for_input());
+        assert_eq!(read_u64(&mut htif, FROMHOST), 0x0100_0000_0000_017A);
+    }
+
+    #[test]
+    fn test_keyboard_peek() {
+        let mut keyboard = KeyboardMmioCtl::new();
+        keyboard.append_to_buffer(b'a');
+        let data_addr = Word::from(KeyboardMmioCtl::DATA_ADDR);
+        assert_eq!(keyboard.peek(data_addr), Byte::from(b'a'));
+        assert_eq!(keyboard.read(data_addr), Byte::from(b'a'));
+        assert_eq!(
+            keyboard.peek(Word::from(KeyboardMmioCtl::STATUS_ADDR)),
+            Byte::from(0u8)
+        );
+    }
+
+    #[test]
+    fn test_htif_unsupported_command() {
+        let mut htif = new_htif();
+        write_u64(&mut htif, TOHOST, 0x0102_0000_0000_0000);
+        assert_eq!(htif.take_unsupported_command(), Some(0x0102_0000_0000_0000));
+        assert_eq!(htif.take_unsupported_command(), None);
+        assert_eq!(read_u64(&mut htif, TOHOST), 0);
+        assert_eq!(htif.get_exit_code(), None);
+    }
+}
@@ -1,8 +1,11 @@
 pub mod alu;
+pub mod cache;
 pub mod cmp;
 pub mod control;
+pub mod csr_file;
 pub mod ir;
 pub mod mem_addr_mux;
 pub mod mem_ctl;
+pub mod muldiv;
 pub mod pc;
 pub mod regfile;
@@ -0,0 +1,206 @@
+use crate::backend::util::types::*;
+use crossbeam_channel::{Sender, unbounded};
+use rsim_core::component::Component;
+use rsim_core::rx::Rx;
+use rsim_core::sim_manager::SimManager;
+use rsim_core::tx::Tx;
+use rsim_core::types::ComponentId;
+use rsim_core::types::EventId;
+use rsim_core::types::Input;
+use rsim_core::types::Output;
+use rsim_macro::ComponentAttribute;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// A multi-cycle multiply/divide unit for the M extension
+///
+/// The operands are latched when `start` is seen,
+/// `resp` is raised for a single cycle after `latency` cycles with the result on `out`.
+#[ComponentAttribute({
+"port": {
+    "input": [
+        ["a", "Word"],
+        ["b", "Word"],
+        ["op", "Byte"],
+        ["start", "Byte"]
+    ],
+    "output": [
+        ["out", "Word"],
+        ["resp", "Byte"]
+    ],
+    "clock": true
+}
+})]
+pub struct MulDiv {
+    pub latency: u32,
+    result: Word,
+    cycles_remaining: u32,
+    is_busy: bool,
+    is_done: bool,
+}
+
+impl MulDiv {
+    pub const DEFAULT_LATENCY: u32 = 4;
+
+    pub fn new(
+        component_id: ComponentId,
+        sim_manager: Arc<SimManager>,
+        ack_sender: Sender<EventId>,
+        latency: u32,
+        a: Rx<Word>,
+        b: Rx<Word>,
+        op: Rx<Byte>,
+        start: Rx<Byte>,
+        out: Tx<Word>,
+        resp: Tx<Byte>,
+    ) -> Self {
+        let clock_channel = unbounded();
+
+        MulDiv {
+            latency,
+            result: Word::unknown(),
+            cycles_remaining: 0,
+            is_busy: false,
+            is_done: false,
+            component_id,
+            sim_manager,
+            ack_sender,
+            clock_sender: clock_channel.0,
+            clock_receiver: clock_channel.1,
+            a,
+            b,
+            op,
+            start,
+            out,
+            resp,
+        }
+    }
+
+    fn init_impl(&mut self) {}
+
+    fn reset_impl(&mut self) {
+        self.result = Word::unknown();
+        self.cycles_remaining = 0;
+        self.is_busy = false;
+        self.is_done = false;
+    }
+
+    fn poll_impl(&mut self) {}
+
+    fn on_clock(&mut self) {
+        if self.is_done {
+            self.resp.send(Byte::from(0u8), 0);
+            self.is_done = false;
+        } else if self.is_busy {
+            self.cycles_remaining = self.cycles_remaining.saturating_sub(1);
+            if self.cycles_remaining == 0 {
+                self.out.send(self.result, 0);
+                self.resp.send(Byte::from(1u8), 0);
+                self.is_busy = false;
+                self.is_done = true;
+            }
+        } else if self.start.get_value().is_something_nonzero() {
+            self.result = compute(self.a.get_value(), self.b.get_value(), self.op.get_value());
+            self.cycles_remaining = self.latency.max(1);
+            self.is_busy = true;
+        }
+    }
+
+    fn on_comb(&mut self) {}
+}
+
+/// Computes the M extension operation `op` (the instruction's funct3) on `a` and `b`
+pub fn compute(a: Word, b: Word, op: Byte) -> Word {
+    let (Some(a), Some(b), Some(op)) = (
+        Into::<Option<u32>>::into(a),
+        Into::<Option<u32>>::into(b),
+        Into::<Option<u8>>::into(op),
+    ) else {
+        return Word::unknown();
+    };
+
+    let out = match op {
+        funct3::muldiv::MUL => a.wrapping_mul(b),
+        funct3::muldiv::MULH => (((a as i32 as i64) * (b as i32 as i64)) >> 32) as u32,
+        funct3::muldiv::MULHSU => (((a as i32 as i64) * (b as i64)) >> 32) as u32,
+        funct3::muldiv::MULHU => (((a as u64) * (b as u64)) >> 32) as u32,
+        funct3::muldiv::DIV => {
+            if b == 0 {
+                u32::MAX
+            } else {
+                (a as i32).wrapping_div(b as i32) as u32
+            }
+        }
+        funct3::muldiv::DIVU => a.checked_div(b).unwrap_or(u32::MAX),
+        funct3::muldiv::REM => {
+            if b == 0 {
+                a
+            } else {
+                (a as i32).wrapping_rem(b as i32) as u32
+            }
+        }
+        funct3::muldiv::REMU => a.checked_rem(b).unwrap_or(a),
+        _ => return Word::unknown(),
+    };
+
+    Word::from(out)
+}
+
+impl Debug for MulDiv {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MulDiv: {{a: {:?}, b: {:?}, op: {:?}, start: {:?}}}",
+            self.a.get_value(),
+            self.b.get_value(),
+            self.op.get_value(),
+            self.start.get_value()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use funct3::muldiv::*;
+
+    fn run(a: u32, b: u32, op: u8) -> u32 {
+        Into::<Option<u32>>::into(compute(Word::from(a), Word::from(b), Byte::from(op))).unwrap()
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(run(7, 6, MUL), 42);
+        assert_eq!(run(-3i32 as u32, 5, MUL), -15i32 as u32);
+        assert_eq!(run(-1i32 as u32, -1i32 as u32, MULH), 0);
+        assert_eq!(run(-1i32 as u32, -1i32 as u32, MULHU), 0xFFFFFFFE);
+        assert_eq!(run(-1i32 as u32, -1i32 as u32, MULHSU), 0xFFFFFFFF);
+        assert_eq!(run(0x80000000, 0x80000000, MULH), 0x40000000);
+    }
+
+    #[test]
+    fn test_div_rem() {
+        assert_eq!(run(-7i32 as u32, 2, DIV), -3i32 as u32);
+        assert_eq!(run(-7i32 as u32, 2, REM), -1i32 as u32);
+        assert_eq!(run(7, 2, DIVU), 3);
+        assert_eq!(run(7, 2, REMU), 1);
+    }
+
+    #[test]
+    fn test_div_edge_cases() {
+        assert_eq!(run(5, 0, DIV), u32::MAX);
+        assert_eq!(run(5, 0, DIVU), u32::MAX);
+        assert_eq!(run(5, 0, REM), 5);
+        assert_eq!(run(5, 0, REMU), 5);
+        assert_eq!(run(0x80000000, -1i32 as u32, DIV), 0x80000000);
+        assert_eq!(run(0x80000000, -1i32 as u32, REM), 0);
+    }
+
+    #[test]
+    fn test_unknown() {
+        assert_eq!(
+            compute(Word::unknown(), Word::from(1u32), Byte::from(0u8)),
+            Word::unknown()
+        );
+    }
+}
@@ -87,6 +87,8 @@ impl Debug for Pc {
     "input": [
         ["pc", "Word"],
         ["alu_out", "Word"],
+        ["mtvec", "Word"],
+        ["mepc", "Word"],
         ["sel", "Byte"]
     ],
     "output": [
@@ -103,6 +105,8 @@ impl PcMux {
         ack_sender: Sender<EventId>,
         pc: Rx<Word>,
         alu_out: Rx<Word>,
+        mtvec: Rx<Word>,
+        mepc: Rx<Word>,
         sel: Rx<Byte>,
         out: Tx<Word>,
     ) -> Self {
@@ -112,6 +116,8 @@ impl PcMux {
             ack_sender,
             pc,
             alu_out,
+            mtvec,
+            mepc,
             sel,
             out,
         }
@@ -128,6 +134,8 @@ impl PcMux {
             Some(mux_sel::pc::PC_PLUS4) => self.pc.get_value() + Word::from(4u32),
             Some(mux_sel::pc::ALU_OUT) => self.alu_out.get_value(),
             Some(mux_sel::pc::ALU_MOD2) => self.alu_out.get_value() & Word::from(0xFFFFFFFEu32),
+            Some(mux_sel::pc::MTVEC) => self.mtvec.get_value(),
+            Some(mux_sel::pc::MEPC) => self.mepc.get_value(),
             _ => Word::unknown(),
         };
 
@@ -20,7 +20,7 @@ pub struct Registers {
 }
 
 impl Registers {
-    fn read(&self, index: Bytes<1>) -> Word {
+    pub fn read(&self, index: Bytes<1>) -> Word {
         Into::<Option<u8>>::into(index)
             .map(|idx| {
                 if idx != 0 {
@@ -32,7 +32,7 @@ impl Registers {
             .unwrap_or(Word::unknown())
     }
 
-    fn write(&mut self, index: Bytes<1>, value: Word) {
+    pub fn write(&mut self, index: Bytes<1>, value: Word) {
         if let Some(idx) = Into::<Option<u8>>::into(index) {
             if idx != 0 {
                 self.data[idx as usize] = value
@@ -164,6 +164,8 @@ impl Debug for RegFile {
         ["mem_addr_mux_out", "Word"],
         ["mem_rdata", "Word"],
         ["pc", "Word"],
+        ["muldiv_out", "Word"],
+        ["csr_out", "Word"],
         ["sel", "Byte"]
     ],
     "output": [
@@ -184,6 +186,8 @@ impl RegFileMux {
         mem_addr_mux_out: Rx<Word>,
         mem_rdata: Rx<Word>,
         pc: Rx<Word>,
+        muldiv_out: Rx<Word>,
+        csr_out: Rx<Word>,
         sel: Rx<Byte>,
         out: Tx<Word>,
     ) -> Self {
@@ -197,6 +201,8 @@ impl RegFileMux {
             mem_addr_mux_out,
             mem_rdata,
             pc,
+            muldiv_out,
+            csr_out,
             sel,
             out,
         }
@@ -251,11 +257,17 @@ impl RegFileMux {
             }
             Some(mux_sel::regfile::LHU) => self.mem_rdata.get_value()[mem_rdata_idx]
                 .map(|lsb| {
-                    self.mem_rdata.get_value()[mem_rdata_idx + 1]
+                    self.mem_rdata
+                        .get_value()
+                        .data
+                        .get(mem_rdata_idx + 1)
+                        .unwrap_or(&None)
                         .map(|msb| Word::from((((msb as u16) << 8) | lsb as u16) as u32))
                         .unwrap_or(Word::unknown())
                 })
                 .unwrap_or(Word::unknown()),
+            Some(mux_sel::regfile::MULDIV_OUT) => self.muldiv_out.get_value(),
+            Some(mux_sel::regfile::CSR_OUT) => self.csr_out.get_value(),
             _ => Word::unknown(),
         };
 
@@ -267,13 +279,15 @@ impl Debug for RegFileMux {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "RegFileMux: {{alu_out: {:?}, cmp_out: {:?}, u_imm: {:?}, mem_addr_mux_out: {:?} mem_rdata: {:?} pc: {:?} sel: {:?}}}",
+            "RegFileMux: {{alu_out: {:?}, cmp_out: {:?}, u_imm: {:?}, mem_addr_mux_out: {:?} mem_rdata: {:?} pc: {:?} muldiv_out: {:?} csr_out: {:?} sel: {:?}}}",
             self.alu_out.get_value(),
             self.cmp_out.get_value(),
             self.u_imm.get_value(),
             self.mem_addr_mux_out.get_value(),
             self.mem_rdata.get_value(),
             self.pc.get_value(),
+            self.muldiv_out.get_value(),
+            self.csr_out.get_value(),
             self.sel.get_value()
         )
     }
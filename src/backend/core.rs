@@ -1,21 +1,32 @@
+use crate::backend::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
 use crate::backend::component::alu::Alu;
 use crate::backend::component::alu::AluMux1;
 use crate::backend::component::alu::AluMux2;
+use crate::backend::component::cache::{Cache, CacheConfig, CacheCtl, CacheType};
 use crate::backend::component::cmp::Cmp;
 use crate::backend::component::cmp::CmpMux;
 use crate::backend::component::control::Control;
+use crate::backend::component::csr_file::CsrFile;
 use crate::backend::component::ir::IR;
 use crate::backend::component::mem_addr_mux::MemAddrMux;
-use crate::backend::component::mem_ctl::{KeyboardMmioCtl, MemCtl, MmioCtl, VgaMmioCtl};
+use crate::backend::component::mem_ctl::{
+    AccessFault, ClintMmioCtl, Elf, HtifMmioCtl, KeyboardMmioCtl, MemCtl, MemMapType, MmioCtl,
+    VgaMmioCtl, WatchpointHit,
+};
+use crate::backend::component::muldiv::MulDiv;
 use crate::backend::component::pc::Pc;
 use crate::backend::component::pc::PcMux;
 use crate::backend::component::regfile::RegFile;
 use crate::backend::component::regfile::RegFileMux;
-use crate::backend::core::StatsType::InstructionsRan;
-use crate::backend::util::byte::Bytes;
+use crate::backend::core::StatsType::{CyclesRan, InstructionsRan};
+use crate::backend::cosim::Commit;
+use crate::backend::functional::FunctionalCore;
+use crate::backend::history::{History, Snapshot};
+use crate::backend::loader::{ImageFormat, Program, load_binary, load_ihex, load_readmemh};
 use crate::backend::util::types::Byte;
 use crate::backend::util::types::States;
 use crate::backend::util::types::Word;
+use crate::backend::vcd::{VcdProbe, VcdSignals, VcdWriter};
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use rsim_core::component::Component;
 use rsim_core::sim_dispatcher::SimDispatcher;
@@ -24,7 +35,8 @@ use rsim_core::tx::Tx;
 use rsim_core::types::EventId;
 use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 #[cfg(not(target_arch = "wasm32"))]
 use std::thread;
@@ -41,6 +53,7 @@ use strum::IntoEnumIterator;
 #[derive(EnumIter, Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum StatsType {
     InstructionsRan,
+    CyclesRan,
 }
 
 #[derive(EnumIter, Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -50,9 +63,11 @@ pub enum ComponentType {
     AluMux2,
     Cmp,
     CmpMux,
+    CsrFile,
     Ir,
     MemAddrMux,
     MemCtl,
+    MulDiv,
     Pc,
     PcMux,
     RegFile,
@@ -67,6 +82,8 @@ pub struct Core {
     pub sim_dispatcher_handlers: Vec<JoinHandle<()>>,
     // todo, clean this mess up, with enum?
     pub mem_ctl: Arc<Mutex<MemCtl>>,
+    pub icache: Arc<Mutex<CacheCtl>>,
+    pub dcache: Arc<Mutex<CacheCtl>>,
     pub control: Arc<Mutex<Control>>,
     pub ir: Arc<Mutex<IR>>,
     pub pc_mux: Arc<Mutex<PcMux>>,
@@ -79,140 +96,794 @@ pub struct Core {
     pub cmp: Arc<Mutex<Cmp>>,
     pub regfile_mux: Arc<Mutex<RegFileMux>>,
     pub regfile: Arc<Mutex<RegFile>>,
+    pub muldiv: Arc<Mutex<MulDiv>>,
+    pub csr_file: Arc<Mutex<CsrFile>>,
     pub keyboard_mmio_ctl: Arc<Mutex<KeyboardMmioCtl>>,
     pub vga_mmio_ctl: Arc<Mutex<VgaMmioCtl>>,
+    pub clint_mmio_ctl: Arc<Mutex<ClintMmioCtl>>,
+    pub htif_mmio_ctl: Arc<Mutex<HtifMmioCtl>>,
     commit_file: Mutex<Option<File>>,
+    vcd_writer: Mutex<Option<VcdWriter<BufWriter<File>>>>,
     stats: Mutex<HashMap<StatsType, u128>>,
+    history: Mutex<History>,
+    /// Set by `pause`, checked by the loops that run until something happens
+    is_pause_requested: AtomicBool,
 }
 
 impl Core {
+    /// Instructions the functional model runs between calls to the hook
+    const FAST_FORWARD_BATCH_SIZE: u128 = 10000;
+
     fn log_commits(&self) {
         if let Some(commit_file) = self.commit_file.lock().unwrap().as_mut() {
-            // locking is fine here, we are not advancing the sim
-            let control = self.control.lock().unwrap();
-            let pc = self.pc.lock().unwrap();
-            let ir = self.ir.lock().unwrap();
-            let regfile = self.regfile.lock().unwrap();
-            let mem_ctl = self.mem_ctl.lock().unwrap();
-
-            if !ir.can_end()
-                && (control.state == States::Fetch
-                    || control.state == States::Decode
-                    || ((control.state == States::Load || control.state == States::Store)
-                        && !mem_ctl.cpu_resp.get_value().is_something_nonzero()))
-            {
+            let Some(commit) = self.get_commit() else {
                 return;
+            };
+            let line = format!("{}\n", commit);
+            commit_file.write_all(line.as_bytes()).unwrap();
+
+            let instructions_ran = self.stats.lock().unwrap()[&InstructionsRan];
+            if instructions_ran % 1000 == 0 {
+                println!("commit #{}", instructions_ran);
+                print!("{}", line);
             }
+        }
+    }
 
-            let mut line = String::new();
+    fn dump_vcd(&self) {
+        if let Some(vcd_writer) = self.vcd_writer.lock().unwrap().as_mut() {
+            vcd_writer
+                .dump(self.get_cycles_ran(), self.get_vcd_modules())
+                .unwrap();
+        }
+    }
 
-            line.push_str(&format!(
-                "core   0: 3 0x{:x} (0x{:x})",
-                pc.data_inner, ir.data_inner
-            ));
+    /// The ports of every component, grouped by component
+    fn get_vcd_modules(&self) -> Vec<(&'static str, VcdSignals)> {
+        vec![
+            ("mem_ctl", self.mem_ctl.lock().unwrap().get_vcd_signals()),
+            ("icache", self.icache.lock().unwrap().get_vcd_signals()),
+            ("dcache", self.dcache.lock().unwrap().get_vcd_signals()),
+            ("control", self.control.lock().unwrap().get_vcd_signals()),
+            ("ir", self.ir.lock().unwrap().get_vcd_signals()),
+            ("pc_mux", self.pc_mux.lock().unwrap().get_vcd_signals()),
+            ("pc", self.pc.lock().unwrap().get_vcd_signals()),
+            (
+                "mem_addr_mux",
+                self.mem_addr_mux.lock().unwrap().get_vcd_signals(),
+            ),
+            ("alu_mux1", self.alu_mux1.lock().unwrap().get_vcd_signals()),
+            ("alu_mux2", self.alu_mux2.lock().unwrap().get_vcd_signals()),
+            ("alu", self.alu.lock().unwrap().get_vcd_signals()),
+            ("cmp_mux", self.cmp_mux.lock().unwrap().get_vcd_signals()),
+            ("cmp", self.cmp.lock().unwrap().get_vcd_signals()),
+            (
+                "regfile_mux",
+                self.regfile_mux.lock().unwrap().get_vcd_signals(),
+            ),
+            ("regfile", self.regfile.lock().unwrap().get_vcd_signals()),
+            ("muldiv", self.muldiv.lock().unwrap().get_vcd_signals()),
+            ("csr_file", self.csr_file.lock().unwrap().get_vcd_signals()),
+        ]
+    }
 
-            if regfile.rd_wr.get_value().is_something_nonzero()
-                && ir.get_rd_idx().is_something_nonzero()
-            {
-                let raw_rd: u8 = Into::<Option<u8>>::into(ir.get_rd_idx()).unwrap();
-                if raw_rd < 10 {
-                    line.push_str(&format!(" x{}  ", raw_rd))
-                } else {
-                    line.push_str(&format!(" x{} ", raw_rd))
-                }
-                line.push_str(&format!("0x{:x}", regfile.rd_data.get_value()));
-            }
+    /// Runs `f` with the commit log and the waveform dump switched off, for cycles they already have
+    fn without_logging(&self, f: impl FnOnce()) {
+        let commit_file = self.commit_file.lock().unwrap().take();
+        let vcd_writer = self.vcd_writer.lock().unwrap().take();
+        f();
+        *self.commit_file.lock().unwrap() = commit_file;
+        *self.vcd_writer.lock().unwrap() = vcd_writer;
+    }
 
-            if control.state == States::Load
-                && mem_ctl.cpu_rmask.get_value().is_something_nonzero()
-                && control.mem_resp.get_value().is_something_nonzero()
-            {
-                line.push_str(&format!(" mem 0x{:x}", mem_ctl.cpu_addr.get_value()));
-            }
+    /// Writes out what the waveform dump has buffered
+    pub fn flush_vcd(&self) {
+        if let Some(vcd_writer) = self.vcd_writer.lock().unwrap().as_mut() {
+            vcd_writer.flush().unwrap();
+        }
+    }
 
-            if control.state == States::Store
-                && mem_ctl.cpu_wmask.get_value().is_something_nonzero()
-                && control.mem_resp.get_value().is_something_nonzero()
-            {
-                let wmask = Into::<Option<u8>>::into(mem_ctl.cpu_wmask.get_value()).unwrap();
-                let mut byte_count = 0;
-                for i in 0..4u8 {
-                    if (wmask >> i) & 0x1 == 0x1 {
-                        byte_count += 1;
-                    }
-                }
+    /// The instruction retiring in the current cycle, if any, as it is written to the commit log
+    fn get_commit(&self) -> Option<Commit> {
+        // locking is fine here, we are not advancing the sim
+        let control = self.control.lock().unwrap();
+        let pc = self.pc.lock().unwrap();
+        let ir = self.ir.lock().unwrap();
+        let regfile = self.regfile.lock().unwrap();
+        // what control sees, the caches may still be busy with memory
+        let icache = self.icache.lock().unwrap();
+        let muldiv = self.muldiv.lock().unwrap();
 
-                line.push_str(&format!(" mem 0x{:x}", mem_ctl.cpu_addr.get_value()));
-                if let Some(wdata) = Into::<Option<u32>>::into(mem_ctl.cpu_wdata.get_value()) {
-                    let wdata_str = match byte_count {
-                        1 => {
-                            format!("{:x}", Byte::from(wdata as u8))
-                        }
-                        2 => {
-                            format!("{:x}", Bytes::<2>::from(wdata as u16))
-                        }
-                        4 => {
-                            format!("{:x}", Word::from(wdata))
-                        }
-                        _ => "".to_string(),
-                    };
-                    line.push_str(&format!(" 0x{}", wdata_str));
-                }
-            }
+        if !ir.can_end()
+            && (control.trap.get_value().is_something_nonzero()
+                || control.state == States::Fetch
+                || control.state == States::Decode
+                || ((control.state == States::Load || control.state == States::Store)
+                    && !icache.cpu_resp.get_value().is_something_nonzero())
+                || (control.state == States::MulDiv
+                    && !muldiv.resp.get_value().is_something_nonzero()))
+        {
+            return None;
+        }
 
-            line.push('\n');
-            commit_file.write_all(line.as_bytes()).unwrap();
+        let mut commit = Commit {
+            pc: pc.data_inner,
+            inst: ir.data_inner,
+            rd_write: None,
+            mem_addr: None,
+            mem_wdata: None,
+        };
 
-            let instructions_ran = self.stats.lock().unwrap()[&InstructionsRan];
-            if instructions_ran % 1000 == 0 {
-                println!("commit #{}", instructions_ran);
-                print!("{}", line);
+        if regfile.rd_wr.get_value().is_something_nonzero()
+            && ir.get_rd_idx().is_something_nonzero()
+        {
+            let raw_rd: u8 = Into::<Option<u8>>::into(ir.get_rd_idx()).unwrap();
+            commit.rd_write = Some((raw_rd, regfile.rd_data.get_value()));
+        }
+
+        if control.state == States::Load
+            && icache.cpu_rmask.get_value().is_something_nonzero()
+            && control.mem_resp.get_value().is_something_nonzero()
+        {
+            commit.mem_addr = Some(icache.cpu_addr.get_value());
+        }
+
+        if control.state == States::Store
+            && icache.cpu_wmask.get_value().is_something_nonzero()
+            && control.mem_resp.get_value().is_something_nonzero()
+        {
+            let wmask = Into::<Option<u8>>::into(icache.cpu_wmask.get_value()).unwrap();
+            let byte_count = wmask.count_ones() as usize;
+
+            commit.mem_addr = Some(icache.cpu_addr.get_value());
+            let wdata = icache.cpu_wdata.get_value();
+            if !wdata.has_unknown() {
+                commit.mem_wdata = Some(wdata.data[..byte_count.min(4)].to_vec());
             }
         }
+
+        Some(commit)
     }
 
     pub fn run_cycle<F: Fn() + Copy>(&self, hook: Option<F>) {
+        if self.history.lock().unwrap().is_empty() {
+            self.take_snapshot(false);
+        }
+        let old_pc = self.pc.lock().unwrap().data_inner;
+
         self.sim_manager.run_cycle().unwrap();
         self.sim_manager.run_cycle_end().unwrap();
+        self.clint_mmio_ctl.lock().unwrap().tick();
+        *self.stats.lock().unwrap().get_mut(&CyclesRan).unwrap() += 1;
         self.log_commits();
+        self.dump_vcd();
+
+        if old_pc != self.pc.lock().unwrap().data_inner {
+            self.on_instruction_boundary();
+        }
         if let Some(ref hook) = hook {
             hook();
         }
     }
 
+    /// Runs until an instruction retires and returns it, or None if the program ends first
+    pub fn run_commit<F: Fn() + Copy>(&self, hook: Option<F>) -> Option<Commit> {
+        while !self.has_ended() {
+            self.run_cycle(hook);
+            if let Some(commit) = self.get_commit() {
+                return Some(commit);
+            }
+        }
+        None
+    }
+
     pub fn run_instruction<F: Fn() + Copy>(&self, hook: Option<F>) {
         let old_pc = self.pc.lock().unwrap().data_inner;
+        self.mem_ctl.lock().unwrap().watchpoint_hit = None;
 
-        while !self.ir.lock().unwrap().can_end() && old_pc == self.pc.lock().unwrap().data_inner {
+        while !self.has_ended() && old_pc == self.pc.lock().unwrap().data_inner {
             self.run_cycle(hook)
         }
+    }
 
-        let mut locked_stats = self.stats.lock().unwrap();
-        let instructions_ran = locked_stats[&InstructionsRan];
-        locked_stats.insert(InstructionsRan, instructions_ran + 1);
+    /// An instruction has just retired and the next one is being fetched
+    fn on_instruction_boundary(&self) {
+        *self
+            .stats
+            .lock()
+            .unwrap()
+            .get_mut(&InstructionsRan)
+            .unwrap() += 1;
+
+        let cycles_ran = self.get_cycles_ran();
+        let is_snapshot_due = self.history.lock().unwrap().is_snapshot_due(cycles_ran);
+        if is_snapshot_due {
+            self.take_snapshot(true);
+        } else {
+            self.history
+                .lock()
+                .unwrap()
+                .push_instruction_boundary(cycles_ran);
+        }
+    }
+
+    fn take_snapshot(&self, is_settled: bool) {
+        let snapshot = self.capture_snapshot(is_settled);
+        let mem_undo_log = std::mem::take(&mut self.mem_ctl.lock().unwrap().mem_undo_log);
+        self.history
+            .lock()
+            .unwrap()
+            .push_snapshot(snapshot, mem_undo_log);
+    }
+
+    fn capture_snapshot(&self, is_settled: bool) -> Snapshot {
+        let csr_file = self.csr_file.lock().unwrap();
+        // only the lines are kept, no request is pending between two instructions,
+        // nor right after a reset
+        let (icache, dcache) = self.get_caches();
+        Snapshot {
+            cycles_ran: self.get_cycles_ran(),
+            instructions_ran: self.get_instructions_ran(),
+            is_settled,
+            state: self.control.lock().unwrap().state,
+            pc: self.pc.lock().unwrap().data_inner,
+            ir: self.ir.lock().unwrap().data_inner,
+            registers: self.regfile.lock().unwrap().registers,
+            mstatus: csr_file.mstatus,
+            mie: csr_file.mie,
+            mip: csr_file.mip,
+            mtvec: csr_file.mtvec,
+            mscratch: csr_file.mscratch,
+            mepc: csr_file.mepc,
+            mcause: csr_file.mcause,
+            mtval: csr_file.mtval,
+            mcycle: csr_file.mcycle,
+            minstret: csr_file.minstret,
+            keyboard_mmio_ctl: self.keyboard_mmio_ctl.lock().unwrap().clone(),
+            vga_mmio_ctl: self.vga_mmio_ctl.lock().unwrap().clone(),
+            clint_mmio_ctl: self.clint_mmio_ctl.lock().unwrap().clone(),
+            htif_mmio_ctl: self.htif_mmio_ctl.lock().unwrap().clone(),
+            icache,
+            dcache,
+            mem_undo_log: Default::default(),
+        }
+    }
+
+    /// Drops the history and starts it over from `snapshot`, which is then restored
+    ///
+    /// For when the state did not come from running the datapath, memory is kept as it is
+    fn restart_history_from(&self, snapshot: &Snapshot) {
+        self.mem_ctl.lock().unwrap().mem_undo_log.clear();
+        {
+            let mut history = self.history.lock().unwrap();
+            history.clear();
+            history.push_snapshot(snapshot.clone(), Default::default());
+        }
+        self.restore_snapshot(snapshot);
+    }
+
+    fn restore_snapshot(&self, snapshot: &Snapshot) {
+        // resetting clears whatever is left on the ports, but memory has to survive it
+        let (backend_mem, label, elf_regions) = {
+            let mut mem_ctl = self.mem_ctl.lock().unwrap();
+            (
+                std::mem::take(&mut mem_ctl.backend_mem),
+                std::mem::take(&mut mem_ctl.label),
+                std::mem::take(&mut mem_ctl.elf_regions),
+            )
+        };
+        self.reset_components();
+        {
+            let mut mem_ctl = self.mem_ctl.lock().unwrap();
+            mem_ctl.backend_mem = backend_mem;
+            mem_ctl.label = label;
+            mem_ctl.elf_regions = elf_regions;
+        }
+        self.icache.lock().unwrap().cache = snapshot.icache.clone();
+        self.dcache.lock().unwrap().cache = snapshot.dcache.clone();
+        *self.keyboard_mmio_ctl.lock().unwrap() = snapshot.keyboard_mmio_ctl.clone();
+        *self.vga_mmio_ctl.lock().unwrap() = snapshot.vga_mmio_ctl.clone();
+        *self.htif_mmio_ctl.lock().unwrap() = snapshot.htif_mmio_ctl.clone();
+        // before settling too, since mip is latched from it
+        *self.clint_mmio_ctl.lock().unwrap() = snapshot.clint_mmio_ctl.clone();
+        self.install_mmio_ctls();
+
+        self.control.lock().unwrap().state = snapshot.state;
+        self.pc.lock().unwrap().data_inner = snapshot.pc;
+        self.ir.lock().unwrap().data_inner = snapshot.ir;
+        self.regfile.lock().unwrap().registers = snapshot.registers;
+        {
+            let mut csr_file = self.csr_file.lock().unwrap();
+            csr_file.mstatus = snapshot.mstatus;
+            csr_file.mie = snapshot.mie;
+            csr_file.mip = snapshot.mip;
+            csr_file.mtvec = snapshot.mtvec;
+            csr_file.mscratch = snapshot.mscratch;
+            csr_file.mepc = snapshot.mepc;
+            csr_file.mcause = snapshot.mcause;
+            csr_file.mtval = snapshot.mtval;
+        }
+
+        if snapshot.is_settled {
+            // coming out of reset it takes a cycle to issue the fetch that was already in flight,
+            // the counters are set afterwards so that the cycle does not count, nor is it logged
+            self.without_logging(|| self.run_cycle(None::<fn()>));
+        }
+        // mtime is a counter as well
+        *self.clint_mmio_ctl.lock().unwrap() = snapshot.clint_mmio_ctl.clone();
+        self.csr_file.lock().unwrap().mcycle = snapshot.mcycle;
+        self.csr_file.lock().unwrap().minstret = snapshot.minstret;
+        let mut stats = self.stats.lock().unwrap();
+        stats.insert(CyclesRan, snapshot.cycles_ran);
+        stats.insert(InstructionsRan, snapshot.instructions_ran);
+    }
+
+    /// Goes back to `cycles_ran` by restoring the latest snapshot before it and running forward
+    ///
+    /// Returns false if that is further back than the history goes
+    fn rewind_to(&self, cycles_ran: u128) -> bool {
+        let snapshot = {
+            let mut mem_ctl = self.mem_ctl.lock().unwrap();
+            let mem_ctl = &mut *mem_ctl;
+            self.history.lock().unwrap().rewind(
+                cycles_ran,
+                &mut mem_ctl.mem_undo_log,
+                &mut mem_ctl.backend_mem,
+            )
+        };
+        let Some(snapshot) = snapshot else {
+            return false;
+        };
+
+        self.restore_snapshot(&snapshot);
+        let inputs = self
+            .history
+            .lock()
+            .unwrap()
+            .get_inputs_since(snapshot.cycles_ran);
+        self.replay_to(cycles_ran, &inputs);
+        // the console has already shown what was printed while running forward again
+        let mut htif_mmio_ctl = self.htif_mmio_ctl.lock().unwrap();
+        htif_mmio_ctl.take_output();
+        htif_mmio_ctl.take_unsupported_command();
+        true
+    }
+
+    /// Runs forward again to `cycles_ran`, handing over `inputs` at the cycles they arrived at
+    fn replay_to(&self, cycles_ran: u128, inputs: &[(u128, u8)]) {
+        self.without_logging(|| {
+            let mut inputs = inputs.iter().peekable();
+            loop {
+                while let Some((_, input)) =
+                    inputs.next_if(|(cycle, _)| *cycle <= self.get_cycles_ran())
+                {
+                    self.deliver_input(*input);
+                }
+                if self.get_cycles_ran() >= cycles_ran {
+                    break;
+                }
+                self.run_cycle(None::<fn()>);
+            }
+        });
+    }
+
+    /// Hands a byte typed on the console to the program
+    ///
+    /// It is kept in the history so that it arrives again when stepping back runs forward over it
+    pub fn send_input(&self, input: u8) {
+        if self.history.lock().unwrap().is_empty() {
+            self.take_snapshot(false);
+        }
+        self.history
+            .lock()
+            .unwrap()
+            .push_input(self.get_cycles_ran(), input);
+        self.deliver_input(input);
+    }
+
+    fn deliver_input(&self, input: u8) {
+        // a pending htif getchar takes priority over the keyboard
+        let mut htif_mmio_ctl = self.htif_mmio_ctl.lock().unwrap();
+        if htif_mmio_ctl.is_waiting_for_input() {
+            htif_mmio_ctl.append_to_buffer(input);
+        } else {
+            self.keyboard_mmio_ctl
+                .lock()
+                .unwrap()
+                .append_to_buffer(input);
+        }
+    }
+
+    pub fn step_back_cycle(&self) -> bool {
+        let cycles_ran = self.get_cycles_ran();
+        cycles_ran > 0 && self.rewind_to(cycles_ran - 1)
+    }
+
+    /// Goes back to the start of the current instruction, or the previous one if at a boundary
+    pub fn step_back_instruction(&self) -> bool {
+        let boundary = self
+            .history
+            .lock()
+            .unwrap()
+            .get_previous_instruction_boundary(self.get_cycles_ran());
+        boundary.is_some_and(|cycles_ran| self.rewind_to(cycles_ran))
+    }
+
+    /// Serializes the state at the current cycle
+    ///
+    /// What is saved is the latest snapshot and the memory as of it with its map, the current cycle
+    /// and the console input since the snapshot. Loading runs forward from the snapshot again,
+    /// which brings back the values on the ports.
+    pub fn save_checkpoint(&self) -> Vec<u8> {
+        if self.history.lock().unwrap().is_empty() {
+            self.take_snapshot(false);
+        }
+
+        let mut writer = CheckpointWriter::new();
+        self.get_cycles_ran().save(&mut writer);
+        {
+            let mem_ctl = self.mem_ctl.lock().unwrap();
+            let history = self.history.lock().unwrap();
+            let snapshot = history.get_latest_snapshot().unwrap();
+            snapshot.save(&mut writer);
+            history
+                .get_inputs_since(snapshot.cycles_ran)
+                .save(&mut writer);
+
+            let mut backend_mem = mem_ctl.backend_mem.clone();
+            History::undo(mem_ctl.mem_undo_log.clone(), &mut backend_mem);
+            backend_mem.save(&mut writer);
+            mem_ctl.label.save(&mut writer);
+            mem_ctl.mem_map_type.save(&mut writer);
+            mem_ctl.elf_regions.save(&mut writer);
+        }
+        writer.into_inner()
+    }
+
+    /// Restores the state saved by `save_checkpoint`, the current state is kept if it fails to parse
+    pub fn load_checkpoint(&self, data: &[u8]) -> Result<(), String> {
+        let mut reader = CheckpointReader::new(data)?;
+        let cycles_ran = u128::load(&mut reader)?;
+        let snapshot = Snapshot::load(&mut reader)?;
+        let inputs: Vec<(u128, u8)> = Checkpoint::load(&mut reader)?;
+        let backend_mem = Checkpoint::load(&mut reader)?;
+        let label = Checkpoint::load(&mut reader)?;
+        let mem_map_type = Checkpoint::load(&mut reader)?;
+        let elf_regions = Checkpoint::load(&mut reader)?;
+
+        self.reset();
+        {
+            let mut mem_ctl = self.mem_ctl.lock().unwrap();
+            mem_ctl.backend_mem = backend_mem;
+            mem_ctl.label = label;
+            mem_ctl.mem_map_type = mem_map_type;
+            mem_ctl.elf_regions = elf_regions;
+        }
+        self.restart_history_from(&snapshot);
+        for (input_cycles_ran, input) in &inputs {
+            self.history
+                .lock()
+                .unwrap()
+                .push_input(*input_cycles_ran, *input);
+        }
+        // the commit log and the waveform dump start at the cycle the checkpoint was saved at
+        self.replay_to(cycles_ran, &inputs);
+        Ok(())
+    }
+
+    pub fn get_cycles_ran(&self) -> u128 {
+        self.stats.lock().unwrap()[&CyclesRan]
+    }
+
+    pub fn get_instructions_ran(&self) -> u128 {
+        self.stats.lock().unwrap()[&InstructionsRan]
     }
 
     pub fn run_until_addr<F: Fn() + Copy>(&self, addr: &BTreeSet<Word>, hook: Option<F>) {
-        while !self.ir.lock().unwrap().can_end() {
+        self.is_pause_requested.store(false, Ordering::Relaxed);
+        while !self.has_ended() && !self.is_pause_requested() {
             if addr.contains(&self.pc.lock().unwrap().data_inner) {
                 break;
             }
             self.run_instruction(hook);
+            if self.get_watchpoint_hit().is_some() {
+                break;
+            }
+        }
+    }
+
+    /// Runs the functional model until it arrives at one of `breakpoints`, hits a watchpoint,
+    /// the program ends or `max_instructions` have run, then the datapath takes over from there
+    ///
+    /// Each instruction counts as a single cycle and is not written to the commit log
+    pub fn fast_forward<F: Fn() + Copy>(
+        &self,
+        breakpoints: &BTreeSet<Word>,
+        max_instructions: Option<u128>,
+        hook: Option<F>,
+    ) {
+        self.is_pause_requested.store(false, Ordering::Relaxed);
+        // the architectural state is only consistent between two instructions
+        if self.control.lock().unwrap().state != States::Fetch {
+            self.run_instruction(hook);
+        }
+        self.mem_ctl.lock().unwrap().watchpoint_hit = None;
+        // the functional model only knows memory
+        self.flush_caches();
+        let Some(mut pc) = Into::<Option<u32>>::into(self.pc.lock().unwrap().data_inner) else {
+            return;
+        };
+        let mut ir = self.ir.lock().unwrap().data_inner;
+
+        let mut instructions_remaining = max_instructions.unwrap_or(u128::MAX);
+        while instructions_remaining > 0 {
+            let max_steps = instructions_remaining.min(Self::FAST_FORWARD_BATCH_SIZE);
+            let steps = {
+                let mut regfile = self.regfile.lock().unwrap();
+                let mut csr_file = self.csr_file.lock().unwrap();
+                let mut mem_ctl = self.mem_ctl.lock().unwrap();
+                let mut functional_core = FunctionalCore::new(
+                    pc,
+                    ir,
+                    &mut regfile.registers,
+                    &mut csr_file,
+                    &mut mem_ctl,
+                    &self.clint_mmio_ctl,
+                    &self.htif_mmio_ctl,
+                );
+                let steps = functional_core.run(max_steps, breakpoints);
+                pc = functional_core.pc;
+                ir = functional_core.ir;
+                steps
+            };
+
+            instructions_remaining -= steps;
+            {
+                let mut stats = self.stats.lock().unwrap();
+                *stats.get_mut(&CyclesRan).unwrap() += steps;
+                *stats.get_mut(&InstructionsRan).unwrap() += steps;
+            }
+            if let Some(ref hook) = hook {
+                hook();
+            }
+            if steps < max_steps || self.is_pause_requested() {
+                break;
+            }
+        }
+
+        self.control.lock().unwrap().state = States::Fetch;
+        self.pc.lock().unwrap().data_inner = Word::from(pc);
+        self.ir.lock().unwrap().data_inner = ir;
+        let snapshot = self.capture_snapshot(true);
+        // restoring resets the memory controller, which forgets the watchpoint hit
+        let watchpoint_hit = self.get_watchpoint_hit();
+        self.restart_history_from(&snapshot);
+        self.mem_ctl.lock().unwrap().watchpoint_hit = watchpoint_hit;
+    }
+
+    /// Executes the next instruction on the functional model, returns it if it retired,
+    /// or None if it trapped or took an interrupt instead
+    ///
+    /// Unlike `fast_forward` the datapath is not brought up to date afterwards,
+    /// this is for a core that is only ever stepped this way, such as a lockstep reference
+    pub fn step_functional(&self) -> Option<Commit> {
+        let pc = Into::<Option<u32>>::into(self.pc.lock().unwrap().data_inner)?;
+        let ir = self.ir.lock().unwrap().data_inner;
+        let (commit, pc, ir) = {
+            let mut regfile = self.regfile.lock().unwrap();
+            let mut csr_file = self.csr_file.lock().unwrap();
+            let mut mem_ctl = self.mem_ctl.lock().unwrap();
+            let mut functional_core = FunctionalCore::new(
+                pc,
+                ir,
+                &mut regfile.registers,
+                &mut csr_file,
+                &mut mem_ctl,
+                &self.clint_mmio_ctl,
+                &self.htif_mmio_ctl,
+            );
+            let commit = functional_core.step();
+            (commit, functional_core.pc, functional_core.ir)
+        };
+
+        self.pc.lock().unwrap().data_inner = Word::from(pc);
+        self.ir.lock().unwrap().data_inner = ir;
+        {
+            let mut stats = self.stats.lock().unwrap();
+            *stats.get_mut(&CyclesRan).unwrap() += 1;
+            if commit.is_some() {
+                *stats.get_mut(&InstructionsRan).unwrap() += 1;
+            }
         }
+        commit
     }
 
     pub fn run_end<F: Fn() + Copy>(&self, hook: Option<F>) {
-        while !self.ir.lock().unwrap().can_end() {
+        self.is_pause_requested.store(false, Ordering::Relaxed);
+        while !self.has_ended() && !self.is_pause_requested() {
             self.run_instruction(hook);
+            if self.get_watchpoint_hit().is_some() {
+                break;
+            }
+        }
+    }
+
+    /// Runs cycle by cycle until the pc arrives at one of `breakpoints`, a watchpoint is hit,
+    /// the program ends or it is paused, the hook decides how fast
+    ///
+    /// Unlike `run_until_addr` it leaves a breakpoint it starts on
+    pub fn run_free<F: Fn() + Copy>(&self, breakpoints: &BTreeSet<Word>, hook: Option<F>) {
+        self.is_pause_requested.store(false, Ordering::Relaxed);
+        self.mem_ctl.lock().unwrap().watchpoint_hit = None;
+        while !self.has_ended() && !self.is_pause_requested() {
+            let old_pc = self.pc.lock().unwrap().data_inner;
+            self.run_cycle(hook);
+            let pc = self.pc.lock().unwrap().data_inner;
+            if (pc != old_pc && breakpoints.contains(&pc)) || self.get_watchpoint_hit().is_some() {
+                break;
+            }
         }
     }
 
-    pub fn load_elf(&self, data: &[u8]) {
-        self.mem_ctl.lock().unwrap().load_elf(data);
+    /// Stops `run_end`, `run_until_addr`, `run_free` or `fast_forward` early,
+    /// for their hook to call when the user asks them to stop
+    ///
+    /// `run_end` and `run_until_addr` still finish the instruction they are in
+    pub fn pause(&self) {
+        self.is_pause_requested.store(true, Ordering::Relaxed);
+    }
+
+    fn is_pause_requested(&self) -> bool {
+        self.is_pause_requested.load(Ordering::Relaxed)
+    }
+
+    /// The watchpoint hit by the last instruction, if any
+    pub fn get_watchpoint_hit(&self) -> Option<WatchpointHit> {
+        self.mem_ctl.lock().unwrap().watchpoint_hit.clone()
+    }
+
+    /// Whether the program has exited through htif,
+    /// or is spinning on one of the end instructions if it has no `tohost`,
+    /// or has halted on an access fault it has no trap handler for
+    pub fn has_ended(&self) -> bool {
+        let has_exited = self.htif_mmio_ctl.lock().unwrap().get_exit_code().is_some();
+        has_exited
+            || self.ir.lock().unwrap().can_end()
+            || self.get_unhandled_access_fault().is_some()
+    }
+
+    /// Where the memory map comes from, takes effect from the next access
+    pub fn set_mem_map_type(&self, mem_map_type: MemMapType) {
+        self.mem_ctl.lock().unwrap().mem_map_type = mem_map_type;
+    }
+
+    /// Starts the cache over cold with `config`, the history is dropped since it ran with the old one
+    ///
+    /// The current instruction is finished first, the lines it is waiting on must not go away
+    pub fn set_cache_config(
+        &self,
+        cache_type: CacheType,
+        config: CacheConfig,
+    ) -> Result<(), String> {
+        config.validate()?;
+        if self.control.lock().unwrap().state != States::Fetch && !self.has_ended() {
+            self.run_instruction(None::<fn()>);
+        }
+        let cache_ctl = match cache_type {
+            CacheType::Instruction => &self.icache,
+            CacheType::Data => &self.dcache,
+        };
+        {
+            let mut cache_ctl = cache_ctl.lock().unwrap();
+            self.write_back(cache_ctl.cache.flush());
+            cache_ctl.cache.set_config(config)?;
+        }
+        self.history.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// The instruction cache and the data cache
+    pub fn get_caches(&self) -> (Cache, Cache) {
+        (
+            self.icache.lock().unwrap().cache.clone(),
+            self.dcache.lock().unwrap().cache.clone(),
+        )
+    }
+
+    /// Writes the dirty lines of both caches to memory and drops every line
+    fn flush_caches(&self) {
+        for cache_ctl in [&self.icache, &self.dcache] {
+            let write_backs = cache_ctl.lock().unwrap().cache.flush();
+            self.write_back(write_backs);
+        }
+    }
+
+    /// Bytes a line does not know are left as they are in memory
+    fn write_back(&self, write_backs: Vec<(u32, Vec<Byte>)>) {
+        let mut mem_ctl = self.mem_ctl.lock().unwrap();
+        for (line_addr, data) in write_backs {
+            for (addr, byte) in (line_addr..).zip(data) {
+                if Into::<Option<u8>>::into(byte).is_some() {
+                    mem_ctl.poke_byte(addr, byte);
+                }
+            }
+        }
+    }
+
+    /// The byte at `addr` as the program sees it, the data cache may hold a newer one than memory
+    pub fn peek_byte(&self, addr: u32) -> Byte {
+        if let Some(byte) = self.dcache.lock().unwrap().cache.peek_byte(addr) {
+            return byte;
+        }
+        self.mem_ctl.lock().unwrap().peek_byte(addr)
+    }
+
+    /// Changes the byte at `addr` in memory and in any line holding it
+    pub fn poke_byte(&self, addr: u32, data: Byte) {
+        self.icache.lock().unwrap().cache.poke_byte(addr, data);
+        self.dcache.lock().unwrap().cache.poke_byte(addr, data);
+        self.mem_ctl.lock().unwrap().poke_byte(addr, data);
+    }
+
+    /// The access fault the program has halted on, since trapping to mtvec would fault again
+    pub fn get_unhandled_access_fault(&self) -> Option<AccessFault> {
+        let mtvec = self.csr_file.lock().unwrap().mtvec;
+        let mem_ctl = self.mem_ctl.lock().unwrap();
+        if mem_ctl.has_unhandled_access_fault(mtvec) {
+            mem_ctl.access_fault.clone()
+        } else {
+            None
+        }
+    }
+
+    /// Replaces whatever was loaded with an ELF, the current state is kept if it cannot be parsed
+    pub fn load_elf(&self, data: &[u8]) -> Result<(), String> {
+        let elf = Elf::parse(data)?;
+        self.reset();
+        self.mem_ctl.lock().unwrap().load_elf(&elf);
+        Ok(())
+    }
+
+    /// Replaces whatever was loaded with an assembled program
+    pub fn load_program(&self, program: &Program) {
+        self.reset();
+        self.mem_ctl.lock().unwrap().load_program(program);
+    }
+
+    /// Loads an ELF, a flat binary, Intel HEX or readmemh image, whichever `data` looks like
+    ///
+    /// `base` is where flat binaries and readmemh images start
+    pub fn load_image(
+        &self,
+        file_name: &str,
+        data: &[u8],
+        base: u32,
+    ) -> Result<ImageFormat, String> {
+        let format = ImageFormat::detect(file_name, data);
+        let text = || String::from_utf8_lossy(data);
+        match format {
+            ImageFormat::Elf => self.load_elf(data)?,
+            ImageFormat::Binary => self.load_program(&load_binary(data, base)),
+            ImageFormat::IntelHex => self.load_program(&load_ihex(&text())?),
+            ImageFormat::Readmemh => self.load_program(&load_readmemh(&text(), base)?),
+        }
+        Ok(format)
     }
 
     pub fn reset(&self) {
+        self.reset_components();
+        self.keyboard_mmio_ctl.lock().unwrap().reset();
+        self.vga_mmio_ctl.lock().unwrap().reset();
+        self.clint_mmio_ctl.lock().unwrap().reset();
+        self.htif_mmio_ctl.lock().unwrap().reset();
+        self.history.lock().unwrap().clear();
+        self.install_mmio_ctls();
+    }
+
+    fn reset_components(&self) {
         self.mem_ctl.lock().unwrap().reset();
+        self.icache.lock().unwrap().reset();
+        self.dcache.lock().unwrap().reset();
         self.control.lock().unwrap().reset();
         self.ir.lock().unwrap().reset();
         self.pc_mux.lock().unwrap().reset();
@@ -225,20 +896,45 @@ impl Core {
         self.cmp.lock().unwrap().reset();
         self.regfile_mux.lock().unwrap().reset();
         self.regfile.lock().unwrap().reset();
-        self.keyboard_mmio_ctl.lock().unwrap().reset();
-        self.vga_mmio_ctl.lock().unwrap().reset();
+        self.muldiv.lock().unwrap().reset();
+        self.csr_file.lock().unwrap().reset();
+    }
 
+    /// Installs the devices, the htif only if the loaded ELF has `tohost`/`fromhost`
+    fn install_mmio_ctls(&self) {
         self.mem_ctl.lock().unwrap().install_mmio_ctl(
-            KeyboardMmioCtl::STATUS_ADDR..KeyboardMmioCtl::DATA_ADDR + 1,
+            KeyboardMmioCtl::STATUS_ADDR..KeyboardMmioCtl::INTERRUPT_ENABLE_ADDR + 1,
             self.keyboard_mmio_ctl.clone(),
         );
         self.mem_ctl.lock().unwrap().install_mmio_ctl(
             VgaMmioCtl::BASE_ADDR..VgaMmioCtl::BASE_ADDR + VgaMmioCtl::NUM_BYTES as u32,
             self.vga_mmio_ctl.clone(),
         );
+        self.mem_ctl.lock().unwrap().install_mmio_ctl(
+            ClintMmioCtl::MTIMECMP_ADDR..ClintMmioCtl::MTIMECMP_ADDR + 8,
+            self.clint_mmio_ctl.clone(),
+        );
+        self.mem_ctl.lock().unwrap().install_mmio_ctl(
+            ClintMmioCtl::MTIME_ADDR..ClintMmioCtl::MTIME_ADDR + 8,
+            self.clint_mmio_ctl.clone(),
+        );
+
+        let htif_addrs = {
+            let htif_mmio_ctl = self.htif_mmio_ctl.lock().unwrap();
+            [
+                htif_mmio_ctl.get_tohost_addr(),
+                htif_mmio_ctl.get_fromhost_addr(),
+            ]
+        };
+        for addr in htif_addrs.into_iter().flatten() {
+            self.mem_ctl
+                .lock()
+                .unwrap()
+                .install_mmio_ctl(addr..addr + 8, self.htif_mmio_ctl.clone());
+        }
     }
 
-    pub fn new(threads_to_use: usize, commit_file: Option<File>) -> Self {
+    pub fn new(threads_to_use: usize, commit_file: Option<File>, vcd_file: Option<File>) -> Self {
         let ack_channel = unbounded();
         let sim_manager = SimManager::new(ack_channel.1.clone());
         let stats: Mutex<HashMap<StatsType, u128>> = Mutex::new(Default::default());
@@ -250,6 +946,21 @@ impl Core {
         // i swear there has to be a better way of doing this
         let mut mem_ctl_cpu_rdata = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut mem_ctl_cpu_resp = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut mem_ctl_cpu_fault = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_cpu_rdata = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_cpu_resp = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_cpu_fault = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_mem_addr = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_mem_wdata = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_mem_rmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut icache_mem_wmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_cpu_rdata = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_cpu_resp = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_cpu_fault = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_mem_addr = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_mem_wdata = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_mem_rmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut dcache_mem_wmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut control_pc_load = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut control_ir_load = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut control_rf_load = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
@@ -264,6 +975,14 @@ impl Core {
         let mut control_cmp_mux_sel = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut control_mem_rmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut control_mem_wmask = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_muldiv_start = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_muldiv_op = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_csr_op = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_csr_wr = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_mret = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_trap = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_trap_cause = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut control_trap_val = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut ir_funct3 = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut ir_funct7 = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut ir_opcode = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
@@ -286,11 +1005,34 @@ impl Core {
         let mut regfile_mux_out = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut regfile_rs1_data = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
         let mut regfile_rs2_data = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut muldiv_out = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut muldiv_resp = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut csr_file_out = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut csr_file_mtvec_out = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut csr_file_mepc_out = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut csr_file_illegal = Tx::<Byte>::new(sim_manager.clone(), ack_channel.0.clone());
+        let mut csr_file_interrupt = Tx::<Word>::new(sim_manager.clone(), ack_channel.0.clone());
 
-        let mem_ctl_cpu_rdata_rx_ir = mem_ctl_cpu_rdata.add_rx();
-        let mem_ctl_cpu_rdata_rx_regfile_mux = mem_ctl_cpu_rdata.add_rx();
+        let mem_ctl_cpu_rdata_rx = mem_ctl_cpu_rdata.add_rx();
         let mem_ctl_cpu_resp_rx = mem_ctl_cpu_resp.add_rx();
+        let mem_ctl_cpu_fault_rx = mem_ctl_cpu_fault.add_rx();
+        let icache_cpu_rdata_rx_ir = icache_cpu_rdata.add_rx();
+        let icache_cpu_rdata_rx_regfile_mux = icache_cpu_rdata.add_rx();
+        let icache_cpu_resp_rx = icache_cpu_resp.add_rx();
+        let icache_cpu_fault_rx = icache_cpu_fault.add_rx();
+        let icache_mem_addr_rx = icache_mem_addr.add_rx();
+        let icache_mem_wdata_rx = icache_mem_wdata.add_rx();
+        let icache_mem_rmask_rx = icache_mem_rmask.add_rx();
+        let icache_mem_wmask_rx = icache_mem_wmask.add_rx();
+        let dcache_cpu_rdata_rx = dcache_cpu_rdata.add_rx();
+        let dcache_cpu_resp_rx = dcache_cpu_resp.add_rx();
+        let dcache_cpu_fault_rx = dcache_cpu_fault.add_rx();
+        let dcache_mem_addr_rx = dcache_mem_addr.add_rx();
+        let dcache_mem_wdata_rx = dcache_mem_wdata.add_rx();
+        let dcache_mem_rmask_rx = dcache_mem_rmask.add_rx();
+        let dcache_mem_wmask_rx = dcache_mem_wmask.add_rx();
         let control_pc_load_rx = control_pc_load.add_rx();
+        let control_pc_load_rx_csr_file = control_pc_load.add_rx();
         let control_ir_load_rx = control_ir_load.add_rx();
         let control_rf_load_rx = control_rf_load.add_rx();
         let control_alu_op_rx = control_alu_op.add_rx();
@@ -300,20 +1042,34 @@ impl Core {
         let control_alu_mux2_sel_rx = control_alu_mux2_sel.add_rx();
         let control_rf_mux_sel_rx = control_rf_mux_sel.add_rx();
         let control_mem_addr_mux_sel_rx = control_mem_addr_mux_sel.add_rx();
+        let control_mem_addr_mux_sel_rx_mem_ctl = control_mem_addr_mux_sel.add_rx();
+        let control_mem_addr_mux_sel_rx_icache = control_mem_addr_mux_sel.add_rx();
+        let control_mem_addr_mux_sel_rx_dcache = control_mem_addr_mux_sel.add_rx();
         let control_cmp_mux_sel_rx = control_cmp_mux_sel.add_rx();
         let control_mem_rmask_rx = control_mem_rmask.add_rx();
         let control_mem_wmask_rx = control_mem_wmask.add_rx();
+        let control_muldiv_start_rx = control_muldiv_start.add_rx();
+        let control_muldiv_op_rx = control_muldiv_op.add_rx();
+        let control_csr_op_rx = control_csr_op.add_rx();
+        let control_csr_wr_rx = control_csr_wr.add_rx();
+        let control_mret_rx = control_mret.add_rx();
+        let control_trap_rx = control_trap.add_rx();
+        let control_trap_cause_rx = control_trap_cause.add_rx();
+        let control_trap_val_rx = control_trap_val.add_rx();
         let ir_funct3_rx = ir_funct3.add_rx();
         let ir_funct7_rx = ir_funct7.add_rx();
         let ir_opcode_rx = ir_opcode.add_rx();
         let ir_i_imm_rx_alu_mux2 = ir_i_imm.add_rx();
         let ir_i_imm_rx_cmp_mux = ir_i_imm.add_rx();
+        let ir_i_imm_rx_csr_file = ir_i_imm.add_rx();
+        let ir_i_imm_rx_control = ir_i_imm.add_rx();
         let ir_s_imm_rx = ir_s_imm.add_rx();
         let ir_b_imm_rx = ir_b_imm.add_rx();
         let ir_u_imm_rx_alu_mux2 = ir_u_imm.add_rx();
         let ir_u_imm_rx_rf_mux = ir_u_imm.add_rx();
         let ir_j_imm_rx = ir_j_imm.add_rx();
         let ir_rs1_idx_rx = ir_rs1_idx.add_rx();
+        let ir_rs1_idx_rx_csr_file = ir_rs1_idx.add_rx();
         let ir_rs2_idx_rx = ir_rs2_idx.add_rx();
         let ir_rd_idx_rx = ir_rd_idx.add_rx();
         let pc_mux_out_rx = pc_mux_out.add_rx();
@@ -321,7 +1077,8 @@ impl Core {
         let pc_out_rx_pc_mux = pc_out.add_rx();
         let pc_out_rx_alu_mux1 = pc_out.add_rx();
         let pc_out_rx_rf_mux = pc_out.add_rx();
-        let mem_addr_mux_out_rx_mem_ctl = mem_addr_mux_out.add_rx();
+        let pc_out_rx_csr_file = pc_out.add_rx();
+        let mem_addr_mux_out_rx_icache = mem_addr_mux_out.add_rx();
         let mem_addr_mux_out_rx_control = mem_addr_mux_out.add_rx();
         let mem_addr_mux_out_rx_rf_mux = mem_addr_mux_out.add_rx();
         let alu_mux1_out_rx = alu_mux1_out.add_rx();
@@ -335,9 +1092,19 @@ impl Core {
         let regfile_mux_out_rx = regfile_mux_out.add_rx();
         let regfile_rs1_data_rx_alu_mux1 = regfile_rs1_data.add_rx();
         let regfile_rs1_data_rx_cmp = regfile_rs1_data.add_rx();
+        let regfile_rs1_data_rx_muldiv = regfile_rs1_data.add_rx();
+        let regfile_rs1_data_rx_csr_file = regfile_rs1_data.add_rx();
         let regfile_rs2_data_rx_alu_mux2 = regfile_rs2_data.add_rx();
         let regfile_rs2_data_rx_cmp_mux = regfile_rs2_data.add_rx();
-        let regfile_rs2_data_rx_mem_ctl = regfile_rs2_data.add_rx();
+        let regfile_rs2_data_rx_icache = regfile_rs2_data.add_rx();
+        let regfile_rs2_data_rx_muldiv = regfile_rs2_data.add_rx();
+        let muldiv_out_rx_rf_mux = muldiv_out.add_rx();
+        let muldiv_resp_rx = muldiv_resp.add_rx();
+        let csr_file_out_rx_rf_mux = csr_file_out.add_rx();
+        let csr_file_mtvec_out_rx = csr_file_mtvec_out.add_rx();
+        let csr_file_mepc_out_rx = csr_file_mepc_out.add_rx();
+        let csr_file_illegal_rx = csr_file_illegal.add_rx();
+        let csr_file_interrupt_rx = csr_file_interrupt.add_rx();
 
         let pc_mux = Arc::new(Mutex::new(PcMux::new(
             3,
@@ -345,6 +1112,8 @@ impl Core {
             ack_channel.0.clone(),
             pc_out_rx_pc_mux,
             alu_out_rx_pc_mux,
+            csr_file_mtvec_out_rx,
+            csr_file_mepc_out_rx,
             control_pc_mux_sel_rx,
             pc_mux_out,
         )));
@@ -358,21 +1127,71 @@ impl Core {
             pc_out,
         )));
 
+        let htif_mmio_ctl = Arc::new(Mutex::new(HtifMmioCtl::new()));
         let mem_ctl = Arc::new(Mutex::new(MemCtl::new(
             0,
             sim_manager.clone(),
             ack_channel.0.clone(),
             pc.clone(),
-            mem_addr_mux_out_rx_mem_ctl,
-            regfile_rs2_data_rx_mem_ctl,
-            control_mem_rmask_rx,
-            control_mem_wmask_rx,
+            htif_mmio_ctl.clone(),
+            dcache_mem_addr_rx,
+            dcache_mem_wdata_rx,
+            dcache_mem_rmask_rx,
+            dcache_mem_wmask_rx,
+            control_mem_addr_mux_sel_rx_mem_ctl,
             mem_ctl_cpu_rdata,
             mem_ctl_cpu_resp,
+            mem_ctl_cpu_fault,
+        )));
+
+        let icache = Arc::new(Mutex::new(CacheCtl::new(
+            6,
+            sim_manager.clone(),
+            ack_channel.0.clone(),
+            CacheType::Instruction,
+            mem_ctl.clone(),
+            mem_addr_mux_out_rx_icache,
+            regfile_rs2_data_rx_icache,
+            control_mem_rmask_rx,
+            control_mem_wmask_rx,
+            control_mem_addr_mux_sel_rx_icache,
+            dcache_cpu_rdata_rx,
+            dcache_cpu_resp_rx,
+            dcache_cpu_fault_rx,
+            icache_cpu_rdata,
+            icache_cpu_resp,
+            icache_cpu_fault,
+            icache_mem_addr,
+            icache_mem_wdata,
+            icache_mem_rmask,
+            icache_mem_wmask,
+        )));
+
+        let dcache = Arc::new(Mutex::new(CacheCtl::new(
+            7,
+            sim_manager.clone(),
+            ack_channel.0.clone(),
+            CacheType::Data,
+            mem_ctl.clone(),
+            icache_mem_addr_rx,
+            icache_mem_wdata_rx,
+            icache_mem_rmask_rx,
+            icache_mem_wmask_rx,
+            control_mem_addr_mux_sel_rx_dcache,
+            mem_ctl_cpu_rdata_rx,
+            mem_ctl_cpu_resp_rx,
+            mem_ctl_cpu_fault_rx,
+            dcache_cpu_rdata,
+            dcache_cpu_resp,
+            dcache_cpu_fault,
+            dcache_mem_addr,
+            dcache_mem_wdata,
+            dcache_mem_rmask,
+            dcache_mem_wmask,
         )));
         let keyboard_mmio_ctl = Arc::new(Mutex::new(KeyboardMmioCtl::new()));
         mem_ctl.lock().unwrap().install_mmio_ctl(
-            KeyboardMmioCtl::STATUS_ADDR..KeyboardMmioCtl::DATA_ADDR + 1,
+            KeyboardMmioCtl::STATUS_ADDR..KeyboardMmioCtl::INTERRUPT_ENABLE_ADDR + 1,
             keyboard_mmio_ctl.clone(),
         );
         let vga_mmio_ctl = Arc::new(Mutex::new(VgaMmioCtl::new()));
@@ -380,6 +1199,15 @@ impl Core {
             VgaMmioCtl::BASE_ADDR..VgaMmioCtl::BASE_ADDR + VgaMmioCtl::NUM_BYTES as u32,
             vga_mmio_ctl.clone(),
         );
+        let clint_mmio_ctl = Arc::new(Mutex::new(ClintMmioCtl::new()));
+        mem_ctl.lock().unwrap().install_mmio_ctl(
+            ClintMmioCtl::MTIMECMP_ADDR..ClintMmioCtl::MTIMECMP_ADDR + 8,
+            clint_mmio_ctl.clone(),
+        );
+        mem_ctl.lock().unwrap().install_mmio_ctl(
+            ClintMmioCtl::MTIME_ADDR..ClintMmioCtl::MTIME_ADDR + 8,
+            clint_mmio_ctl.clone(),
+        );
 
         let control = Arc::new(Mutex::new(Control::new(
             1,
@@ -390,7 +1218,12 @@ impl Core {
             cmp_out_rx_control,
             ir_opcode_rx,
             mem_addr_mux_out_rx_control,
-            mem_ctl_cpu_resp_rx,
+            icache_cpu_resp_rx,
+            icache_cpu_fault_rx,
+            muldiv_resp_rx,
+            ir_i_imm_rx_control,
+            csr_file_illegal_rx,
+            csr_file_interrupt_rx,
             control_pc_load,
             control_ir_load,
             control_rf_load,
@@ -404,6 +1237,14 @@ impl Core {
             control_cmp_mux_sel,
             control_mem_rmask,
             control_mem_wmask,
+            control_muldiv_start,
+            control_muldiv_op,
+            control_csr_op,
+            control_csr_wr,
+            control_mret,
+            control_trap,
+            control_trap_cause,
+            control_trap_val,
         )));
 
         let ir = Arc::new(Mutex::new(IR::new(
@@ -411,7 +1252,7 @@ impl Core {
             sim_manager.clone(),
             ack_channel.0.clone(),
             control_ir_load_rx,
-            mem_ctl_cpu_rdata_rx_ir,
+            icache_cpu_rdata_rx_ir,
             ir_funct3,
             ir_funct7,
             ir_opcode,
@@ -497,8 +1338,10 @@ impl Core {
             cmp_out_rx_rf_mux,
             ir_u_imm_rx_rf_mux,
             mem_addr_mux_out_rx_rf_mux,
-            mem_ctl_cpu_rdata_rx_regfile_mux,
+            icache_cpu_rdata_rx_regfile_mux,
             pc_out_rx_rf_mux,
+            muldiv_out_rx_rf_mux,
+            csr_file_out_rx_rf_mux,
             control_rf_mux_sel_rx,
             regfile_mux_out,
         )));
@@ -516,6 +1359,43 @@ impl Core {
             regfile_rs2_data,
         )));
 
+        let muldiv = Arc::new(Mutex::new(MulDiv::new(
+            15,
+            sim_manager.clone(),
+            ack_channel.0.clone(),
+            MulDiv::DEFAULT_LATENCY,
+            regfile_rs1_data_rx_muldiv,
+            regfile_rs2_data_rx_muldiv,
+            control_muldiv_op_rx,
+            control_muldiv_start_rx,
+            muldiv_out,
+            muldiv_resp,
+        )));
+
+        let csr_file = Arc::new(Mutex::new(CsrFile::new(
+            16,
+            sim_manager.clone(),
+            ack_channel.0.clone(),
+            clint_mmio_ctl.clone(),
+            keyboard_mmio_ctl.clone(),
+            ir_i_imm_rx_csr_file,
+            ir_rs1_idx_rx_csr_file,
+            regfile_rs1_data_rx_csr_file,
+            pc_out_rx_csr_file,
+            control_csr_op_rx,
+            control_csr_wr_rx,
+            control_pc_load_rx_csr_file,
+            control_trap_rx,
+            control_trap_cause_rx,
+            control_trap_val_rx,
+            control_mret_rx,
+            csr_file_out,
+            csr_file_mtvec_out,
+            csr_file_mepc_out,
+            csr_file_illegal,
+            csr_file_interrupt,
+        )));
+
         let components_vec: Vec<Arc<Mutex<dyn Component>>> = vec![
             mem_ctl.clone(),
             control.clone(),
@@ -523,6 +1403,8 @@ impl Core {
             pc_mux.clone(),
             pc.clone(),
             mem_addr_mux.clone(),
+            icache.clone(),
+            dcache.clone(),
             alu_mux1.clone(),
             alu_mux2.clone(),
             alu.clone(),
@@ -530,6 +1412,8 @@ impl Core {
             cmp.clone(),
             regfile_mux.clone(),
             regfile.clone(),
+            muldiv.clone(),
+            csr_file.clone(),
         ];
 
         let sim_dispatchers: Vec<_> = components_vec
@@ -550,6 +1434,8 @@ impl Core {
             sim_manager,
             sim_dispatcher_handlers,
             mem_ctl,
+            icache,
+            dcache,
             control,
             ir,
             pc_mux,
@@ -562,10 +1448,54 @@ impl Core {
             cmp,
             regfile_mux,
             regfile,
+            muldiv,
+            csr_file,
             keyboard_mmio_ctl,
             vga_mmio_ctl,
+            clint_mmio_ctl,
+            htif_mmio_ctl,
             commit_file: Mutex::new(commit_file),
+            vcd_writer: Mutex::new(vcd_file.map(|file| VcdWriter::new(BufWriter::new(file)))),
             stats,
+            history: Default::default(),
+            is_pause_requested: AtomicBool::new(false),
         }
     }
 }
+
+/// Used by the headless cli only
+impl Core {
+    /// Same as `run_end`, but also stops once `max_cycles` or `max_instructions` more have been ran
+    ///
+    /// The limits are checked between instructions, returns whether the program has ended
+    pub fn run_end_with_limit<F: Fn() + Copy>(
+        &self,
+        max_cycles: Option<u128>,
+        max_instructions: Option<u128>,
+        hook: Option<F>,
+    ) -> bool {
+        let start_cycles = self.get_cycles_ran();
+        let start_instructions = self.get_instructions_ran();
+
+        while !self.has_ended() {
+            if max_cycles.is_some_and(|max| self.get_cycles_ran() - start_cycles >= max)
+                || max_instructions
+                    .is_some_and(|max| self.get_instructions_ran() - start_instructions >= max)
+            {
+                return false;
+            }
+            self.run_instruction(hook);
+        }
+
+        true
+    }
+
+    /// The guest's exit code, from htif if it has exited through `tohost`, otherwise a0
+    pub fn get_exit_code(&self) -> Option<u32> {
+        self.htif_mmio_ctl
+            .lock()
+            .unwrap()
+            .get_exit_code()
+            .or_else(|| self.regfile.lock().unwrap().registers.data[10].into())
+    }
+}
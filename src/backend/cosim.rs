@@ -0,0 +1,471 @@
+use crate::backend::core::Core;
+use crate::backend::util::byte::Bytes;
+use crate::backend::util::types::Word;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+
+/// Matching instructions kept before a divergence, and instructions shown after it
+const CONTEXT_LEN: usize = 5;
+
+/// A retired instruction, as a line of a spike style commit log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub pc: Word,
+    pub inst: Word,
+    /// (rd, value), writes to x0 are left out
+    pub rd_write: Option<(u8, Word)>,
+    pub mem_addr: Option<Word>,
+    /// The bytes a store wrote, least significant first
+    pub mem_wdata: Option<Vec<Option<u8>>>,
+}
+
+impl Commit {
+    /// Parses a line of `spike --log-commits`, returns `Ok(None)` if it is not a commit
+    ///
+    /// Only the registers and memory accesses this core logs are kept, csr writes are skipped
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let Some(rest) = line.strip_prefix("core") else {
+            return Ok(None);
+        };
+        let mut tokens = rest.split_whitespace().peekable();
+        // hart, then the privilege level, which a disassembly trace does not have
+        if tokens.next().is_none_or(|hart| !hart.ends_with(':'))
+            || tokens
+                .next()
+                .is_none_or(|privilege| privilege.parse::<u8>().is_err())
+        {
+            return Ok(None);
+        }
+
+        let mut next = |name: &str| {
+            tokens
+                .next()
+                .ok_or_else(|| format!("missing {} in \"{}\"", name, line))
+        };
+        let pc = parse_word(next("pc")?)?;
+        let inst = next("instruction")?;
+        let inst = inst
+            .strip_prefix('(')
+            .and_then(|inst| inst.strip_suffix(')'))
+            .ok_or_else(|| format!("invalid instruction {}", inst))?;
+        let mut commit = Commit {
+            pc,
+            inst: parse_word(inst)?,
+            rd_write: None,
+            mem_addr: None,
+            mem_wdata: None,
+        };
+
+        while let Some(token) = tokens.next() {
+            let mut value = || {
+                tokens
+                    .next()
+                    .ok_or_else(|| format!("missing value for {} in \"{}\"", token, line))
+            };
+            if token == "mem" {
+                commit.mem_addr = Some(parse_word(value()?)?);
+                // a store is followed by its data, a load is not
+                if let Some(wdata) = tokens.next_if(|wdata| wdata.starts_with("0x")) {
+                    commit.mem_wdata = Some(parse_hex(wdata)?);
+                }
+            } else if let Some(Ok(rd)) = token.strip_prefix('x').map(str::parse::<u8>) {
+                let rd_data = parse_word(value()?)?;
+                if rd != 0 {
+                    commit.rd_write = Some((rd, rd_data));
+                }
+            } else {
+                value()?;
+            }
+        }
+
+        Ok(Some(commit))
+    }
+
+    /// The names of the fields that differ from `other`
+    pub fn get_mismatched_fields(&self, other: &Commit) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.pc != other.pc {
+            fields.push("pc");
+        }
+        if self.inst != other.inst {
+            fields.push("instruction");
+        }
+        if self.rd_write != other.rd_write {
+            fields.push("rd write");
+        }
+        if self.mem_addr != other.mem_addr {
+            fields.push("memory address");
+        }
+        if self.mem_wdata != other.mem_wdata {
+            fields.push("memory data");
+        }
+        fields
+    }
+}
+
+impl Display for Commit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "core   0: 3 0x{:x} (0x{:x})", self.pc, self.inst)?;
+        if let Some((rd, rd_data)) = self.rd_write {
+            if rd < 10 {
+                write!(f, " x{}  0x{:x}", rd, rd_data)?;
+            } else {
+                write!(f, " x{} 0x{:x}", rd, rd_data)?;
+            }
+        }
+        if let Some(mem_addr) = self.mem_addr {
+            write!(f, " mem 0x{:x}", mem_addr)?;
+        }
+        if let Some(mem_wdata) = &self.mem_wdata {
+            let wdata_str = mem_wdata
+                .iter()
+                .rev()
+                .map(|x| {
+                    x.map(|byte| format!("{:02x}", byte))
+                        .unwrap_or("xx".to_string())
+                })
+                .collect::<String>();
+            write!(f, " 0x{}", wdata_str)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses hex digits after a `0x`, `xx` is an unknown byte, returns the bytes least significant first
+fn parse_hex(s: &str) -> Result<Vec<Option<u8>>, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .filter(|digits| !digits.is_empty() && digits.is_ascii())
+        .ok_or_else(|| format!("invalid hex value {}", s))?;
+    let digits = if digits.len() % 2 == 1 {
+        format!("0{}", digits)
+    } else {
+        digits.to_string()
+    };
+    digits
+        .as_bytes()
+        .rchunks(2)
+        .map(|pair| match pair {
+            b"xx" => Ok(None),
+            _ => std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .map(Some)
+                .ok_or_else(|| format!("invalid hex value {}", s)),
+        })
+        .collect()
+}
+
+fn parse_word(s: &str) -> Result<Word, String> {
+    let mut bytes = parse_hex(s)?;
+    if bytes.len() > 4 {
+        return Err(format!("{} does not fit in 32 bits", s));
+    }
+    bytes.resize(4, Some(0));
+    Ok(Bytes {
+        data: bytes.try_into().unwrap(),
+    })
+}
+
+/// Where the expected instructions come from
+pub enum Reference {
+    /// A spike style commit log, lines that are not commits are skipped
+    Log {
+        lines: std::io::Lines<Box<dyn BufRead>>,
+        line_number: usize,
+    },
+    /// The functional model, running the program on its own core
+    Functional(Box<Core>),
+}
+
+impl Reference {
+    pub fn from_log(reader: impl BufRead + 'static) -> Self {
+        Reference::Log {
+            lines: (Box::new(reader) as Box<dyn BufRead>).lines(),
+            line_number: 0,
+        }
+    }
+
+    pub fn from_elf(elf: &[u8]) -> Result<Self, String> {
+        let core = Core::new(1, None, None);
+        core.load_elf(elf)?;
+        Ok(Reference::Functional(Box::new(core)))
+    }
+
+    /// The next retired instruction, None once the reference has run out
+    fn next_commit(&mut self) -> Result<Option<Commit>, String> {
+        match self {
+            Reference::Log { lines, line_number } => {
+                for line in lines {
+                    *line_number += 1;
+                    let line = line.map_err(|e| e.to_string())?;
+                    if let Some(commit) = Commit::parse(&line)
+                        .map_err(|e| format!("reference line {}: {}", line_number, e))?
+                    {
+                        return Ok(Some(commit));
+                    }
+                }
+                Ok(None)
+            }
+            Reference::Functional(core) => {
+                while !core.has_ended() {
+                    if let Some(commit) = core.step_functional() {
+                        return Ok(Some(commit));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Where the last instruction came from, for the report
+    fn get_location(&self) -> String {
+        match self {
+            Reference::Log { line_number, .. } => format!("reference line {}", line_number),
+            Reference::Functional(core) => {
+                format!("reference instruction {}", core.get_instructions_ran())
+            }
+        }
+    }
+}
+
+/// The first instruction the core and the reference disagree on
+pub struct Divergence {
+    /// Instructions retired up to and including the one that diverged
+    pub instructions_ran: u128,
+    pub reference_location: String,
+    pub before: Vec<Commit>,
+    /// None if that side ended first
+    pub core: Option<Commit>,
+    pub reference: Option<Commit>,
+    pub core_after: Vec<Commit>,
+    pub reference_after: Vec<Commit>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fields = match (&self.core, &self.reference) {
+            (Some(core), Some(reference)) => core.get_mismatched_fields(reference).join(", "),
+            (None, _) => "core ended first".to_string(),
+            (_, None) => "reference ended first".to_string(),
+        };
+        writeln!(
+            f,
+            "diverged at instruction {} ({}): {}",
+            self.instructions_ran, self.reference_location, fields
+        )?;
+
+        let show = |commit: &Option<Commit>| {
+            commit
+                .as_ref()
+                .map_or("<ended>".to_string(), |commit| commit.to_string())
+        };
+        for commit in self.before.iter() {
+            writeln!(f, "            {}", commit)?;
+        }
+        writeln!(f, "core      > {}", show(&self.core))?;
+        writeln!(f, "reference > {}", show(&self.reference))?;
+        for i in 0..self.core_after.len().max(self.reference_after.len()) {
+            if let Some(commit) = self.core_after.get(i) {
+                writeln!(f, "core        {}", commit)?;
+            }
+            if let Some(commit) = self.reference_after.get(i) {
+                writeln!(f, "reference   {}", commit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub enum LockstepResult {
+    Ended,
+    LimitReached,
+    Diverged(Box<Divergence>),
+}
+
+/// Runs `core` until it ends or a limit is reached,
+/// comparing every instruction it retires with the reference and stopping at the first difference
+///
+/// Reference instructions before the first one at the core's starting pc are skipped,
+/// which drops spike's boot rom. Timing is not compared, so reading mcycle or mtime,
+/// or taking a timer interrupt, diverges from the functional model.
+pub fn run_lockstep<F: Fn() + Copy>(
+    core: &Core,
+    reference: &mut Reference,
+    max_cycles: Option<u128>,
+    max_instructions: Option<u128>,
+    hook: Option<F>,
+) -> Result<LockstepResult, String> {
+    let start_cycles = core.get_cycles_ran();
+    let mut instructions_ran = 0u128;
+    let mut before = VecDeque::with_capacity(CONTEXT_LEN + 1);
+
+    while !core.has_ended() {
+        if max_cycles.is_some_and(|max| core.get_cycles_ran() - start_cycles >= max)
+            || max_instructions.is_some_and(|max| instructions_ran >= max)
+        {
+            return Ok(LockstepResult::LimitReached);
+        }
+
+        let commit = core.run_commit(hook);
+        let mut expected = reference.next_commit()?;
+        if instructions_ran == 0
+            && let Some(commit) = &commit
+        {
+            while expected
+                .as_ref()
+                .is_some_and(|expected| expected.pc != commit.pc)
+            {
+                expected = reference.next_commit()?;
+            }
+        }
+        if commit.is_none() && expected.is_none() {
+            break;
+        }
+        instructions_ran += 1;
+
+        if commit != expected {
+            let reference_location = reference.get_location();
+            let mut core_after = Vec::new();
+            let mut reference_after = Vec::new();
+            for _ in 0..CONTEXT_LEN {
+                core_after.extend(core.run_commit(hook));
+                reference_after.extend(reference.next_commit()?);
+            }
+            return Ok(LockstepResult::Diverged(Box::new(Divergence {
+                instructions_ran,
+                reference_location,
+                before: before.into(),
+                core: commit,
+                reference: expected,
+                core_after,
+                reference_after,
+            })));
+        }
+
+        before.extend(commit);
+        if before.len() > CONTEXT_LEN {
+            before.pop_front();
+        }
+    }
+
+    Ok(LockstepResult::Ended)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::assembler::assemble;
+    use std::io::Cursor;
+
+    const PROGRAM: &str = "
+_start:
+    addi a0, zero, 1
+    addi a0, a0, 2
+    addi a1, a0, 4
+    slti zero, zero, -256
+";
+    /// What spike logs for `PROGRAM`, after a line of its boot rom
+    const LOG: [&str; 5] = [
+        "core   0: 3 0x00001000 (0x00000297) x5  0x00001000",
+        "core   0: 3 0x40000000 (0x00100513) x10 0x00000001",
+        "core   0: 3 0x40000004 (0x00250513) x10 0x00000003",
+        "core   0: 3 0x40000008 (0x00450593) x11 0x00000007",
+        "core   0: 3 0x4000000c (0xf0002013)",
+    ];
+
+    fn run_against_log(log: &[&str]) -> LockstepResult {
+        let core = Core::new(1, None, None);
+        core.load_program(&assemble(PROGRAM).unwrap());
+        let mut reference = Reference::from_log(Cursor::new(log.join("\n")));
+        run_lockstep(&core, &mut reference, Some(1000), None, None::<fn()>).unwrap()
+    }
+
+    #[test]
+    fn test_commit_parse() {
+        let line = "core   0: 3 0x80000004 (0x00112623) mem 0x80001ffc 0x000000a5";
+        let commit = Commit::parse(line).unwrap().unwrap();
+        assert_eq!(commit.pc, Word::from(0x80000004u32));
+        assert_eq!(commit.inst, Word::from(0x00112623u32));
+        assert_eq!(commit.rd_write, None);
+        assert_eq!(commit.mem_addr, Some(Word::from(0x80001ffcu32)));
+        assert_eq!(
+            commit.mem_wdata,
+            Some(vec![Some(0xa5), Some(0), Some(0), Some(0)])
+        );
+        assert_eq!(commit.to_string(), line);
+
+        let line = "core   0: 3 0x80000008 (0x00812483) x9  0xxxxxxxxx mem 0x80002008";
+        let commit = Commit::parse(line).unwrap().unwrap();
+        assert_eq!(commit.rd_write, Some((9, Word::unknown())));
+        assert_eq!(commit.mem_wdata, None);
+        assert_eq!(commit.to_string(), line);
+
+        // spike logs csr writes, which are not compared
+        let line = "core   0: 3 0x8000000c (0x30529073) c773_mtvec 0x80000100 x12 0x00000001";
+        let commit = Commit::parse(line).unwrap().unwrap();
+        assert_eq!(commit.rd_write, Some((12, Word::from(1u32))));
+        assert_eq!(commit.mem_addr, None);
+
+        let byte_store = "core   0: 3 0x80000010 (0x00b50023) mem 0x80002001 0x7f";
+        let commit = Commit::parse(byte_store).unwrap().unwrap();
+        assert_eq!(commit.mem_wdata, Some(vec![Some(0x7f)]));
+        assert_eq!(commit.to_string(), byte_store);
+    }
+
+    #[test]
+    fn test_commit_parse_skips_other_lines() {
+        assert_eq!(Commit::parse("commit #1000").unwrap(), None);
+        assert_eq!(
+            Commit::parse("core   0: 0x00001000 (0x00000297) auipc   t0, 0x0").unwrap(),
+            None
+        );
+        assert_eq!(
+            Commit::parse("core   0: exception trap_illegal_instruction, epc 0x80000000").unwrap(),
+            None
+        );
+        assert!(Commit::parse("core   0: 3 0x80000000").is_err());
+        assert!(Commit::parse("core   0: 3 0x8000000000000000 (0x00000297)").is_err());
+        assert!(Commit::parse("core   0: 3 0x80000000 (0x00000297) x5").is_err());
+    }
+
+    #[test]
+    fn test_lockstep_ended() {
+        assert!(matches!(run_against_log(&LOG), LockstepResult::Ended));
+    }
+
+    #[test]
+    fn test_lockstep_diverged() {
+        let mut log = LOG;
+        log[2] = "core   0: 3 0x40000004 (0x00250513) x10 0x00000004";
+        let LockstepResult::Diverged(divergence) = run_against_log(&log) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergence.instructions_ran, 2);
+        assert_eq!(divergence.reference_location, "reference line 3");
+        assert_eq!(divergence.core.as_ref().unwrap().to_string(), LOG[2]);
+        assert_eq!(divergence.reference.as_ref().unwrap().to_string(), log[2]);
+
+        let report = divergence.to_string();
+        assert!(report.starts_with("diverged at instruction 2 (reference line 3): rd write\n"));
+        assert!(report.contains(&format!("            {}\n", LOG[1])));
+        assert!(report.contains(&format!("core      > {}\n", LOG[2])));
+        assert!(report.contains(&format!("reference > {}\n", log[2])));
+        for line in &LOG[3..] {
+            assert!(report.contains(&format!("core        {}\n", line)));
+            assert!(report.contains(&format!("reference   {}\n", line)));
+        }
+    }
+
+    #[test]
+    fn test_lockstep_reference_ended_first() {
+        let LockstepResult::Diverged(divergence) = run_against_log(&LOG[..3]) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergence.instructions_ran, 3);
+        assert!(divergence.reference.is_none());
+        assert!(divergence.to_string().contains("reference ended first"));
+        assert!(divergence.to_string().contains("reference > <ended>"));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::backend::component::control::is_legal_instruction;
+use crate::backend::component::ir::IR;
+use crate::backend::util::types::{Word, csr_addr, funct3, funct7, funct12, opcode};
+
+/// The abi name of each register
+pub const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Disassembles an instruction at `pc` in the style of objdump without aliases
+///
+/// Registers use their abi names and branch and jump targets are absolute.
+/// Anything the core does not implement is shown as a `.word`
+pub fn disassemble(inst: u32, pc: u32) -> String {
+    let fields = IR::decode(inst);
+    if !is_legal_instruction(fields.opcode, fields.funct3, fields.funct7) {
+        return format!(".word 0x{:08x}", inst);
+    }
+
+    let rd = REG_NAMES[fields.rd as usize];
+    let rs1 = REG_NAMES[fields.rs1 as usize];
+    let rs2 = REG_NAMES[fields.rs2 as usize];
+    let imm = |imm: Word| Into::<Option<u32>>::into(imm).unwrap() as i32;
+    let i_imm = imm(fields.i_imm);
+    let s_imm = imm(fields.s_imm);
+    let b_target = pc.wrapping_add(imm(fields.b_imm) as u32);
+    let u_imm = imm(fields.u_imm) as u32 >> 12;
+    let j_target = pc.wrapping_add(imm(fields.j_imm) as u32);
+
+    match fields.opcode {
+        opcode::LUI => format!("lui {}, 0x{:x}", rd, u_imm),
+        opcode::AUIPC => format!("auipc {}, 0x{:x}", rd, u_imm),
+        opcode::JAL => format!("jal {}, 0x{:x}", rd, j_target),
+        opcode::JALR => format!("jalr {}, {}({})", rd, i_imm, rs1),
+        opcode::BR => {
+            let name = match fields.funct3 {
+                funct3::branch::BEQ => "beq",
+                funct3::branch::BNE => "bne",
+                funct3::branch::BLT => "blt",
+                funct3::branch::BGE => "bge",
+                funct3::branch::BLTU => "bltu",
+                _ => "bgeu",
+            };
+            format!("{} {}, {}, 0x{:x}", name, rs1, rs2, b_target)
+        }
+        opcode::LOAD => {
+            let name = match fields.funct3 {
+                funct3::load::LB => "lb",
+                funct3::load::LH => "lh",
+                funct3::load::LW => "lw",
+                funct3::load::LBU => "lbu",
+                _ => "lhu",
+            };
+            format!("{} {}, {}({})", name, rd, i_imm, rs1)
+        }
+        opcode::STORE => {
+            let name = match fields.funct3 {
+                funct3::store::SB => "sb",
+                funct3::store::SH => "sh",
+                _ => "sw",
+            };
+            format!("{} {}, {}({})", name, rs2, s_imm, rs1)
+        }
+        opcode::IMM => match fields.funct3 {
+            funct3::arith::SLL => format!("slli {}, {}, {}", rd, rs1, fields.rs2),
+            funct3::arith::SR => {
+                let name = if fields.funct7 & 0x20 != 0 {
+                    "srai"
+                } else {
+                    "srli"
+                };
+                format!("{} {}, {}, {}", name, rd, rs1, fields.rs2)
+            }
+            _ => {
+                let name = match fields.funct3 {
+                    funct3::arith::ADD => "addi",
+                    funct3::arith::SLT => "slti",
+                    funct3::arith::SLTU => "sltiu",
+                    funct3::arith::XOR => "xori",
+                    funct3::arith::OR => "ori",
+                    _ => "andi",
+                };
+                format!("{} {}, {}, {}", name, rd, rs1, i_imm)
+            }
+        },
+        opcode::REG => {
+            let name = if fields.funct7 == funct7::MULDIV {
+                match fields.funct3 {
+                    funct3::muldiv::MUL => "mul",
+                    funct3::muldiv::MULH => "mulh",
+                    funct3::muldiv::MULHSU => "mulhsu",
+                    funct3::muldiv::MULHU => "mulhu",
+                    funct3::muldiv::DIV => "div",
+                    funct3::muldiv::DIVU => "divu",
+                    funct3::muldiv::REM => "rem",
+                    _ => "remu",
+                }
+            } else {
+                let is_alt = fields.funct7 & 0x20 != 0;
+                match fields.funct3 {
+                    funct3::arith::ADD if is_alt => "sub",
+                    funct3::arith::ADD => "add",
+                    funct3::arith::SLL => "sll",
+                    funct3::arith::SLT => "slt",
+                    funct3::arith::SLTU => "sltu",
+                    funct3::arith::XOR => "xor",
+                    funct3::arith::SR if is_alt => "sra",
+                    funct3::arith::SR => "srl",
+                    funct3::arith::OR => "or",
+                    _ => "and",
+                }
+            };
+            format!("{} {}, {}, {}", name, rd, rs1, rs2)
+        }
+        opcode::SYSTEM => {
+            let csr = get_csr_name((inst >> 20) as u16);
+            match fields.funct3 {
+                funct3::system::PRIV => match (inst >> 20) as u16 {
+                    funct12::ECALL => "ecall".to_string(),
+                    funct12::EBREAK => "ebreak".to_string(),
+                    funct12::MRET => "mret".to_string(),
+                    funct12::WFI => "wfi".to_string(),
+                    _ => format!(".word 0x{:08x}", inst),
+                },
+                funct3::system::CSRRW => format!("csrrw {}, {}, {}", rd, csr, rs1),
+                funct3::system::CSRRS => format!("csrrs {}, {}, {}", rd, csr, rs1),
+                funct3::system::CSRRC => format!("csrrc {}, {}, {}", rd, csr, rs1),
+                funct3::system::CSRRWI => format!("csrrwi {}, {}, {}", rd, csr, fields.rs1),
+                funct3::system::CSRRSI => format!("csrrsi {}, {}, {}", rd, csr, fields.rs1),
+                _ => format!("csrrci {}, {}, {}", rd, csr, fields.rs1),
+            }
+        }
+        // fence
+        _ => "fence".to_string(),
+    }
+}
+
+/// The CSRs the core implements and their names
+pub const CSR_NAMES: [(u16, &str); 21] = [
+    (csr_addr::MSTATUS, "mstatus"),
+    (csr_addr::MISA, "misa"),
+    (csr_addr::MIE, "mie"),
+    (csr_addr::MTVEC, "mtvec"),
+    (csr_addr::MSCRATCH, "mscratch"),
+    (csr_addr::MEPC, "mepc"),
+    (csr_addr::MCAUSE, "mcause"),
+    (csr_addr::MTVAL, "mtval"),
+    (csr_addr::MIP, "mip"),
+    (csr_addr::MCYCLE, "mcycle"),
+    (csr_addr::MINSTRET, "minstret"),
+    (csr_addr::MCYCLEH, "mcycleh"),
+    (csr_addr::MINSTRETH, "minstreth"),
+    (csr_addr::CYCLE, "cycle"),
+    (csr_addr::INSTRET, "instret"),
+    (csr_addr::CYCLEH, "cycleh"),
+    (csr_addr::INSTRETH, "instreth"),
+    (csr_addr::MVENDORID, "mvendorid"),
+    (csr_addr::MARCHID, "marchid"),
+    (csr_addr::MIMPID, "mimpid"),
+    (csr_addr::MHARTID, "mhartid"),
+];
+
+fn get_csr_name(addr: u16) -> String {
+    CSR_NAMES
+        .iter()
+        .find(|(csr_addr, _)| *csr_addr == addr)
+        .map_or(format!("0x{:x}", addr), |(_, name)| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let pc = 0x40000000;
+        let cases = [
+            (0x00000013, "addi zero, zero, 0"),
+            (0xff010113, "addi sp, sp, -16"),
+            (0x00112623, "sw ra, 12(sp)"),
+            (0x00c12083, "lw ra, 12(sp)"),
+            (0x40b50533, "sub a0, a0, a1"),
+            (0x02b50533, "mul a0, a0, a1"),
+            (0x41f55513, "srai a0, a0, 31"),
+            (0x400002b7, "lui t0, 0x40000"),
+            (0x00008067, "jalr zero, 0(ra)"),
+            (0x008000ef, "jal ra, 0x40000008"),
+            (0xfeb50ee3, "beq a0, a1, 0x3ffffffc"),
+            (0x30529073, "csrrw zero, mtvec, t0"),
+            (0x34202573, "csrrs a0, mcause, zero"),
+            (0x00000073, "ecall"),
+            (0x30200073, "mret"),
+            (0x0ff0000f, "fence"),
+            (0x00000000, ".word 0x00000000"),
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(disassemble(inst, pc), expected, "0x{:08x}", inst);
+        }
+    }
+}
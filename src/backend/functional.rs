@@ -0,0 +1,365 @@
+use crate::backend::component::control::is_legal_instruction;
+use crate::backend::component::csr_file::CsrFile;
+use crate::backend::component::ir::IR;
+use crate::backend::component::mem_ctl::{AccessType, ClintMmioCtl, HtifMmioCtl, MemCtl};
+use crate::backend::component::muldiv;
+use crate::backend::component::regfile::Registers;
+use crate::backend::cosim::Commit;
+use crate::backend::util::types::*;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// An instruction-set model over the architectural state of `Core`, used to fast-forward
+///
+/// Every instruction completes in a single step, timing is not modelled:
+/// mcycle and the clint's mtime advance once per instruction.
+/// Unknown register values read as zero, loads keep unknown bytes like the datapath does.
+pub struct FunctionalCore<'a> {
+    pub pc: u32,
+    /// The last instruction executed
+    pub ir: Word,
+    registers: &'a mut Registers,
+    csr_file: &'a mut CsrFile,
+    mem_ctl: &'a mut MemCtl,
+    clint: &'a Mutex<ClintMmioCtl>,
+    htif: &'a Mutex<HtifMmioCtl>,
+}
+
+/// What an instruction that did not trap commits
+struct Retired {
+    next_pc: u32,
+    rd_data: Option<Word>,
+    /// (csr, op, rs1 index, rs1 data) for Zicsr instructions
+    csr_op: Option<(u16, u8, u8, u32)>,
+    is_mret: bool,
+    mem_addr: Option<u32>,
+    /// The bytes a store writes, least significant first
+    mem_wdata: Option<Vec<Option<u8>>>,
+}
+
+impl Retired {
+    fn new(next_pc: u32) -> Self {
+        Self {
+            next_pc,
+            rd_data: None,
+            csr_op: None,
+            is_mret: false,
+            mem_addr: None,
+            mem_wdata: None,
+        }
+    }
+
+    fn with_rd(next_pc: u32, rd_data: u32) -> Self {
+        Self {
+            rd_data: Some(Word::from(rd_data)),
+            ..Self::new(next_pc)
+        }
+    }
+}
+
+impl<'a> FunctionalCore<'a> {
+    pub fn new(
+        pc: u32,
+        ir: Word,
+        registers: &'a mut Registers,
+        csr_file: &'a mut CsrFile,
+        mem_ctl: &'a mut MemCtl,
+        clint: &'a Mutex<ClintMmioCtl>,
+        htif: &'a Mutex<HtifMmioCtl>,
+    ) -> Self {
+        Self {
+            pc,
+            ir,
+            registers,
+            csr_file,
+            mem_ctl,
+            clint,
+            htif,
+        }
+    }
+
+    /// Whether the program has exited through htif, is spinning on one of the end instructions
+    /// or has halted on an access fault it has no trap handler for
+    pub fn has_ended(&self) -> bool {
+        self.htif.lock().unwrap().get_exit_code().is_some()
+            || IR::is_end_instruction(self.ir)
+            || self.mem_ctl.has_unhandled_access_fault(self.csr_file.mtvec)
+    }
+
+    /// Steps until `max_steps`, the end of the program, a watchpoint,
+    /// or arriving at one of `breakpoints`, returns the number of steps taken
+    pub fn run(&mut self, max_steps: u128, breakpoints: &BTreeSet<Word>) -> u128 {
+        let mut steps = 0;
+        while steps < max_steps && !self.has_ended() {
+            self.step();
+            steps += 1;
+            if breakpoints.contains(&Word::from(self.pc)) || self.mem_ctl.watchpoint_hit.is_some() {
+                break;
+            }
+        }
+        steps
+    }
+
+    /// Takes the pending interrupt if there is one, otherwise executes the instruction at pc
+    ///
+    /// Returns the instruction if it retired, None if it trapped or an interrupt was taken
+    pub fn step(&mut self) -> Option<Commit> {
+        self.csr_file.update_mip();
+        let interrupt_cause = self.csr_file.get_interrupt_cause();
+        let result = if interrupt_cause != 0 {
+            Err((interrupt_cause, 0))
+        } else if let Err(access_fault) = self.mem_ctl.check_access(AccessType::Fetch, self.pc) {
+            Err((access_fault.get_cause(), self.pc))
+        } else {
+            self.ir = self.mem_ctl.read_word(self.pc, 0x0F, true);
+            self.execute()
+        };
+
+        // counters advance first so an explicit write wins, same as the csr file
+        self.csr_file.mcycle = self.csr_file.mcycle.wrapping_add(1);
+        let commit = match result {
+            Ok(retired) => {
+                self.csr_file.minstret = self.csr_file.minstret.wrapping_add(1);
+                if let Some((addr, op, rs1_idx, rs1_data)) = retired.csr_op {
+                    self.csr_file.execute_op(addr, op, rs1_idx, Some(rs1_data));
+                }
+                if retired.is_mret {
+                    self.csr_file.return_from_trap();
+                }
+                let rd_idx = self.get_rd_idx();
+                if let Some(rd_data) = retired.rd_data {
+                    self.registers.write(rd_idx, rd_data);
+                }
+                let commit = Commit {
+                    pc: Word::from(self.pc),
+                    inst: self.ir,
+                    rd_write: retired.rd_data.and_then(|rd_data| {
+                        Into::<Option<u8>>::into(rd_idx)
+                            .filter(|rd| *rd != 0)
+                            .map(|rd| (rd, rd_data))
+                    }),
+                    mem_addr: retired.mem_addr.map(Word::from),
+                    mem_wdata: retired.mem_wdata,
+                };
+                self.pc = retired.next_pc;
+                Some(commit)
+            }
+            Err((cause, val)) => {
+                self.csr_file.enter_trap(Some(self.pc), cause, val);
+                self.pc = self.csr_file.mtvec;
+                None
+            }
+        };
+        self.clint.lock().unwrap().tick();
+        commit
+    }
+
+    fn get_rd_idx(&self) -> Byte {
+        Into::<Option<u32>>::into(self.ir)
+            .map(|inst| Byte::from(((inst >> 7) & 0x1F) as u8))
+            .unwrap_or(Byte::unknown())
+    }
+
+    fn read_register(&self, idx: u32) -> u32 {
+        Into::<Option<u32>>::into(self.registers.read(Byte::from(idx as u8))).unwrap_or(0)
+    }
+
+    /// Executes `ir`, returns (mcause, mtval) if it traps
+    ///
+    /// Nothing is committed before it is known not to trap, other than the store itself
+    fn execute(&mut self) -> Result<Retired, (u32, u32)> {
+        let illegal = (trap_cause::ILLEGAL_INSTRUCTION, 0);
+        let Some(inst) = Into::<Option<u32>>::into(self.ir) else {
+            return Err(illegal);
+        };
+
+        let opcode = (inst & 0x7F) as u8;
+        let funct3 = ((inst >> 12) & 0x7) as u8;
+        let funct7 = ((inst >> 25) & 0x7F) as u8;
+        if !is_legal_instruction(opcode, funct3, funct7) {
+            return Err(illegal);
+        }
+
+        let rs1_idx = (inst >> 15) & 0x1F;
+        let rs1 = self.read_register(rs1_idx);
+        let rs2 = self.read_register((inst >> 20) & 0x1F);
+        let i_imm = ((inst as i32) >> 20) as u32;
+        let s_imm = (((inst & 0xFE000000) as i32) >> 20) as u32 | ((inst >> 7) & 0x1F);
+        let b_imm = (((inst & 0x80000000) as i32) >> 19) as u32
+            | ((inst & 0x80) << 4)
+            | ((inst >> 20) & 0x7E0)
+            | ((inst >> 7) & 0x1E);
+        let u_imm = inst & 0xFFFFF000;
+        let j_imm = (((inst & 0x80000000) as i32) >> 11) as u32
+            | (inst & 0xFF000)
+            | ((inst >> 9) & 0x800)
+            | ((inst >> 20) & 0x7FE);
+        let pc_plus4 = self.pc.wrapping_add(4);
+
+        let jump = |target: u32| {
+            if target & 0x3 != 0 {
+                Err((trap_cause::INSTRUCTION_ADDRESS_MISALIGNED, target))
+            } else {
+                Ok(target)
+            }
+        };
+
+        Ok(match opcode {
+            opcode::LUI => Retired::with_rd(pc_plus4, u_imm),
+            opcode::AUIPC => Retired::with_rd(pc_plus4, self.pc.wrapping_add(u_imm)),
+            opcode::JAL => Retired::with_rd(jump(self.pc.wrapping_add(j_imm))?, pc_plus4),
+            opcode::JALR => Retired::with_rd(jump(rs1.wrapping_add(i_imm) & !0x1)?, pc_plus4),
+            opcode::BR => {
+                let is_taken = match funct3 {
+                    funct3::branch::BEQ => rs1 == rs2,
+                    funct3::branch::BNE => rs1 != rs2,
+                    funct3::branch::BLT => (rs1 as i32) < (rs2 as i32),
+                    funct3::branch::BGE => (rs1 as i32) >= (rs2 as i32),
+                    funct3::branch::BLTU => rs1 < rs2,
+                    _ => rs1 >= rs2,
+                };
+                if is_taken {
+                    Retired::new(jump(self.pc.wrapping_add(b_imm))?)
+                } else {
+                    Retired::new(pc_plus4)
+                }
+            }
+            opcode::LOAD => {
+                let addr = rs1.wrapping_add(i_imm);
+                let offset = (addr & 0x3) as usize;
+                let rmask = match funct3 {
+                    funct3::load::LW => 0x0F,
+                    funct3::load::LH | funct3::load::LHU => 0x03 << offset,
+                    _ => 0x01 << offset,
+                };
+                if addr & Self::get_align_mask(funct3) != 0 {
+                    return Err((trap_cause::LOAD_ADDRESS_MISALIGNED, addr));
+                }
+                self.mem_ctl
+                    .check_access(AccessType::Load, addr)
+                    .map_err(|access_fault| (access_fault.get_cause(), addr))?;
+
+                let word = self.mem_ctl.read_word(addr, rmask, false);
+                let rd_data = match funct3 {
+                    funct3::load::LW => word,
+                    funct3::load::LH | funct3::load::LHU => {
+                        match (word[offset], word[offset + 1]) {
+                            (Some(lsb), Some(msb)) => {
+                                let half = ((msb as u16) << 8) | lsb as u16;
+                                if funct3 == funct3::load::LH {
+                                    Word::from(half as i16 as i32 as u32)
+                                } else {
+                                    Word::from(half as u32)
+                                }
+                            }
+                            _ => Word::unknown(),
+                        }
+                    }
+                    _ => match word[offset] {
+                        Some(byte) if funct3 == funct3::load::LB => {
+                            Word::from(byte as i8 as i32 as u32)
+                        }
+                        Some(byte) => Word::from(byte as u32),
+                        None => Word::unknown(),
+                    },
+                };
+                Retired {
+                    rd_data: Some(rd_data),
+                    mem_addr: Some(addr),
+                    ..Retired::new(pc_plus4)
+                }
+            }
+            opcode::STORE => {
+                let addr = rs1.wrapping_add(s_imm);
+                let offset = addr & 0x3;
+                let wmask = match funct3 {
+                    funct3::store::SW => 0x0F,
+                    funct3::store::SH => 0x03 << offset,
+                    _ => 0x01 << offset,
+                };
+                if addr & Self::get_align_mask(funct3) != 0 {
+                    return Err((trap_cause::STORE_ADDRESS_MISALIGNED, addr));
+                }
+                self.mem_ctl
+                    .check_access(AccessType::Store, addr)
+                    .map_err(|access_fault| (access_fault.get_cause(), addr))?;
+
+                self.mem_ctl.write_word(addr, wmask, Word::from(rs2));
+                Retired {
+                    mem_addr: Some(addr),
+                    mem_wdata: Some(Word::from(rs2).data[..1 << (funct3 & 0x3)].to_vec()),
+                    ..Retired::new(pc_plus4)
+                }
+            }
+            opcode::IMM => Retired::with_rd(
+                pc_plus4,
+                Self::compute(funct3, funct7 & 0x20 != 0, rs1, i_imm),
+            ),
+            opcode::REG if funct7 == funct7::MULDIV => {
+                let out = muldiv::compute(Word::from(rs1), Word::from(rs2), Byte::from(funct3));
+                Retired {
+                    rd_data: Some(out),
+                    ..Retired::new(pc_plus4)
+                }
+            }
+            opcode::REG => {
+                let is_alt = funct7 & 0x20 != 0;
+                let rs2 = if funct3 == funct3::arith::ADD && is_alt {
+                    rs2.wrapping_neg()
+                } else {
+                    rs2
+                };
+                Retired::with_rd(pc_plus4, Self::compute(funct3, is_alt, rs1, rs2))
+            }
+            opcode::SYSTEM if funct3 == funct3::system::PRIV => match (inst >> 20) as u16 {
+                funct12::ECALL => return Err((trap_cause::ECALL_FROM_M, 0)),
+                funct12::EBREAK => return Err((trap_cause::BREAKPOINT, 0)),
+                funct12::MRET => Retired {
+                    is_mret: true,
+                    ..Retired::new(self.csr_file.mepc)
+                },
+                funct12::WFI => Retired::new(pc_plus4),
+                _ => return Err(illegal),
+            },
+            opcode::SYSTEM => {
+                let addr = (inst >> 20) as u16;
+                if self.csr_file.is_illegal_op(addr, funct3, rs1_idx as u8) {
+                    return Err(illegal);
+                }
+                Retired {
+                    rd_data: self.csr_file.read(addr).map(Word::from),
+                    csr_op: Some((addr, funct3, rs1_idx as u8, rs1)),
+                    ..Retired::new(pc_plus4)
+                }
+            }
+            // fence
+            _ => Retired::new(pc_plus4),
+        })
+    }
+
+    /// The address bits that have to be zero for a load or store of this width
+    fn get_align_mask(funct3: u8) -> u32 {
+        match funct3 & 0x3 {
+            0b01 => 0x1,
+            0b10 => 0x3,
+            _ => 0x0,
+        }
+    }
+
+    /// The integer register-register and register-immediate operations,
+    /// `is_alt` selects sra over srl, subtraction is done by the caller
+    fn compute(funct3: u8, is_alt: bool, a: u32, b: u32) -> u32 {
+        match funct3 {
+            funct3::arith::ADD => a.wrapping_add(b),
+            funct3::arith::SLL => a << (b & 0x1F),
+            funct3::arith::SLT => ((a as i32) < (b as i32)) as u32,
+            funct3::arith::SLTU => (a < b) as u32,
+            funct3::arith::SR if is_alt => ((a as i32) >> (b & 0x1F)) as u32,
+            funct3::arith::SR => a >> (b & 0x1F),
+            // xor, or and and share their encoding with the alu ops
+            alu_op::XOR => a ^ b,
+            alu_op::OR => a | b,
+            _ => a & b,
+        }
+    }
+}
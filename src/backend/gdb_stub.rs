@@ -0,0 +1,373 @@
+//! A GDB remote serial protocol stub, used by the headless cli
+//!
+//! Only one client is served, the core is halted whenever gdb is not continuing or stepping.
+
+use crate::backend::core::Core;
+use crate::backend::util::types::{Byte, Word};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+/// x0-x31 followed by pc
+const NUM_REGISTERS: usize = 33;
+const PC_REGISTER: usize = 32;
+/// How many instructions to run between checks for a ctrl-c from gdb
+const INTERRUPT_POLL_INTERVAL: u32 = 1024;
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<architecture>riscv:rv32</architecture>
+<feature name="org.gnu.gdb.riscv.cpu">
+<reg name="zero" bitsize="32" type="int"/>
+<reg name="ra" bitsize="32" type="code_ptr"/>
+<reg name="sp" bitsize="32" type="data_ptr"/>
+<reg name="gp" bitsize="32" type="data_ptr"/>
+<reg name="tp" bitsize="32" type="data_ptr"/>
+<reg name="t0" bitsize="32" type="int"/>
+<reg name="t1" bitsize="32" type="int"/>
+<reg name="t2" bitsize="32" type="int"/>
+<reg name="fp" bitsize="32" type="data_ptr"/>
+<reg name="s1" bitsize="32" type="int"/>
+<reg name="a0" bitsize="32" type="int"/>
+<reg name="a1" bitsize="32" type="int"/>
+<reg name="a2" bitsize="32" type="int"/>
+<reg name="a3" bitsize="32" type="int"/>
+<reg name="a4" bitsize="32" type="int"/>
+<reg name="a5" bitsize="32" type="int"/>
+<reg name="a6" bitsize="32" type="int"/>
+<reg name="a7" bitsize="32" type="int"/>
+<reg name="s2" bitsize="32" type="int"/>
+<reg name="s3" bitsize="32" type="int"/>
+<reg name="s4" bitsize="32" type="int"/>
+<reg name="s5" bitsize="32" type="int"/>
+<reg name="s6" bitsize="32" type="int"/>
+<reg name="s7" bitsize="32" type="int"/>
+<reg name="s8" bitsize="32" type="int"/>
+<reg name="s9" bitsize="32" type="int"/>
+<reg name="s10" bitsize="32" type="int"/>
+<reg name="s11" bitsize="32" type="int"/>
+<reg name="t3" bitsize="32" type="int"/>
+<reg name="t4" bitsize="32" type="int"/>
+<reg name="t5" bitsize="32" type="int"/>
+<reg name="t6" bitsize="32" type="int"/>
+<reg name="pc" bitsize="32" type="code_ptr"/>
+</feature>
+</target>"#;
+
+pub struct GdbStub<'a, F: Fn() + Copy> {
+    core: &'a Core,
+    hook: Option<F>,
+    stream: TcpStream,
+    breakpoints: BTreeSet<Word>,
+}
+
+impl<'a, F: Fn() + Copy> GdbStub<'a, F> {
+    /// Waits for gdb on `127.0.0.1:port` and serves it until it detaches or disconnects
+    ///
+    /// `hook` is called after every cycle like in `Core::run_cycle`
+    pub fn serve(core: &'a Core, port: u16, hook: Option<F>) -> std::io::Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        eprintln!("Waiting for gdb on {}", listener.local_addr()?);
+        let (stream, addr) = listener.accept()?;
+        eprintln!("gdb connected from {}", addr);
+        stream.set_nodelay(true)?;
+
+        let mut gdb_stub = Self {
+            core,
+            hook,
+            stream,
+            breakpoints: Default::default(),
+        };
+        while let Some(packet) = gdb_stub.read_packet()? {
+            match gdb_stub.handle_packet(&packet) {
+                Some(response) => gdb_stub.write_packet(&response)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Returns `None` once gdb disconnects, acks are sent but the ones from gdb are ignored
+    fn read_packet(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                // stray acks, or ctrl-c while already halted
+                Some(_) => continue,
+            }
+
+            let mut packet = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => packet.push(byte),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected_checksum = u8::from_str_radix(&String::from_utf8_lossy(&checksum), 16);
+            if expected_checksum == Ok(Self::checksum(&packet)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&packet).to_string()));
+            } else {
+                self.stream.write_all(b"-")?;
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, Self::checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    fn checksum(data: &[u8]) -> u8 {
+        data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+    }
+
+    /// Whether gdb has sent a ctrl-c, without blocking
+    ///
+    /// Acks are dropped like `read_packet` does, anything else is left in the stream for it
+    fn is_interrupted(&mut self) -> bool {
+        let mut byte = [0u8];
+        self.stream.set_nonblocking(true).unwrap();
+        let is_interrupted = loop {
+            match self.stream.peek(&mut byte) {
+                Ok(1) if byte[0] == b'+' || byte[0] == b'-' => {
+                    self.stream.read_exact(&mut byte).ok();
+                }
+                Ok(1) if byte[0] == 0x03 => {
+                    self.stream.read_exact(&mut byte).ok();
+                    break true;
+                }
+                _ => break false,
+            }
+        };
+        self.stream.set_nonblocking(false).unwrap();
+        is_interrupted
+    }
+
+    /// `None` closes the connection
+    fn handle_packet(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let response = match command {
+            "?" => self.get_stop_reply(SIGTRAP),
+            "g" => (0..NUM_REGISTERS)
+                .map(|idx| Self::encode_word(self.read_register(idx)))
+                .collect(),
+            "G" => {
+                for (idx, chunk) in args.as_bytes().chunks(8).take(NUM_REGISTERS).enumerate() {
+                    if let Some(value) = Self::decode_word(&String::from_utf8_lossy(chunk)) {
+                        self.write_register(idx, value);
+                    }
+                }
+                "OK".to_string()
+            }
+            "p" => match parse_hex(args).map(|idx| idx as usize) {
+                Some(idx) if idx < NUM_REGISTERS => Self::encode_word(self.read_register(idx)),
+                _ => "E01".to_string(),
+            },
+            "P" => match args.split_once('=').and_then(|(idx, value)| {
+                Some((parse_hex(idx)? as usize, Self::decode_word(value)?))
+            }) {
+                Some((idx, value)) if idx < NUM_REGISTERS => {
+                    self.write_register(idx, value);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_addr_len(args) {
+                Some((addr, len)) => self.read_memory(addr, len),
+                None => "E01".to_string(),
+            },
+            "M" => match args
+                .split_once(':')
+                .and_then(|(addr_len, data)| Some((parse_addr_len(addr_len)?, decode_hex(data)?)))
+            {
+                Some(((addr, len), data)) if data.len() == len as usize => {
+                    self.write_memory(addr, &data);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.write_register(PC_REGISTER, addr);
+                }
+                self.resume(command == "s")
+            }
+            "Z" | "z" => match args.strip_prefix("0,").and_then(|args| {
+                let (addr, _kind) = args.split_once(',')?;
+                parse_hex(addr)
+            }) {
+                Some(addr) => {
+                    if command == "Z" {
+                        self.breakpoints.insert(Word::from(addr));
+                    } else {
+                        self.breakpoints.remove(&Word::from(addr));
+                    }
+                    "OK".to_string()
+                }
+                // only software breakpoints are supported
+                None => "".to_string(),
+            },
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.write_packet("OK").ok();
+                return None;
+            }
+            "k" => return None,
+            _ => self.handle_query(packet),
+        };
+        Some(response)
+    }
+
+    fn handle_query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=4000;qXfer:features:read+;swbreak+".to_string()
+        } else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_addr_len(args) {
+                Some((offset, len)) => {
+                    let offset = (offset as usize).min(TARGET_XML.len());
+                    let end = (offset + len as usize).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                    format!("{}{}", prefix, &TARGET_XML[offset..end])
+                }
+                None => "E01".to_string(),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            // unsupported
+            "".to_string()
+        }
+    }
+
+    /// Steps one instruction, or runs until a breakpoint, the program ends or gdb interrupts
+    fn resume(&mut self, is_step: bool) -> String {
+        if self.core.has_ended() {
+            return self.get_stop_reply(SIGTRAP);
+        }
+        // always make progress, even when continuing from a breakpoint
+        self.core.run_instruction(self.hook);
+        if is_step {
+            return self.get_stop_reply(SIGTRAP);
+        }
+
+        let mut num_instructions = 0u32;
+        while !self.core.has_ended() {
+            if self
+                .breakpoints
+                .contains(&self.core.pc.lock().unwrap().data_inner)
+            {
+                break;
+            }
+            num_instructions += 1;
+            if num_instructions % INTERRUPT_POLL_INTERVAL == 0 && self.is_interrupted() {
+                return self.get_stop_reply(SIGINT);
+            }
+            self.core.run_instruction(self.hook);
+        }
+        self.get_stop_reply(SIGTRAP)
+    }
+
+    fn get_stop_reply(&self, signal: u8) -> String {
+        if self.core.has_ended() {
+            format!("W{:02x}", self.core.get_exit_code().unwrap_or(0) as u8)
+        } else {
+            format!("S{:02x}", signal)
+        }
+    }
+
+    fn read_register(&self, idx: usize) -> Option<u32> {
+        if idx == PC_REGISTER {
+            self.core.pc.lock().unwrap().data_inner.into()
+        } else if idx == 0 {
+            Some(0)
+        } else {
+            self.core.regfile.lock().unwrap().registers.data[idx].into()
+        }
+    }
+
+    fn write_register(&self, idx: usize, value: u32) {
+        if idx == PC_REGISTER {
+            self.core.pc.lock().unwrap().data_inner = Word::from(value);
+        } else if idx != 0 {
+            self.core.regfile.lock().unwrap().registers.data[idx] = Word::from(value);
+        }
+    }
+
+    /// Stops at the first byte that is unknown, like a partial read
+    ///
+    /// Device registers read as the cpu would see them, without side effects like popping a FIFO
+    fn read_memory(&self, addr: u32, len: u32) -> String {
+        let mut data = String::new();
+        let get_byte = |i: u32| -> Option<u8> { self.core.peek_byte(addr.wrapping_add(i)).into() };
+        for byte in (0..len).map_while(get_byte) {
+            write!(data, "{:02x}", byte).unwrap();
+        }
+        if data.is_empty() && len != 0 {
+            "E14".to_string()
+        } else {
+            data
+        }
+    }
+
+    /// Devices get the bytes like a store would, memory can be rewound past the write
+    fn write_memory(&self, addr: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.core
+                .poke_byte(addr.wrapping_add(i as u32), Byte::from(*byte));
+        }
+    }
+
+    /// Little endian, unknown registers are reported as unavailable
+    fn encode_word(value: Option<u32>) -> String {
+        match value {
+            Some(value) => format!("{:08x}", value.swap_bytes()),
+            None => "xxxxxxxx".to_string(),
+        }
+    }
+
+    fn decode_word(data: &str) -> Option<u32> {
+        parse_hex(data)
+            .filter(|_| data.len() == 8)
+            .map(u32::swap_bytes)
+    }
+}
+
+fn parse_hex(data: &str) -> Option<u32> {
+    u32::from_str_radix(data, 16).ok()
+}
+
+/// Parses `addr,len`
+fn parse_addr_len(data: &str) -> Option<(u32, u32)> {
+    let (addr, len) = data.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,256 @@
+use crate::backend::component::cache::Cache;
+use crate::backend::component::mem_ctl::{ClintMmioCtl, HtifMmioCtl, KeyboardMmioCtl, VgaMmioCtl};
+use crate::backend::component::regfile::Registers;
+use crate::backend::util::paged_mem::PagedMem;
+use crate::backend::util::types::{Byte, States, Word};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Old values of the bytes written to `MemCtl::backend_mem`, `None` if the byte did not exist
+pub type MemUndoLog = BTreeMap<Word, Option<Byte>>;
+
+/// Everything needed to bring `Core` back to an instruction boundary
+///
+/// Memory is not copied, it is rolled back through the undo logs instead
+#[derive(Clone)]
+pub struct Snapshot {
+    pub cycles_ran: u128,
+    pub instructions_ran: u128,
+    /// Whether the datapath has settled on the first fetch, false for the snapshot right after a reset
+    pub is_settled: bool,
+    pub state: States,
+    pub pc: Word,
+    pub ir: Word,
+    pub registers: Registers,
+    pub mstatus: u32,
+    pub mie: u32,
+    pub mip: u32,
+    pub mtvec: u32,
+    pub mscratch: u32,
+    pub mepc: u32,
+    pub mcause: u32,
+    pub mtval: u32,
+    pub mcycle: u64,
+    pub minstret: u64,
+    pub keyboard_mmio_ctl: KeyboardMmioCtl,
+    pub vga_mmio_ctl: VgaMmioCtl,
+    pub clint_mmio_ctl: ClintMmioCtl,
+    pub htif_mmio_ctl: HtifMmioCtl,
+    pub icache: Cache,
+    pub dcache: Cache,
+    /// Writes from this snapshot up to the next one
+    pub mem_undo_log: MemUndoLog,
+}
+
+/// Snapshots taken every `SNAPSHOT_INTERVAL` cycles, plus the cycle each instruction started at
+#[derive(Default)]
+pub struct History {
+    snapshots: VecDeque<Snapshot>,
+    instruction_boundaries: VecDeque<u128>,
+    /// Bytes typed on the console and the cycle they arrived at, handed over again when running
+    /// forward from a snapshot
+    inputs: VecDeque<(u128, u8)>,
+}
+
+impl History {
+    pub const SNAPSHOT_INTERVAL: u128 = 1000;
+    pub const MAX_SNAPSHOTS: usize = 1000;
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.instruction_boundaries.clear();
+        self.inputs.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn get_latest_snapshot(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    pub fn is_snapshot_due(&self, cycles_ran: u128) -> bool {
+        self.snapshots
+            .back()
+            .is_none_or(|snapshot| cycles_ran - snapshot.cycles_ran >= Self::SNAPSHOT_INTERVAL)
+    }
+
+    /// `mem_undo_log` are the writes since the last snapshot
+    pub fn push_snapshot(&mut self, snapshot: Snapshot, mem_undo_log: MemUndoLog) {
+        if let Some(last_snapshot) = self.snapshots.back_mut() {
+            last_snapshot.mem_undo_log = mem_undo_log;
+        }
+        self.instruction_boundaries.push_back(snapshot.cycles_ran);
+        self.snapshots.push_back(snapshot);
+
+        if self.snapshots.len() > Self::MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+            let oldest_cycle = self.snapshots.front().unwrap().cycles_ran;
+            while self
+                .instruction_boundaries
+                .front()
+                .is_some_and(|cycle| *cycle < oldest_cycle)
+            {
+                self.instruction_boundaries.pop_front();
+            }
+            while self
+                .inputs
+                .front()
+                .is_some_and(|(cycle, _)| *cycle < oldest_cycle)
+            {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    pub fn push_instruction_boundary(&mut self, cycles_ran: u128) {
+        if self.instruction_boundaries.back() != Some(&cycles_ran) {
+            self.instruction_boundaries.push_back(cycles_ran);
+        }
+    }
+
+    pub fn push_input(&mut self, cycles_ran: u128, input: u8) {
+        self.inputs.push_back((cycles_ran, input));
+    }
+
+    /// The inputs that arrived at or after `cycles_ran`, oldest first
+    pub fn get_inputs_since(&self, cycles_ran: u128) -> Vec<(u128, u8)> {
+        self.inputs
+            .iter()
+            .filter(|(cycle, _)| *cycle >= cycles_ran)
+            .copied()
+            .collect()
+    }
+
+    /// The cycle the latest instruction that started before `cycles_ran` started at
+    pub fn get_previous_instruction_boundary(&self, cycles_ran: u128) -> Option<u128> {
+        self.instruction_boundaries
+            .iter()
+            .rev()
+            .find(|cycle| **cycle < cycles_ran)
+            .copied()
+    }
+
+    /// Drops everything after the latest snapshot at or before `cycles_ran` and returns it,
+    /// with `backend_mem` rolled back to it
+    ///
+    /// The inputs up to `cycles_ran` are kept for running forward again, later ones are dropped
+    ///
+    /// `mem_undo_log` are the writes since the last snapshot, it is emptied
+    pub fn rewind(
+        &mut self,
+        cycles_ran: u128,
+        mem_undo_log: &mut MemUndoLog,
+        backend_mem: &mut PagedMem,
+    ) -> Option<Snapshot> {
+        let idx = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.cycles_ran <= cycles_ran)?;
+
+        Self::undo(std::mem::take(mem_undo_log), backend_mem);
+        while self.snapshots.len() > idx + 1 {
+            self.snapshots.pop_back();
+            let last_snapshot = self.snapshots.back_mut().unwrap();
+            Self::undo(std::mem::take(&mut last_snapshot.mem_undo_log), backend_mem);
+        }
+        let snapshot = self.snapshots.back().unwrap().clone();
+        while self
+            .instruction_boundaries
+            .back()
+            .is_some_and(|cycle| *cycle > snapshot.cycles_ran)
+        {
+            self.instruction_boundaries.pop_back();
+        }
+        while self
+            .inputs
+            .back()
+            .is_some_and(|(cycle, _)| *cycle >= cycles_ran)
+        {
+            self.inputs.pop_back();
+        }
+
+        Some(snapshot)
+    }
+
+    /// Puts back the old values in `mem_undo_log`
+    pub fn undo(mem_undo_log: MemUndoLog, backend_mem: &mut PagedMem) {
+        for (addr, byte) in mem_undo_log {
+            if let Some(addr) = addr.into() {
+                backend_mem.insert(addr, byte.unwrap_or(Byte::unknown()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_snapshot(cycles_ran: u128) -> Snapshot {
+        Snapshot {
+            cycles_ran,
+            instructions_ran: 0,
+            is_settled: true,
+            state: States::Fetch,
+            pc: Word::from(0u32),
+            ir: Word::from(0u32),
+            registers: Default::default(),
+            mstatus: 0,
+            mie: 0,
+            mip: 0,
+            mtvec: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            mcycle: 0,
+            minstret: 0,
+            keyboard_mmio_ctl: KeyboardMmioCtl::new(),
+            vga_mmio_ctl: VgaMmioCtl::new(),
+            clint_mmio_ctl: ClintMmioCtl::new(),
+            htif_mmio_ctl: HtifMmioCtl::new(),
+            icache: Default::default(),
+            dcache: Default::default(),
+            mem_undo_log: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_history_rewind() {
+        let addr = Word::from(0x100u32);
+        let mut history = History::default();
+        let mut backend_mem = PagedMem::new();
+        backend_mem.insert(0x100, Byte::from(1u8));
+        history.push_snapshot(new_snapshot(0), Default::default());
+
+        backend_mem.insert(0x100, Byte::from(2u8));
+        history.push_snapshot(
+            new_snapshot(10),
+            MemUndoLog::from([(addr, Some(Byte::from(1u8)))]),
+        );
+        history.push_instruction_boundary(14);
+
+        backend_mem.insert(0x100, Byte::from(3u8));
+        let mut mem_undo_log = MemUndoLog::from([(addr, Some(Byte::from(2u8)))]);
+        history.push_input(11, b'a');
+        history.push_input(13, b'b');
+        assert_eq!(history.get_previous_instruction_boundary(16), Some(14));
+        assert_eq!(history.get_previous_instruction_boundary(14), Some(10));
+
+        let snapshot = history
+            .rewind(12, &mut mem_undo_log, &mut backend_mem)
+            .unwrap();
+        assert_eq!(snapshot.cycles_ran, 10);
+        assert_eq!(backend_mem.get(0x100), Byte::from(2u8));
+        assert_eq!(history.get_previous_instruction_boundary(16), Some(10));
+        assert_eq!(history.get_inputs_since(10), vec![(11, b'a')]);
+
+        let snapshot = history
+            .rewind(5, &mut mem_undo_log, &mut backend_mem)
+            .unwrap();
+        assert_eq!(snapshot.cycles_ran, 0);
+        assert_eq!(backend_mem.get(0x100), Byte::from(1u8));
+        assert!(history.get_inputs_since(0).is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::backend::util::types::{Byte, Word};
+use std::collections::BTreeMap;
+
+/// A memory image from the assembler or one of the loaders, loaded the same way as an ELF
+#[derive(Debug)]
+pub struct Program {
+    pub mem: BTreeMap<Word, Byte>,
+    pub label: BTreeMap<Word, String>,
+    pub entry: u32,
+}
+
+impl Program {
+    fn new(entry: u32) -> Self {
+        Program {
+            mem: BTreeMap::new(),
+            label: BTreeMap::new(),
+            entry,
+        }
+    }
+
+    fn insert(&mut self, addr: u32, byte: Byte) {
+        self.mem.insert(Word::from(addr), byte);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    Elf,
+    /// A flat binary, loaded at a given base address
+    Binary,
+    IntelHex,
+    /// The text format of verilog's `$readmemh`
+    Readmemh,
+}
+
+impl ImageFormat {
+    /// Guesses the format from the ELF magic, the file extension or the text itself
+    pub fn detect(file_name: &str, data: &[u8]) -> ImageFormat {
+        if data.starts_with(b"\x7fELF") {
+            return ImageFormat::Elf;
+        }
+        let extension = file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        if extension.as_deref() == Some("bin") {
+            return ImageFormat::Binary;
+        }
+        let Ok(text) = std::str::from_utf8(data) else {
+            return ImageFormat::Binary;
+        };
+        if text.trim_start().starts_with(':') {
+            ImageFormat::IntelHex
+        } else if !text.trim().is_empty() && is_readmemh(text) {
+            ImageFormat::Readmemh
+        } else {
+            ImageFormat::Binary
+        }
+    }
+}
+
+impl std::fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ImageFormat::Elf => "ELF",
+            ImageFormat::Binary => "binary",
+            ImageFormat::IntelHex => "Intel HEX",
+            ImageFormat::Readmemh => "readmemh",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Loads `data` as is at `base`, execution starts at `base`
+pub fn load_binary(data: &[u8], base: u32) -> Program {
+    let mut program = Program::new(base);
+    for (i, byte) in data.iter().enumerate() {
+        program.insert(base.wrapping_add(i as u32), (*byte).into());
+    }
+    program
+}
+
+/// Loads Intel HEX records, every record's checksum is verified
+///
+/// Execution starts at the start address record if there is one, otherwise at the lowest address
+pub fn load_ihex(text: &str) -> Result<Program, String> {
+    let mut program = Program::new(0);
+    let mut entry = None;
+    // from the extended segment and extended linear address records
+    let mut upper_addr = 0u32;
+    let mut is_end = false;
+    for (line, record) in text.lines().enumerate() {
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", line + 1, message);
+        if is_end {
+            return Err(error("record after the end of file record"));
+        }
+        let record = record
+            .strip_prefix(':')
+            .ok_or_else(|| error("record does not start with ':'"))?;
+        if record.len() % 2 != 0 {
+            return Err(error("odd number of hex digits"));
+        }
+        let bytes = (0..record.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(record.get(i..i + 2).unwrap_or("-"), 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error("invalid hex digit"))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(error("record length does not match its byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let get_u16 = || {
+            if data.len() == 2 {
+                Ok(u16::from_be_bytes([data[0], data[1]]) as u32)
+            } else {
+                Err(error("expected 2 data bytes"))
+            }
+        };
+        let get_u32 = || {
+            if data.len() == 4 {
+                Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+            } else {
+                Err(error("expected 4 data bytes"))
+            }
+        };
+        match bytes[3] {
+            // data
+            0x00 => {
+                for (i, byte) in data.iter().enumerate() {
+                    let addr = upper_addr.wrapping_add((offset + i as u32) & 0xffff);
+                    program.insert(addr, (*byte).into());
+                }
+            }
+            // end of file
+            0x01 => is_end = true,
+            // extended segment address
+            0x02 => upper_addr = get_u16()? << 4,
+            // start segment address, cs:ip
+            0x03 => {
+                let cs_ip = get_u32()?;
+                entry = Some(((cs_ip >> 16) << 4).wrapping_add(cs_ip & 0xffff));
+            }
+            // extended linear address
+            0x04 => upper_addr = get_u16()? << 16,
+            // start linear address
+            0x05 => entry = Some(get_u32()?),
+            record_type => return Err(error(&format!("unknown record type {:02X}", record_type))),
+        }
+    }
+    if !is_end {
+        return Err("missing end of file record".to_string());
+    }
+    program.entry = entry
+        .or_else(|| program.mem.keys().next().and_then(|addr| (*addr).into()))
+        .unwrap_or(0);
+    Ok(program)
+}
+
+/// Loads the text format of verilog's `$readmemh`, execution starts at `base`
+///
+/// Every value is a word of the widest value in the file, which is 1, 2 or 4 bytes,
+/// stored little endian from `base` on. `@` addresses count in those words like in verilog,
+/// and an `x` or `z` digit leaves its byte unknown
+pub fn load_readmemh(text: &str, base: u32) -> Result<Program, String> {
+    let tokens = tokenize_readmemh(text)?;
+    let max_digits = tokens
+        .iter()
+        .filter(|(_, token)| !token.starts_with('@'))
+        .map(|(_, token)| token.len())
+        .max()
+        .unwrap_or(0);
+    let num_bytes = match max_digits {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=8 => 4,
+        _ => return Err(format!("values wider than 32 bits ({} digits)", max_digits)),
+    };
+
+    let mut program = Program::new(base);
+    let mut index = 0u32;
+    for (line, token) in tokens {
+        let error = |message: &str| format!("line {}: {}", line + 1, message);
+        if let Some(addr) = token.strip_prefix('@') {
+            index = u32::from_str_radix(addr, 16).map_err(|_| error("invalid address"))?;
+            continue;
+        }
+        if !token
+            .chars()
+            .all(|digit| digit.is_ascii_hexdigit() || is_unknown_digit(digit))
+        {
+            return Err(error(&format!("invalid value {}", token)));
+        }
+        // least significant digit first
+        let mut digits = token.chars().rev();
+        let addr = base.wrapping_add(index.wrapping_mul(num_bytes));
+        for i in 0..num_bytes {
+            let low = digits.next().unwrap_or('0');
+            let high = digits.next().unwrap_or('0');
+            let byte = match (low.to_digit(16), high.to_digit(16)) {
+                (Some(low), Some(high)) => Byte::from(((high << 4) | low) as u8),
+                _ => Byte::unknown(),
+            };
+            program.insert(addr.wrapping_add(i), byte);
+        }
+        index = index.wrapping_add(1);
+    }
+    Ok(program)
+}
+
+fn is_unknown_digit(digit: char) -> bool {
+    matches!(digit, 'x' | 'X' | 'z' | 'Z' | '?')
+}
+
+/// The words of the file with their zero based line number, without comments and `_`
+fn tokenize_readmemh(text: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut tokens = Vec::new();
+    let mut is_block_comment = false;
+    for (line, mut rest) in text.lines().enumerate() {
+        loop {
+            if is_block_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        is_block_comment = false;
+                        rest = &rest[end + 2..];
+                    }
+                    None => break,
+                }
+            }
+            let (code, comment) = match (rest.find("//"), rest.find("/*")) {
+                (Some(line_comment), Some(block_comment)) if block_comment < line_comment => {
+                    (&rest[..block_comment], Some(&rest[block_comment + 2..]))
+                }
+                (Some(line_comment), _) => (&rest[..line_comment], None),
+                (None, Some(block_comment)) => {
+                    (&rest[..block_comment], Some(&rest[block_comment + 2..]))
+                }
+                (None, None) => (rest, None),
+            };
+            tokens.extend(
+                code.split_whitespace()
+                    .map(|token| (line, token.replace('_', ""))),
+            );
+            match comment {
+                Some(comment) => {
+                    is_block_comment = true;
+                    rest = comment;
+                }
+                None => break,
+            }
+        }
+    }
+    if is_block_comment {
+        return Err("unterminated block comment".to_string());
+    }
+    Ok(tokens)
+}
+
+fn is_readmemh(text: &str) -> bool {
+    tokenize_readmemh(text).is_ok_and(|tokens| {
+        tokens.iter().all(|(_, token)| {
+            let digits = token.strip_prefix('@').unwrap_or(token);
+            !digits.is_empty()
+                && digits
+                    .chars()
+                    .all(|digit| digit.is_ascii_hexdigit() || is_unknown_digit(digit))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_byte(program: &Program, addr: u32) -> Option<u8> {
+        program
+            .mem
+            .get(&Word::from(addr))
+            .copied()
+            .and_then(Into::into)
+    }
+
+    #[test]
+    fn test_load_binary() {
+        let program = load_binary(&[0x13, 0x00, 0x00, 0x00], 0x40000000);
+        assert_eq!(program.entry, 0x40000000);
+        assert_eq!(program.mem.len(), 4);
+        assert_eq!(get_byte(&program, 0x40000000), Some(0x13));
+        assert_eq!(get_byte(&program, 0x40000003), Some(0x00));
+    }
+
+    #[test]
+    fn test_load_ihex() {
+        let text = ":020000044000BA
+:0400000013000000E9
+:04000400B700004001
+:0400000540000004B3
+:00000001FF
+";
+        let program = load_ihex(text).unwrap();
+        assert_eq!(program.entry, 0x40000004);
+        assert_eq!(program.mem.len(), 8);
+        assert_eq!(get_byte(&program, 0x40000000), Some(0x13));
+        assert_eq!(get_byte(&program, 0x40000004), Some(0xb7));
+        assert_eq!(get_byte(&program, 0x40000007), Some(0x40));
+
+        // without a start address
+        let program = load_ihex(":020000044000BA\n:0100100013DC\n:00000001FF\n").unwrap();
+        assert_eq!(program.entry, 0x40000010);
+
+        let errors = [
+            (
+                ":0400000013000000E8\n:00000001FF",
+                "line 1: checksum mismatch",
+            ),
+            (":0400000013000000E9\n", "missing end of file record"),
+            (
+                "0400000013000000E9\n:00000001FF",
+                "line 1: record does not start with ':'",
+            ),
+            (":00000006FA\n:00000001FF", "line 1: unknown record type 06"),
+        ];
+        for (text, expected) in errors {
+            assert_eq!(load_ihex(text).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn test_load_readmemh() {
+        let text = "// a comment
+@0
+00000013 /* nop */ 4000_02b7
+@4 xxxxxx13
+";
+        let program = load_readmemh(text, 0x40000000).unwrap();
+        assert_eq!(program.entry, 0x40000000);
+        assert_eq!(get_byte(&program, 0x40000000), Some(0x13));
+        assert_eq!(get_byte(&program, 0x40000004), Some(0xb7));
+        assert_eq!(get_byte(&program, 0x40000007), Some(0x40));
+        // words, not bytes
+        assert_eq!(get_byte(&program, 0x40000010), Some(0x13));
+        assert_eq!(
+            program.mem.get(&Word::from(0x40000011u32)),
+            Some(&Byte::unknown())
+        );
+
+        // bytes, shorter values are zero extended
+        let program = load_readmemh("13 0\n/* multi\nline */ b7", 0x100).unwrap();
+        assert_eq!(program.mem.len(), 3);
+        assert_eq!(get_byte(&program, 0x101), Some(0x00));
+        assert_eq!(get_byte(&program, 0x102), Some(0xb7));
+
+        assert_eq!(
+            load_readmemh("00000013\n0000001g", 0).unwrap_err(),
+            "line 2: invalid value 0000001g"
+        );
+        assert!(load_readmemh("123456789", 0).is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            ImageFormat::detect("a.elf", b"\x7fELF\x01\x01"),
+            ImageFormat::Elf
+        );
+        assert_eq!(
+            ImageFormat::detect("a.bin", b"00000013"),
+            ImageFormat::Binary
+        );
+        assert_eq!(
+            ImageFormat::detect("a.hex", b":00000001FF\n"),
+            ImageFormat::IntelHex
+        );
+        assert_eq!(
+            ImageFormat::detect("a.hex", b"@0\n00000013 // nop\n"),
+            ImageFormat::Readmemh
+        );
+        assert_eq!(
+            ImageFormat::detect("a.mem", b"00000013\n"),
+            ImageFormat::Readmemh
+        );
+        assert_eq!(
+            ImageFormat::detect("a", &[0x13, 0x00, 0x00, 0x00]),
+            ImageFormat::Binary
+        );
+    }
+}
@@ -1,3 +1,13 @@
+pub mod assembler;
+pub mod checkpoint;
 pub mod component;
 pub mod core;
+pub mod cosim;
+pub mod disassembler;
+pub mod functional;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb_stub;
+pub mod history;
+pub mod loader;
 pub mod util;
+pub mod vcd;
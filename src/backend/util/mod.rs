@@ -2,4 +2,5 @@ pub mod blackhole;
 pub mod byte;
 pub mod event;
 pub mod helper;
+pub mod paged_mem;
 pub mod types;
@@ -0,0 +1,266 @@
+use crate::backend::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
+use crate::backend::util::types::{Byte, Word};
+use std::collections::BTreeMap;
+
+pub const PAGE_SIZE: usize = 0x1000;
+const PAGE_SHIFT: u32 = PAGE_SIZE.trailing_zeros();
+
+/// The bytes of a page, with a bit in `known` for each one that holds a value
+#[derive(Clone, PartialEq)]
+struct Page {
+    data: [u8; PAGE_SIZE],
+    known: [u64; PAGE_SIZE / 64],
+}
+
+impl Page {
+    fn new() -> Self {
+        Page {
+            data: [0; PAGE_SIZE],
+            known: [0; PAGE_SIZE / 64],
+        }
+    }
+
+    fn get(&self, offset: usize) -> Option<u8> {
+        self.is_known(offset).then_some(self.data[offset])
+    }
+
+    fn is_known(&self, offset: usize) -> bool {
+        (self.known[offset / 64] >> (offset % 64)) & 0x1 == 0x1
+    }
+
+    fn set(&mut self, offset: usize, value: Option<u8>) {
+        match value {
+            Some(value) => {
+                self.data[offset] = value;
+                self.known[offset / 64] |= 1 << (offset % 64);
+            }
+            None => self.known[offset / 64] &= !(1 << (offset % 64)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.known.iter().all(|bits| *bits == 0)
+    }
+
+    fn len(&self) -> usize {
+        self.known
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum()
+    }
+}
+
+/// Byte addressed memory made of 4 KiB pages, a page is only allocated once something is in it
+///
+/// Bytes that were never written, or were written as unknown, read as unknown
+#[derive(Clone, Default, PartialEq)]
+pub struct PagedMem {
+    pages: BTreeMap<u32, Box<Page>>,
+}
+
+impl PagedMem {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn split(addr: u32) -> (u32, usize) {
+        (addr >> PAGE_SHIFT, addr as usize & (PAGE_SIZE - 1))
+    }
+
+    pub fn get(&self, addr: u32) -> Byte {
+        let (page_idx, offset) = Self::split(addr);
+        Byte {
+            data: [self.pages.get(&page_idx).and_then(|page| page.get(offset))],
+        }
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        let (page_idx, offset) = Self::split(addr);
+        self.pages
+            .get(&page_idx)
+            .is_some_and(|page| page.is_known(offset))
+    }
+
+    /// Returns the old value like `BTreeMap::insert`, `None` if it was unknown
+    pub fn insert(&mut self, addr: u32, byte: Byte) -> Option<Byte> {
+        let Some(value) = byte.data[0] else {
+            return self.remove(addr);
+        };
+        let (page_idx, offset) = Self::split(addr);
+        let page = self
+            .pages
+            .entry(page_idx)
+            .or_insert_with(|| Box::new(Page::new()));
+        let old_value = page.get(offset);
+        page.set(offset, Some(value));
+        old_value.map(Byte::from)
+    }
+
+    /// Makes the byte unknown, freeing its page once the page is empty
+    pub fn remove(&mut self, addr: u32) -> Option<Byte> {
+        let (page_idx, offset) = Self::split(addr);
+        let page = self.pages.get_mut(&page_idx)?;
+        let old_value = page.get(offset)?;
+        page.set(offset, None);
+        if page.is_empty() {
+            self.pages.remove(&page_idx);
+        }
+        Some(Byte::from(old_value))
+    }
+
+    /// Reads `len` bytes from `addr` a page at a time, wrapping around the address space
+    pub fn read_bytes(&self, addr: u32, len: usize) -> Vec<Byte> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut addr = addr;
+        while bytes.len() < len {
+            let (page_idx, offset) = Self::split(addr);
+            let chunk_len = (len - bytes.len()).min(PAGE_SIZE - offset);
+            match self.pages.get(&page_idx) {
+                Some(page) => bytes.extend((offset..offset + chunk_len).map(|offset| Byte {
+                    data: [page.get(offset)],
+                })),
+                None => bytes.extend(std::iter::repeat_n(Byte::unknown(), chunk_len)),
+            }
+            addr = addr.wrapping_add(chunk_len as u32);
+        }
+        bytes
+    }
+
+    /// Writes `data` to `addr` a page at a time, wrapping around the address space
+    pub fn write_bytes(&mut self, addr: u32, data: &[u8]) {
+        let mut addr = addr;
+        for chunk in Self::chunks(addr, data) {
+            let (page_idx, offset) = Self::split(addr);
+            let page = self
+                .pages
+                .entry(page_idx)
+                .or_insert_with(|| Box::new(Page::new()));
+            for (i, value) in chunk.iter().enumerate() {
+                page.set(offset + i, Some(*value));
+            }
+            addr = addr.wrapping_add(chunk.len() as u32);
+        }
+    }
+
+    /// `data` cut at the page boundaries after `addr`
+    fn chunks(addr: u32, data: &[u8]) -> impl Iterator<Item = &[u8]> {
+        let first_len = data.len().min(PAGE_SIZE - Self::split(addr).1);
+        let (first, rest) = data.split_at(first_len);
+        std::iter::once(first)
+            .filter(|chunk| !chunk.is_empty())
+            .chain(rest.chunks(PAGE_SIZE))
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Number of known bytes
+    pub fn len(&self) -> usize {
+        self.pages.values().map(|page| page.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// The known bytes in address order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u8)> + '_ {
+        self.pages.iter().flat_map(|(page_idx, page)| {
+            (0..PAGE_SIZE).filter_map(move |offset| {
+                page.get(offset)
+                    .map(|value| ((page_idx << PAGE_SHIFT) | offset as u32, value))
+            })
+        })
+    }
+}
+
+impl Extend<(Word, Byte)> for PagedMem {
+    fn extend<I: IntoIterator<Item = (Word, Byte)>>(&mut self, iter: I) {
+        for (addr, byte) in iter {
+            if let Some(addr) = addr.into() {
+                self.insert(addr, byte);
+            }
+        }
+    }
+}
+
+/// Saved the same way as a `BTreeMap<Word, Byte>` of the known bytes
+impl Checkpoint for PagedMem {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        (self.len() as u32).save(writer);
+        for (addr, value) in self.iter() {
+            Word::from(addr).save(writer);
+            Byte::from(value).save(writer);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> Result<Self, String> {
+        let mut mem = PagedMem::new();
+        mem.extend(BTreeMap::<Word, Byte>::load(reader)?);
+        Ok(mem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paged_mem() {
+        let mut mem = PagedMem::new();
+        assert_eq!(mem.insert(0x1FFF, Byte::from(0x12u8)), None);
+        mem.write_bytes(0xFFFFFFFE, &[0xAB, 0xCD, 0xEF]);
+        assert_eq!(
+            mem.insert(0x1FFF, Byte::from(0x34u8)),
+            Some(Byte::from(0x12u8))
+        );
+        assert_eq!(mem.get(0x1FFF), Byte::from(0x34u8));
+        assert_eq!(mem.get(0x2000), Byte::unknown());
+        assert!(mem.contains(0x0));
+        assert!(!mem.contains(0x1));
+        assert_eq!(mem.len(), 4);
+        assert_eq!(mem.iter().collect::<Vec<_>>(), vec![
+            (0x0, 0xEF),
+            (0x1FFF, 0x34),
+            (0xFFFFFFFE, 0xAB),
+            (0xFFFFFFFF, 0xCD)
+        ]);
+
+        assert_eq!(mem.read_bytes(0x1FFE, 3), vec![
+            Byte::unknown(),
+            Byte::from(0x34u8),
+            Byte::unknown()
+        ]);
+        assert_eq!(mem.read_bytes(0xFFFFFFFF, 2), vec![
+            Byte::from(0xCDu8),
+            Byte::from(0xEFu8)
+        ]);
+
+        // unknown bytes are not stored, an empty page is freed
+        assert_eq!(
+            mem.insert(0x1FFF, Byte::unknown()),
+            Some(Byte::from(0x34u8))
+        );
+        assert!(!mem.contains(0x1FFF));
+        assert_eq!(mem.pages.len(), 2);
+        assert_eq!(mem.remove(0x0), Some(Byte::from(0xEFu8)));
+        assert_eq!(mem.remove(0x0), None);
+        assert_eq!(mem.pages.len(), 1);
+    }
+
+    #[test]
+    fn test_paged_mem_write_across_pages() {
+        let mut mem = PagedMem::new();
+        let data = (0..2 * PAGE_SIZE + 2).map(|i| i as u8).collect::<Vec<_>>();
+        mem.write_bytes(0x40000FFF, &data);
+        assert_eq!(mem.pages.len(), 4);
+        assert_eq!(mem.len(), data.len());
+        assert_eq!(
+            mem.read_bytes(0x40000FFF, data.len()),
+            data.iter()
+                .map(|value| Byte::from(*value))
+                .collect::<Vec<_>>()
+        );
+    }
+}
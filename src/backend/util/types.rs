@@ -9,6 +9,8 @@ pub mod mux_sel {
         pub const PC_PLUS4: u8 = 0x00;
         pub const ALU_OUT: u8 = 0x01;
         pub const ALU_MOD2: u8 = 0x02;
+        pub const MTVEC: u8 = 0x03;
+        pub const MEPC: u8 = 0x04;
     }
 
     pub mod mem_addr {
@@ -45,6 +47,8 @@ pub mod mux_sel {
         pub const LBU: u8 = 0x06;
         pub const LH: u8 = 0x07;
         pub const LHU: u8 = 0x08;
+        pub const MULDIV_OUT: u8 = 0x09;
+        pub const CSR_OUT: u8 = 0x0A;
     }
 }
 
@@ -58,6 +62,8 @@ pub mod opcode {
     pub const STORE: u8 = 0b00100011;
     pub const IMM: u8 = 0b00010011;
     pub const REG: u8 = 0b00110011;
+    pub const SYSTEM: u8 = 0b01110011;
+    pub const MISC_MEM: u8 = 0b00001111;
 }
 
 pub mod funct3 {
@@ -86,10 +92,83 @@ pub mod funct3 {
 
     pub mod arith {
         pub const ADD: u8 = 0b000;
+        pub const SLL: u8 = 0b001;
         pub const SLT: u8 = 0b010;
         pub const SLTU: u8 = 0b011;
+        pub const XOR: u8 = 0b100;
         pub const SR: u8 = 0b101;
+        pub const OR: u8 = 0b110;
+        pub const AND: u8 = 0b111;
     }
+
+    pub mod muldiv {
+        pub const MUL: u8 = 0b000;
+        pub const MULH: u8 = 0b001;
+        pub const MULHSU: u8 = 0b010;
+        pub const MULHU: u8 = 0b011;
+        pub const DIV: u8 = 0b100;
+        pub const DIVU: u8 = 0b101;
+        pub const REM: u8 = 0b110;
+        pub const REMU: u8 = 0b111;
+    }
+    pub mod system {
+        pub const PRIV: u8 = 0b000;
+        pub const CSRRW: u8 = 0b001;
+        pub const CSRRS: u8 = 0b010;
+        pub const CSRRC: u8 = 0b011;
+        pub const CSRRWI: u8 = 0b101;
+        pub const CSRRSI: u8 = 0b110;
+        pub const CSRRCI: u8 = 0b111;
+    }
+}
+
+pub mod funct12 {
+    pub const ECALL: u16 = 0x000;
+    pub const EBREAK: u16 = 0x001;
+    pub const WFI: u16 = 0x105;
+    pub const MRET: u16 = 0x302;
+}
+
+pub mod trap_cause {
+    pub const INSTRUCTION_ADDRESS_MISALIGNED: u32 = 0;
+    pub const INSTRUCTION_ACCESS_FAULT: u32 = 1;
+    pub const ILLEGAL_INSTRUCTION: u32 = 2;
+    pub const BREAKPOINT: u32 = 3;
+    pub const LOAD_ADDRESS_MISALIGNED: u32 = 4;
+    pub const LOAD_ACCESS_FAULT: u32 = 5;
+    pub const STORE_ADDRESS_MISALIGNED: u32 = 6;
+    pub const STORE_ACCESS_FAULT: u32 = 7;
+    pub const ECALL_FROM_M: u32 = 11;
+    pub const MACHINE_TIMER_INTERRUPT: u32 = 0x8000_0007;
+    pub const MACHINE_EXTERNAL_INTERRUPT: u32 = 0x8000_000B;
+}
+
+pub mod csr_addr {
+    pub const MSTATUS: u16 = 0x300;
+    pub const MISA: u16 = 0x301;
+    pub const MIE: u16 = 0x304;
+    pub const MTVEC: u16 = 0x305;
+    pub const MSCRATCH: u16 = 0x340;
+    pub const MEPC: u16 = 0x341;
+    pub const MCAUSE: u16 = 0x342;
+    pub const MTVAL: u16 = 0x343;
+    pub const MIP: u16 = 0x344;
+    pub const MCYCLE: u16 = 0xB00;
+    pub const MINSTRET: u16 = 0xB02;
+    pub const MCYCLEH: u16 = 0xB80;
+    pub const MINSTRETH: u16 = 0xB82;
+    pub const CYCLE: u16 = 0xC00;
+    pub const INSTRET: u16 = 0xC02;
+    pub const CYCLEH: u16 = 0xC80;
+    pub const INSTRETH: u16 = 0xC82;
+    pub const MVENDORID: u16 = 0xF11;
+    pub const MARCHID: u16 = 0xF12;
+    pub const MIMPID: u16 = 0xF13;
+    pub const MHARTID: u16 = 0xF14;
+}
+
+pub mod funct7 {
+    pub const MULDIV: u8 = 0b0000001;
 }
 
 pub mod alu_op {
@@ -103,17 +182,22 @@ pub mod alu_op {
     pub const AND: u8 = 0b111;
 }
 
+/// The discriminants are saved in checkpoints, new states take new numbers
 #[derive(Display, Copy, Clone, Debug, PartialEq, Eq, Hash, EnumIter)]
+#[repr(u32)]
 pub enum States {
-    Fetch,
-    Decode,
-    Imm,
-    Lui,
-    Br,
-    Auipc,
-    Load,
-    Store,
-    Jal,
-    Jalr,
-    Reg,
+    Fetch = 0,
+    Decode = 1,
+    Imm = 2,
+    Lui = 3,
+    Br = 4,
+    Auipc = 5,
+    Load = 6,
+    Store = 7,
+    Jal = 8,
+    Jalr = 9,
+    Reg = 10,
+    MulDiv = 11,
+    System = 12,
+    Fence = 13,
 }
@@ -0,0 +1,505 @@
+use crate::backend::component::alu::{Alu, AluMux1, AluMux2};
+use crate::backend::component::cache::CacheCtl;
+use crate::backend::component::cmp::{Cmp, CmpMux};
+use crate::backend::component::control::Control;
+use crate::backend::component::csr_file::CsrFile;
+use crate::backend::component::ir::IR;
+use crate::backend::component::mem_addr_mux::MemAddrMux;
+use crate::backend::component::mem_ctl::MemCtl;
+use crate::backend::component::muldiv::MulDiv;
+use crate::backend::component::pc::{Pc, PcMux};
+use crate::backend::component::regfile::{RegFile, RegFileMux};
+use crate::backend::util::byte::Bytes;
+use std::io::Write;
+
+/// The value of a signal at the end of a cycle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VcdValue {
+    /// Least significant byte first, an unknown byte is dumped as 8 `x` bits
+    Bits(Vec<Option<u8>>),
+    /// Dumped as a GTKWave string signal, for enums
+    String(String),
+}
+
+impl<const T: usize> From<Bytes<T>> for VcdValue {
+    fn from(value: Bytes<T>) -> Self {
+        VcdValue::Bits(value.data.to_vec())
+    }
+}
+
+impl VcdValue {
+    fn get_var_type(&self) -> &'static str {
+        match self {
+            VcdValue::Bits(_) => "wire",
+            VcdValue::String(_) => "string",
+        }
+    }
+
+    fn get_width(&self) -> usize {
+        match self {
+            VcdValue::Bits(bytes) => bytes.len() * 8,
+            VcdValue::String(_) => 1,
+        }
+    }
+
+    /// The value change for the signal identified by `id`
+    fn format(&self, id: &str) -> String {
+        match self {
+            VcdValue::Bits(bytes) => {
+                let bits = bytes
+                    .iter()
+                    .rev()
+                    .map(|x| {
+                        x.map(|byte| format!("{:08b}", byte))
+                            .unwrap_or("xxxxxxxx".to_string())
+                    })
+                    .collect::<String>();
+                format!("b{} {}", bits, id)
+            }
+            // vcd values cannot contain whitespace
+            VcdValue::String(value) => format!("s{} {}", value.replace(' ', "_"), id),
+        }
+    }
+}
+
+/// The signals of a module, by name
+pub type VcdSignals = Vec<(&'static str, VcdValue)>;
+
+/// A component whose ports can be dumped
+pub trait VcdProbe {
+    fn get_vcd_signals(&self) -> VcdSignals;
+}
+
+/// Writes a value change dump with one time step per cycle
+///
+/// The header is written on the first dump, the modules have to keep the same signals afterwards
+pub struct VcdWriter<W: Write> {
+    writer: W,
+    /// The values of the last dump, by module then signal
+    last_values: Option<Vec<Vec<VcdValue>>>,
+    last_time: u128,
+}
+
+impl<W: Write> VcdWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            last_values: None,
+            last_time: 0,
+        }
+    }
+
+    /// The identifier code of the `idx`th signal, printable ascii other than space
+    fn get_id(mut idx: usize) -> String {
+        let mut id = String::new();
+        loop {
+            id.push((b'!' + (idx % 94) as u8) as char);
+            idx /= 94;
+            if idx == 0 {
+                return id;
+            }
+        }
+    }
+
+    fn write_header(&mut self, modules: &[(&'static str, VcdSignals)]) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "$version rsim-rv32i {} $end",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        // a cycle per time step
+        writeln!(self.writer, "$timescale 1ns $end")?;
+        writeln!(self.writer, "$scope module core $end")?;
+        let mut idx = 0;
+        for (module, signals) in modules {
+            writeln!(self.writer, "$scope module {} $end", module)?;
+            for (name, value) in signals {
+                writeln!(
+                    self.writer,
+                    "$var {} {} {} {} $end",
+                    value.get_var_type(),
+                    value.get_width(),
+                    Self::get_id(idx),
+                    name
+                )?;
+                idx += 1;
+            }
+            writeln!(self.writer, "$upscope $end")?;
+        }
+        writeln!(self.writer, "$upscope $end")?;
+        writeln!(self.writer, "$enddefinitions $end")
+    }
+
+    /// Dumps the values at `time`, only the ones that changed since the last dump are written
+    ///
+    /// A time that is not after the last dump is skipped, which happens when cycles are run again
+    /// after stepping back
+    pub fn dump(
+        &mut self,
+        time: u128,
+        modules: Vec<(&'static str, VcdSignals)>,
+    ) -> std::io::Result<()> {
+        let values = modules
+            .iter()
+            .map(|(_, signals)| signals.iter().map(|(_, value)| value.clone()).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        match self.last_values.take() {
+            None => {
+                self.write_header(&modules)?;
+                writeln!(self.writer, "#{}", time)?;
+                writeln!(self.writer, "$dumpvars")?;
+                for (idx, value) in values.iter().flatten().enumerate() {
+                    writeln!(self.writer, "{}", value.format(&Self::get_id(idx)))?;
+                }
+                writeln!(self.writer, "$end")?;
+            }
+            Some(last_values) if time <= self.last_time => {
+                self.last_values = Some(last_values);
+                return Ok(());
+            }
+            Some(last_values) => {
+                let changes = values
+                    .iter()
+                    .flatten()
+                    .zip(last_values.iter().flatten())
+                    .enumerate()
+                    .filter(|(_, (value, last_value))| value != last_value)
+                    .map(|(idx, (value, _))| value.format(&Self::get_id(idx)))
+                    .collect::<Vec<_>>();
+                if !changes.is_empty() {
+                    writeln!(self.writer, "#{}", time)?;
+                    for change in changes {
+                        writeln!(self.writer, "{}", change)?;
+                    }
+                }
+            }
+        }
+
+        self.last_values = Some(values);
+        self.last_time = time;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl VcdProbe for Alu {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("a", self.a.get_value().into()),
+            ("b", self.b.get_value().into()),
+            ("op", self.op.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for AluMux1 {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("rs1", self.rs1.get_value().into()),
+            ("pc", self.pc.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for AluMux2 {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("i_imm", self.i_imm.get_value().into()),
+            ("u_imm", self.u_imm.get_value().into()),
+            ("b_imm", self.b_imm.get_value().into()),
+            ("s_imm", self.s_imm.get_value().into()),
+            ("j_imm", self.j_imm.get_value().into()),
+            ("rs2", self.rs2.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for CacheCtl {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("cpu_addr", self.cpu_addr.get_value().into()),
+            ("cpu_wdata", self.cpu_wdata.get_value().into()),
+            ("cpu_rmask", self.cpu_rmask.get_value().into()),
+            ("cpu_wmask", self.cpu_wmask.get_value().into()),
+            ("mem_addr_mux_sel", self.mem_addr_mux_sel.get_value().into()),
+            ("mem_rdata", self.mem_rdata.get_value().into()),
+            ("mem_resp", self.mem_resp.get_value().into()),
+            ("mem_fault", self.mem_fault.get_value().into()),
+            ("cpu_rdata", self.cpu_rdata.get_value().into()),
+            ("cpu_resp", self.cpu_resp.get_value().into()),
+            ("cpu_fault", self.cpu_fault.get_value().into()),
+            ("mem_addr", self.mem_addr.get_value().into()),
+            ("mem_wdata", self.mem_wdata.get_value().into()),
+            ("mem_rmask", self.mem_rmask.get_value().into()),
+            ("mem_wmask", self.mem_wmask.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for Cmp {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("a", self.a.get_value().into()),
+            ("b", self.b.get_value().into()),
+            ("op", self.op.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for CmpMux {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("rs2", self.rs2.get_value().into()),
+            ("i_imm", self.i_imm.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for Control {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("state", VcdValue::String(self.state.to_string())),
+            ("funct3", self.funct3.get_value().into()),
+            ("funct7", self.funct7.get_value().into()),
+            ("cmp_out", self.cmp_out.get_value().into()),
+            ("opcode", self.opcode.get_value().into()),
+            ("mem_addr_mux_out", self.mem_addr_mux_out.get_value().into()),
+            ("mem_resp", self.mem_resp.get_value().into()),
+            ("mem_fault", self.mem_fault.get_value().into()),
+            ("muldiv_resp", self.muldiv_resp.get_value().into()),
+            ("i_imm", self.i_imm.get_value().into()),
+            ("csr_illegal", self.csr_illegal.get_value().into()),
+            ("interrupt", self.interrupt.get_value().into()),
+            ("load_pc", self.load_pc.get_value().into()),
+            ("load_ir", self.load_ir.get_value().into()),
+            ("load_regfile", self.load_regfile.get_value().into()),
+            ("alu_op", self.alu_op.get_value().into()),
+            ("cmp_op", self.cmp_op.get_value().into()),
+            ("pc_mux_sel", self.pc_mux_sel.get_value().into()),
+            ("alu_mux1_sel", self.alu_mux1_sel.get_value().into()),
+            ("alu_mux2_sel", self.alu_mux2_sel.get_value().into()),
+            ("regfile_mux_sel", self.regfile_mux_sel.get_value().into()),
+            ("mem_addr_mux_sel", self.mem_addr_mux_sel.get_value().into()),
+            ("cmp_mux_sel", self.cmp_mux_sel.get_value().into()),
+            ("mem_rmask", self.mem_rmask.get_value().into()),
+            ("mem_wmask", self.mem_wmask.get_value().into()),
+            ("muldiv_start", self.muldiv_start.get_value().into()),
+            ("muldiv_op", self.muldiv_op.get_value().into()),
+            ("csr_op", self.csr_op.get_value().into()),
+            ("csr_wr", self.csr_wr.get_value().into()),
+            ("mret", self.mret.get_value().into()),
+            ("trap", self.trap.get_value().into()),
+            ("trap_cause", self.trap_cause.get_value().into()),
+            ("trap_val", self.trap_val.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for CsrFile {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("i_imm", self.i_imm.get_value().into()),
+            ("rs1_idx", self.rs1_idx.get_value().into()),
+            ("rs1_data", self.rs1_data.get_value().into()),
+            ("pc", self.pc.get_value().into()),
+            ("op", self.op.get_value().into()),
+            ("wr", self.wr.get_value().into()),
+            ("retire", self.retire.get_value().into()),
+            ("trap", self.trap.get_value().into()),
+            ("trap_cause", self.trap_cause.get_value().into()),
+            ("trap_val", self.trap_val.get_value().into()),
+            ("mret", self.mret.get_value().into()),
+            ("out", self.out.get_value().into()),
+            ("mtvec_out", self.mtvec_out.get_value().into()),
+            ("mepc_out", self.mepc_out.get_value().into()),
+            ("illegal", self.illegal.get_value().into()),
+            ("interrupt", self.interrupt.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for IR {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("load", self.load.get_value().into()),
+            ("data", self.data.get_value().into()),
+            ("funct3", self.funct3.get_value().into()),
+            ("funct7", self.funct7.get_value().into()),
+            ("opcode", self.opcode.get_value().into()),
+            ("i_imm", self.i_imm.get_value().into()),
+            ("s_imm", self.s_imm.get_value().into()),
+            ("b_imm", self.b_imm.get_value().into()),
+            ("u_imm", self.u_imm.get_value().into()),
+            ("j_imm", self.j_imm.get_value().into()),
+            ("rs1", self.rs1.get_value().into()),
+            ("rs2", self.rs2.get_value().into()),
+            ("rd", self.rd.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for MemAddrMux {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("pc", self.pc.get_value().into()),
+            ("alu_out", self.alu_out.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for MemCtl {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("cpu_addr", self.cpu_addr.get_value().into()),
+            ("cpu_wdata", self.cpu_wdata.get_value().into()),
+            ("cpu_rmask", self.cpu_rmask.get_value().into()),
+            ("cpu_wmask", self.cpu_wmask.get_value().into()),
+            ("mem_addr_mux_sel", self.mem_addr_mux_sel.get_value().into()),
+            ("cpu_rdata", self.cpu_rdata.get_value().into()),
+            ("cpu_resp", self.cpu_resp.get_value().into()),
+            ("cpu_fault", self.cpu_fault.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for MulDiv {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("a", self.a.get_value().into()),
+            ("b", self.b.get_value().into()),
+            ("op", self.op.get_value().into()),
+            ("start", self.start.get_value().into()),
+            ("out", self.out.get_value().into()),
+            ("resp", self.resp.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for Pc {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("load", self.load.get_value().into()),
+            ("data", self.data.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for PcMux {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("pc", self.pc.get_value().into()),
+            ("alu_out", self.alu_out.get_value().into()),
+            ("mtvec", self.mtvec.get_value().into()),
+            ("mepc", self.mepc.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for RegFile {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("rs1_idx", self.rs1_idx.get_value().into()),
+            ("rs2_idx", self.rs2_idx.get_value().into()),
+            ("rd_wr", self.rd_wr.get_value().into()),
+            ("rd_idx", self.rd_idx.get_value().into()),
+            ("rd_data", self.rd_data.get_value().into()),
+            ("rs1_data", self.rs1_data.get_value().into()),
+            ("rs2_data", self.rs2_data.get_value().into()),
+        ]
+    }
+}
+
+impl VcdProbe for RegFileMux {
+    fn get_vcd_signals(&self) -> VcdSignals {
+        vec![
+            ("alu_out", self.alu_out.get_value().into()),
+            ("cmp_out", self.cmp_out.get_value().into()),
+            ("u_imm", self.u_imm.get_value().into()),
+            ("mem_addr_mux_out", self.mem_addr_mux_out.get_value().into()),
+            ("mem_rdata", self.mem_rdata.get_value().into()),
+            ("pc", self.pc.get_value().into()),
+            ("muldiv_out", self.muldiv_out.get_value().into()),
+            ("csr_out", self.csr_out.get_value().into()),
+            ("sel", self.sel.get_value().into()),
+            ("out", self.out.get_value().into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::util::types::{Byte, Word};
+
+    fn get_modules(word: Word, state: &str) -> Vec<(&'static str, VcdSignals)> {
+        vec![("test", vec![
+            ("word", word.into()),
+            ("byte", Byte::from(0xA5u8).into()),
+            ("state", VcdValue::String(state.to_string())),
+        ])]
+    }
+
+    #[test]
+    fn test_vcd_dump() {
+        let mut vcd_writer = VcdWriter::new(Vec::new());
+        let mut word = Word::from(1u32);
+        word[3] = None;
+        vcd_writer.dump(1, get_modules(word, "Fetch")).unwrap();
+        vcd_writer
+            .dump(2, get_modules(Word::from(2u32), "Fetch"))
+            .unwrap();
+        // unchanged, and stepped back
+        vcd_writer
+            .dump(3, get_modules(Word::from(2u32), "Fetch"))
+            .unwrap();
+        vcd_writer
+            .dump(2, get_modules(Word::from(3u32), "Decode"))
+            .unwrap();
+        vcd_writer
+            .dump(4, get_modules(Word::from(2u32), "Decode"))
+            .unwrap();
+
+        let vcd = String::from_utf8(vcd_writer.writer).unwrap();
+        assert!(vcd.contains("$var wire 32 ! word $end\n"));
+        assert!(vcd.contains("$var wire 8 \" byte $end\n"));
+        assert!(vcd.contains("$var string 1 # state $end\n"));
+        let (_, changes) = vcd.split_once("$enddefinitions $end\n").unwrap();
+        assert_eq!(
+            changes,
+            "#1\n\
+            $dumpvars\n\
+            bxxxxxxxx000000000000000000000001 !\n\
+            b10100101 \"\n\
+            sFetch #\n\
+            $end\n\
+            #2\n\
+            b00000000000000000000000000000010 !\n\
+            #4\n\
+            sDecode #\n"
+        );
+    }
+
+    #[test]
+    fn test_vcd_id() {
+        assert_eq!(VcdWriter::<Vec<u8>>::get_id(0), "!");
+        assert_eq!(VcdWriter::<Vec<u8>>::get_id(93), "~");
+        assert_eq!(VcdWriter::<Vec<u8>>::get_id(94), "!\"");
+    }
+}
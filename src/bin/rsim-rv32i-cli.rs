@@ -0,0 +1,339 @@
+use crossbeam_channel::unbounded;
+use rsim_rv32i::backend::component::cache::{CacheConfig, CacheType};
+use rsim_rv32i::backend::component::mem_ctl::MemMapType;
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::cosim::{LockstepResult, Reference, run_lockstep};
+use rsim_rv32i::backend::gdb_stub::GdbStub;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: rsim-rv32i-cli [OPTIONS] <ELF>
+
+Runs an ELF without the gui until it ends, then exits with the guest's exit code
+HTIF console output goes to stdout and getchar reads from stdin
+
+Options:
+      --max-cycles <N>          Stop after N cycles
+      --max-instructions <N>    Stop after N instructions
+      --fast-forward <N>        Run the first N instructions on the functional model, the limits apply after
+      --commit-log <FILE>       Write a spike style commit log to FILE
+      --vcd <FILE>              Dump every component port each cycle to FILE as a VCD waveform
+      --lockstep <FILE>         Compare every retired instruction with the spike commit log in FILE
+      --lockstep-functional     Compare every retired instruction with the functional model
+      --mem-map <MAP>           Raise access faults outside of the memory map, one of
+                                linker-script (tests/link.ld) or elf (segment flags, then linker-script)
+      --icache <CONFIG>         Put an instruction cache in front of memory, CONFIG is a comma separated
+                                list of size=<BYTES>,ways=<N>,line=<BYTES>,policy=lru|fifo|random,
+                                write=back|through,penalty=<CYCLES>, what is left out is the default
+                                [default: size=1024,ways=2,line=16,policy=lru,write=back,penalty=10]
+      --dcache <CONFIG>         Put a data cache in front of memory, CONFIG is the same as --icache
+      --threads <N>             Number of simulation threads [default: 1]
+      --gdb <PORT>              Wait for gdb on localhost:PORT instead of running, the limits do not apply
+  -h, --help                    Print this message";
+
+/// Exit code when a limit is reached before the guest ends, same as `timeout(1)`
+const LIMIT_REACHED_EXIT_CODE: u8 = 124;
+/// Exit code for bad arguments or an unreadable ELF
+const USAGE_EXIT_CODE: u8 = 2;
+/// Exit code when lockstep finds a retired instruction that differs from the reference
+const DIVERGED_EXIT_CODE: u8 = 3;
+/// Exit code when the guest halts on an access fault it has no trap handler for
+const ACCESS_FAULT_EXIT_CODE: u8 = 4;
+
+struct Args {
+    elf_path: String,
+    max_cycles: Option<u128>,
+    max_instructions: Option<u128>,
+    fast_forward: Option<u128>,
+    commit_log_path: Option<String>,
+    vcd_path: Option<String>,
+    lockstep_log_path: Option<String>,
+    is_lockstep_functional: bool,
+    mem_map_type: MemMapType,
+    icache_config: Option<CacheConfig>,
+    dcache_config: Option<CacheConfig>,
+    threads: usize,
+    gdb_port: Option<u16>,
+}
+
+/// Returns `Ok(None)` if help is requested
+fn parse_args(mut raw_args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut elf_path = None;
+    let mut max_cycles = None;
+    let mut max_instructions = None;
+    let mut fast_forward = None;
+    let mut commit_log_path = None;
+    let mut vcd_path = None;
+    let mut lockstep_log_path = None;
+    let mut is_lockstep_functional = false;
+    let mut mem_map_type = MemMapType::None;
+    let mut icache_config = None;
+    let mut dcache_config = None;
+    let mut threads = 1;
+    let mut gdb_port = None;
+
+    while let Some(arg) = raw_args.next() {
+        let mut value = |name: &str| {
+            raw_args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--max-cycles" => {
+                max_cycles = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid --max-cycles: {}", e))?,
+                )
+            }
+            "--max-instructions" => {
+                max_instructions = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid --max-instructions: {}", e))?,
+                )
+            }
+            "--fast-forward" => {
+                fast_forward = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid --fast-forward: {}", e))?,
+                )
+            }
+            "--commit-log" => commit_log_path = Some(value(&arg)?),
+            "--vcd" => vcd_path = Some(value(&arg)?),
+            "--lockstep" => lockstep_log_path = Some(value(&arg)?),
+            "--lockstep-functional" => is_lockstep_functional = true,
+            "--mem-map" => {
+                mem_map_type = match value(&arg)?.as_str() {
+                    "linker-script" => MemMapType::LinkerScript,
+                    "elf" => MemMapType::ElfSegments,
+                    mem_map => {
+                        return Err(format!(
+                            "invalid --mem-map: expected linker-script or elf, found {}",
+                            mem_map
+                        ));
+                    }
+                }
+            }
+            "--icache" => {
+                icache_config = Some(
+                    CacheConfig::parse(&value(&arg)?)
+                        .map_err(|e| format!("invalid --icache: {}", e))?,
+                )
+            }
+            "--dcache" => {
+                dcache_config = Some(
+                    CacheConfig::parse(&value(&arg)?)
+                        .map_err(|e| format!("invalid --dcache: {}", e))?,
+                )
+            }
+            "--threads" => {
+                threads = value(&arg)?
+                    .parse()
+                    .ok()
+                    .filter(|threads| *threads > 0)
+                    .ok_or("invalid --threads: expected a positive integer")?
+            }
+            "--gdb" => {
+                gdb_port = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid --gdb: {}", e))?,
+                )
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if elf_path.is_none() => elf_path = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let is_lockstep = lockstep_log_path.is_some() || is_lockstep_functional;
+    if lockstep_log_path.is_some() && is_lockstep_functional {
+        return Err("--lockstep and --lockstep-functional are mutually exclusive".to_string());
+    }
+    if is_lockstep && (gdb_port.is_some() || fast_forward.is_some()) {
+        return Err("lockstep cannot be combined with --gdb or --fast-forward".to_string());
+    }
+
+    Ok(Some(Args {
+        elf_path: elf_path.ok_or("missing <ELF>")?,
+        max_cycles,
+        max_instructions,
+        fast_forward,
+        commit_log_path,
+        vcd_path,
+        lockstep_log_path,
+        is_lockstep_functional,
+        mem_map_type,
+        icache_config,
+        dcache_config,
+        threads,
+        gdb_port,
+    }))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(USAGE_EXIT_CODE);
+        }
+    };
+
+    let elf = match std::fs::read(&args.elf_path) {
+        Ok(elf) => elf,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", args.elf_path, e);
+            return ExitCode::from(USAGE_EXIT_CODE);
+        }
+    };
+
+    let commit_file = match args.commit_log_path.as_ref().map(File::create).transpose() {
+        Ok(commit_file) => commit_file,
+        Err(e) => {
+            eprintln!("error: cannot create commit log: {}", e);
+            return ExitCode::from(USAGE_EXIT_CODE);
+        }
+    };
+
+    let vcd_file = match args.vcd_path.as_ref().map(File::create).transpose() {
+        Ok(vcd_file) => vcd_file,
+        Err(e) => {
+            eprintln!("error: cannot create vcd: {}", e);
+            return ExitCode::from(USAGE_EXIT_CODE);
+        }
+    };
+
+    let mut reference = match &args.lockstep_log_path {
+        Some(path) => match File::open(path) {
+            Ok(file) => Some(Reference::from_log(BufReader::new(file))),
+            Err(e) => {
+                eprintln!("error: cannot read {}: {}", path, e);
+                return ExitCode::from(USAGE_EXIT_CODE);
+            }
+        },
+        None if args.is_lockstep_functional => match Reference::from_elf(elf.as_slice()) {
+            Ok(reference) => Some(reference),
+            Err(e) => {
+                eprintln!("error: cannot load {}: {}", args.elf_path, e);
+                return ExitCode::from(USAGE_EXIT_CODE);
+            }
+        },
+        None => None,
+    };
+
+    let core = Core::new(args.threads, commit_file, vcd_file);
+    core.set_mem_map_type(args.mem_map_type);
+    for (cache_type, config) in [
+        (CacheType::Instruction, args.icache_config),
+        (CacheType::Data, args.dcache_config),
+    ] {
+        if let Some(config) = config {
+            core.set_cache_config(cache_type, config).unwrap();
+        }
+    }
+    if let Err(e) = core.load_elf(elf.as_slice()) {
+        eprintln!("error: cannot load {}: {}", args.elf_path, e);
+        return ExitCode::from(USAGE_EXIT_CODE);
+    }
+
+    // stdin is read on its own thread so that a program that never reads it does not block
+    let stdin_channel = unbounded();
+    std::thread::spawn(move || {
+        for byte in std::io::stdin().lock().bytes() {
+            match byte {
+                Ok(byte) if stdin_channel.0.send(byte).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+    let hook = || {
+        let mut htif_mmio_ctl = core.htif_mmio_ctl.lock().unwrap();
+        while let Ok(byte) = stdin_channel.1.try_recv() {
+            htif_mmio_ctl.append_to_buffer(byte);
+        }
+        let output = htif_mmio_ctl.take_output();
+        if !output.is_empty() {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&output).unwrap();
+            stdout.flush().unwrap();
+        }
+        if let Some(command) = htif_mmio_ctl.take_unsupported_command() {
+            eprintln!("warning: unsupported HTIF command 0x{:016X}", command);
+        }
+    };
+
+    if let Some(fast_forward) = args.fast_forward {
+        core.fast_forward(&Default::default(), Some(fast_forward), Some(hook));
+    }
+
+    let has_ended = match args.gdb_port {
+        Some(port) => {
+            if let Err(e) = GdbStub::serve(&core, port, Some(hook)) {
+                eprintln!("error: gdb: {}", e);
+            }
+            core.has_ended()
+        }
+        None => match reference.as_mut() {
+            Some(reference) => match run_lockstep(
+                &core,
+                reference,
+                args.max_cycles,
+                args.max_instructions,
+                Some(hook),
+            ) {
+                Ok(LockstepResult::Ended) => true,
+                Ok(LockstepResult::LimitReached) => false,
+                Ok(LockstepResult::Diverged(divergence)) => {
+                    eprint!("{}", divergence);
+                    return ExitCode::from(DIVERGED_EXIT_CODE);
+                }
+                Err(e) => {
+                    eprintln!("error: lockstep: {}", e);
+                    return ExitCode::from(USAGE_EXIT_CODE);
+                }
+            },
+            None => core.run_end_with_limit(args.max_cycles, args.max_instructions, Some(hook)),
+        },
+    };
+    core.flush_vcd();
+
+    eprintln!(
+        "{} instructions in {} cycles",
+        core.get_instructions_ran(),
+        core.get_cycles_ran()
+    );
+    let (icache, dcache) = core.get_caches();
+    for (name, cache) in [("icache", icache), ("dcache", dcache)] {
+        if cache.is_enabled() {
+            eprintln!("{}: {}", name, cache.get_stats());
+        }
+    }
+
+    if let Some(access_fault) = core.get_unhandled_access_fault() {
+        eprintln!("halted on an unhandled {}", access_fault);
+        return ExitCode::from(ACCESS_FAULT_EXIT_CODE);
+    }
+
+    if !has_ended {
+        eprintln!("limit reached before the program ended");
+        return ExitCode::from(LIMIT_REACHED_EXIT_CODE);
+    }
+
+    match core.get_exit_code() {
+        Some(exit_code) => ExitCode::from(exit_code as u8),
+        None => {
+            eprintln!("exit code is unknown");
+            ExitCode::FAILURE
+        }
+    }
+}
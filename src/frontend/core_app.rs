@@ -2,12 +2,16 @@ use crate::backend::core::Core;
 use crate::frontend::core_gui_wrapper::CoreGuiWrapper;
 use crate::frontend::tab::Tab;
 use crate::frontend::tab::about::About;
+use crate::frontend::tab::cache::Cache;
 use crate::frontend::tab::console::Console;
 use crate::frontend::tab::control::Control;
 use crate::frontend::tab::datapath::Datapath;
+use crate::frontend::tab::editor::Editor;
 use crate::frontend::tab::memory::Memory;
 use crate::frontend::tab::register::Register;
 use crate::frontend::tab::setting::Setting;
+use crate::frontend::tab::waveform::Waveform;
+use crate::frontend::util::state_channel::state_channel;
 use crossbeam_channel::unbounded;
 use egui::ScrollArea;
 use std::collections::BTreeSet;
@@ -24,29 +28,63 @@ pub struct CoreApp {
 
 impl Default for CoreApp {
     fn default() -> Self {
-        let core = Arc::new(Core::new(1, None));
-        let console_vga_buffer_channel = unbounded();
+        let core = Arc::new(Core::new(1, None, None));
+        let console_vga_buffer_channel = state_channel();
         let console_keyboard_buffer_channel = unbounded();
+        let console_htif_output_channel = unbounded();
         let control_command_channel = unbounded();
         let control_ack_channel = unbounded();
-        let register_data_channel = unbounded();
+        let pause_channel = unbounded();
+        let clock_speed_channel = unbounded();
+        let register_data_channel = state_channel();
         let breakpoint_channel = unbounded();
-        let memory_channel = unbounded();
-        let label_channel = unbounded();
-        let load_elf_channel = unbounded();
-        let datapath_component_channel = unbounded();
+        let watchpoint_channel = unbounded();
+        let watchpoint_hit_channel = state_channel();
+        let mem_map_type_channel = unbounded();
+        let access_fault_channel = state_channel();
+        let cache_config_channel = unbounded();
+        let cache_channel = state_channel();
+        let memory_window_channel = unbounded();
+        let memory_channel = state_channel();
+        let label_channel = state_channel();
+        let pc_channel = state_channel();
+        let load_image_channel = unbounded();
+        let load_program_channel = unbounded();
+        let load_checkpoint_channel = unbounded();
+        let load_error_channel = unbounded();
+        let save_checkpoint_request_channel = unbounded();
+        let save_checkpoint_channel = unbounded();
+        let datapath_component_channel = state_channel();
+        let waveform_sample_channel = state_channel();
         let mut core_wrapper = CoreGuiWrapper::new(
             core.clone(),
-            console_vga_buffer_channel.0.clone(),
+            console_vga_buffer_channel.0,
             console_keyboard_buffer_channel.1.clone(),
+            console_htif_output_channel.0.clone(),
             control_command_channel.1.clone(),
             control_ack_channel.0.clone(),
-            register_data_channel.0.clone(),
+            pause_channel.1.clone(),
+            clock_speed_channel.1.clone(),
+            register_data_channel.0,
             breakpoint_channel.1.clone(),
-            memory_channel.0.clone(),
-            label_channel.0.clone(),
-            load_elf_channel.1.clone(),
-            datapath_component_channel.0.clone(),
+            watchpoint_channel.1.clone(),
+            watchpoint_hit_channel.0,
+            mem_map_type_channel.1.clone(),
+            access_fault_channel.0,
+            cache_config_channel.1.clone(),
+            cache_channel.0,
+            memory_window_channel.1.clone(),
+            memory_channel.0,
+            label_channel.0,
+            pc_channel.0,
+            load_image_channel.1.clone(),
+            load_program_channel.1.clone(),
+            load_checkpoint_channel.1.clone(),
+            load_error_channel.0.clone(),
+            save_checkpoint_request_channel.1.clone(),
+            save_checkpoint_channel.0.clone(),
+            datapath_component_channel.0,
+            waveform_sample_channel.0,
         );
 
         core_wrapper.send_update();
@@ -58,18 +96,37 @@ impl Default for CoreApp {
                 Box::new(Control::new(
                     control_command_channel.0.clone(),
                     control_ack_channel.1.clone(),
+                    pause_channel.0.clone(),
+                    clock_speed_channel.0.clone(),
                 )),
                 Box::new(Memory::new(
                     breakpoint_channel.0.clone(),
+                    watchpoint_channel.0.clone(),
+                    watchpoint_hit_channel.1.clone(),
+                    mem_map_type_channel.0.clone(),
+                    access_fault_channel.1.clone(),
+                    memory_window_channel.0.clone(),
                     memory_channel.1.clone(),
                     label_channel.1.clone(),
-                    load_elf_channel.0.clone(),
+                    pc_channel.1.clone(),
+                    load_image_channel.0.clone(),
+                    load_checkpoint_channel.0.clone(),
+                    load_error_channel.1.clone(),
+                    save_checkpoint_request_channel.0.clone(),
+                    save_checkpoint_channel.1.clone(),
                 )),
+                Box::new(Editor::new(load_program_channel.0.clone())),
                 Box::new(Register::new(register_data_channel.1.clone())),
+                Box::new(Cache::new(
+                    cache_config_channel.0.clone(),
+                    cache_channel.1.clone(),
+                )),
                 Box::new(Datapath::new(datapath_component_channel.1.clone())),
+                Box::new(Waveform::new(waveform_sample_channel.1.clone())),
                 Box::new(Console::new(
                     console_vga_buffer_channel.1.clone(),
                     console_keyboard_buffer_channel.0.clone(),
+                    console_htif_output_channel.1.clone(),
                 )),
                 Box::new(Setting::default()),
                 Box::new(About {}),
@@ -1,14 +1,32 @@
-use crate::backend::component::mem_ctl::VgaMmioCtl;
+use crate::backend::component::cache::{Cache, CacheConfig, CacheType};
+use crate::backend::component::mem_ctl::{
+    AccessFault, MemMapType, VgaMmioCtl, Watchpoint, WatchpointHit,
+};
 use crate::backend::core::ComponentType::*;
 use crate::backend::core::Core;
+use crate::backend::loader::Program;
 use crate::backend::util::types::Byte;
 use crate::backend::util::types::Word;
 use crate::frontend::core_gui_wrapper::ControlCommand::*;
 use crate::frontend::tab::datapath::DatapathComponentMap;
+use crate::frontend::tab::waveform::{WINDOW_CYCLES, WaveformSample, merge_waveform_samples};
 use crate::frontend::util::datapath_component::DatapathComponentDisplayer;
+use crate::frontend::util::state_channel::StateSender;
 use crossbeam_channel::{Receiver, Sender};
+use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// How often the ui is updated while running, every cycle still reaches the waveform
+const UPDATE_INTERVAL: Duration = Duration::from_millis(16);
+/// The longest a paused free run keeps sleeping
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 /// A wrapper for `crate::frontend::core_app`
 /// that communicates with the ui through channels.
@@ -16,53 +34,133 @@ use std::sync::Arc;
 /// Mostly due to wasm thread's limitation that the main thread cannot block or lock
 pub struct CoreGuiWrapper {
     core: Arc<Core>,
-    console_vga_buffer_sender: Sender<[u8; VgaMmioCtl::NUM_BYTES]>,
+    console_vga_buffer_sender: StateSender<[u8; VgaMmioCtl::NUM_BYTES]>,
     console_keyboard_buffer_receiver: Receiver<u8>,
+    console_htif_output_sender: Sender<Vec<u8>>,
     control_command_receiver: Receiver<ControlCommand>,
     control_ack_sender: Sender<()>,
-    register_data_sender: Sender<RegisterData>,
+    pause_receiver: Receiver<()>,
+    clock_speed_receiver: Receiver<u32>,
+    /// Target speed of `ControlCommand::Run`
+    cycles_per_second: u32,
+    register_data_sender: StateSender<RegisterData>,
     breakpoints_receiver: Receiver<BTreeSet<Word>>,
     breakpoints: BTreeSet<Word>,
-    memory_sender: Sender<BTreeMap<Word, Byte>>,
-    label_sender: Sender<BTreeMap<Word, String>>,
-    load_elf_receiver: Receiver<Vec<u8>>,
-    datapath_component_sender: Sender<DatapathComponentMap>,
+    watchpoints_receiver: Receiver<Vec<Watchpoint>>,
+    watchpoint_hit_sender: StateSender<Option<WatchpointHit>>,
+    mem_map_type_receiver: Receiver<MemMapType>,
+    access_fault_sender: StateSender<Option<AccessFault>>,
+    cache_config_receiver: Receiver<(CacheType, CacheConfig)>,
+    /// The instruction cache and the data cache
+    cache_sender: StateSender<(Cache, Cache)>,
+    memory_window_receiver: Receiver<Range<u32>>,
+    /// The addresses shown in the memory tab, only those are sent,
+    /// it can change while running so it is not behind `&mut self`
+    memory_window: RefCell<Range<u32>>,
+    memory_sender: StateSender<MemoryWindow>,
+    label_sender: StateSender<BTreeMap<Word, String>>,
+    pc_sender: StateSender<Word>,
+    load_image_receiver: Receiver<LoadImage>,
+    load_program_receiver: Receiver<Program>,
+    load_checkpoint_receiver: Receiver<Vec<u8>>,
+    load_error_sender: Sender<Option<String>>,
+    save_checkpoint_request_receiver: Receiver<()>,
+    save_checkpoint_sender: Sender<Vec<u8>>,
+    datapath_component_sender: StateSender<DatapathComponentMap>,
+    waveform_sample_sender: StateSender<Vec<WaveformSample>>,
+    /// Samples since the last update, sent along with it
+    waveform_samples: RefCell<Vec<WaveformSample>>,
+    /// What the last sample was taken against, see `WaveformSample`
+    last_sampled_components: RefCell<DatapathComponentMap>,
 }
 
 impl CoreGuiWrapper {
     pub fn new(
         core: Arc<Core>,
-        console_vga_buffer_sender: Sender<[u8; VgaMmioCtl::NUM_BYTES]>,
+        console_vga_buffer_sender: StateSender<[u8; VgaMmioCtl::NUM_BYTES]>,
         console_keyboard_buffer_receiver: Receiver<u8>,
+        console_htif_output_sender: Sender<Vec<u8>>,
         control_command_receiver: Receiver<ControlCommand>,
         control_ack_sender: Sender<()>,
-        register_data_sender: Sender<RegisterData>,
+        pause_receiver: Receiver<()>,
+        clock_speed_receiver: Receiver<u32>,
+        register_data_sender: StateSender<RegisterData>,
         breakpoints_receiver: Receiver<BTreeSet<Word>>,
-        memory_sender: Sender<BTreeMap<Word, Byte>>,
-        label_sender: Sender<BTreeMap<Word, String>>,
-        load_elf_receiver: Receiver<Vec<u8>>,
-        datapath_component_sender: Sender<DatapathComponentMap>,
+        watchpoints_receiver: Receiver<Vec<Watchpoint>>,
+        watchpoint_hit_sender: StateSender<Option<WatchpointHit>>,
+        mem_map_type_receiver: Receiver<MemMapType>,
+        access_fault_sender: StateSender<Option<AccessFault>>,
+        cache_config_receiver: Receiver<(CacheType, CacheConfig)>,
+        cache_sender: StateSender<(Cache, Cache)>,
+        memory_window_receiver: Receiver<Range<u32>>,
+        memory_sender: StateSender<MemoryWindow>,
+        label_sender: StateSender<BTreeMap<Word, String>>,
+        pc_sender: StateSender<Word>,
+        load_image_receiver: Receiver<LoadImage>,
+        load_program_receiver: Receiver<Program>,
+        load_checkpoint_receiver: Receiver<Vec<u8>>,
+        load_error_sender: Sender<Option<String>>,
+        save_checkpoint_request_receiver: Receiver<()>,
+        save_checkpoint_sender: Sender<Vec<u8>>,
+        datapath_component_sender: StateSender<DatapathComponentMap>,
+        waveform_sample_sender: StateSender<Vec<WaveformSample>>,
     ) -> Self {
         Self {
             core,
             console_vga_buffer_sender,
             console_keyboard_buffer_receiver,
+            console_htif_output_sender,
             control_command_receiver,
             control_ack_sender,
+            pause_receiver,
+            clock_speed_receiver,
+            cycles_per_second: DEFAULT_CYCLES_PER_SECOND,
             register_data_sender,
             breakpoints_receiver,
             breakpoints: Default::default(),
+            watchpoints_receiver,
+            watchpoint_hit_sender,
+            mem_map_type_receiver,
+            access_fault_sender,
+            cache_config_receiver,
+            cache_sender,
+            memory_window_receiver,
+            memory_window: RefCell::new(0..0),
             memory_sender,
             label_sender,
-            load_elf_receiver,
+            pc_sender,
+            load_image_receiver,
+            load_program_receiver,
+            load_checkpoint_receiver,
+            load_error_sender,
+            save_checkpoint_request_receiver,
+            save_checkpoint_sender,
             datapath_component_sender,
+            waveform_sample_sender,
+            waveform_samples: Default::default(),
+            last_sampled_components: Default::default(),
         }
     }
 
     pub fn send_update(&self) {
         self.console_vga_buffer_sender
-            .try_send(*self.core.vga_mmio_ctl.lock().unwrap().get_buffer())
-            .unwrap();
+            .send(*self.core.vga_mmio_ctl.lock().unwrap().get_buffer());
+        let (mut htif_output, unsupported_command) = {
+            let mut htif_mmio_ctl = self.core.htif_mmio_ctl.lock().unwrap();
+            (
+                htif_mmio_ctl.take_output(),
+                htif_mmio_ctl.take_unsupported_command(),
+            )
+        };
+        if let Some(command) = unsupported_command {
+            htif_output
+                .extend(format!("\n[unsupported HTIF command 0x{:016X}]\n", command).bytes());
+        }
+        if !htif_output.is_empty() {
+            self.console_htif_output_sender
+                .try_send(htif_output)
+                .unwrap();
+        }
 
         let register_data = RegisterData {
             state: format!("{}", self.core.control.lock().unwrap().state.clone()),
@@ -76,16 +174,84 @@ impl CoreGuiWrapper {
                 .registers
                 .data
                 .map(|byte| byte.to_string()),
+            csrs: {
+                let csr_file = self.core.csr_file.lock().unwrap();
+                let clint_mmio_ctl = self.core.clint_mmio_ctl.lock().unwrap();
+                vec![
+                    ("mstatus", format!("0x{:08X}", csr_file.mstatus)),
+                    ("mie", format!("0x{:08X}", csr_file.mie)),
+                    ("mip", format!("0x{:08X}", csr_file.mip)),
+                    ("mtvec", format!("0x{:08X}", csr_file.mtvec)),
+                    ("mscratch", format!("0x{:08X}", csr_file.mscratch)),
+                    ("mepc", format!("0x{:08X}", csr_file.mepc)),
+                    ("mcause", format!("0x{:08X}", csr_file.mcause)),
+                    ("mtval", format!("0x{:08X}", csr_file.mtval)),
+                    ("mcycle", format!("{}", csr_file.mcycle)),
+                    ("minstret", format!("{}", csr_file.minstret)),
+                    ("mtime", format!("{}", clint_mmio_ctl.get_mtime())),
+                    ("mtimecmp", format!("{}", clint_mmio_ctl.get_mtimecmp())),
+                ]
+            },
         };
-        self.register_data_sender.try_send(register_data).unwrap();
+        self.register_data_sender.send(register_data);
 
-        self.memory_sender
-            .try_send(self.core.mem_ctl.lock().unwrap().backend_mem.clone())
-            .unwrap();
+        self.send_memory_window();
         self.label_sender
-            .try_send(self.core.mem_ctl.lock().unwrap().label.clone())
-            .unwrap();
+            .send(self.core.mem_ctl.lock().unwrap().label.clone());
+        self.pc_sender.send(self.core.pc.lock().unwrap().data_inner);
+        self.watchpoint_hit_sender
+            .send(self.core.get_watchpoint_hit());
+        self.access_fault_sender
+            .send(self.core.get_unhandled_access_fault());
+        self.cache_sender.send(self.core.get_caches());
 
+        let datapath_components = self.get_datapath_components();
+        self.push_waveform_sample(datapath_components.clone());
+        let waveform_samples = std::mem::take(&mut *self.waveform_samples.borrow_mut());
+        self.waveform_sample_sender
+            .send_merged(waveform_samples, merge_waveform_samples);
+        self.datapath_component_sender.send(datapath_components);
+    }
+
+    fn send_memory_window(&self) {
+        if let Some(memory_window) = self.memory_window_receiver.try_iter().last() {
+            *self.memory_window.borrow_mut() = memory_window;
+        }
+        let addr_range = self.memory_window.borrow().clone();
+        let data = self
+            .core
+            .mem_ctl
+            .lock()
+            .unwrap()
+            .backend_mem
+            .read_bytes(addr_range.start, addr_range.len());
+        self.memory_sender.send(MemoryWindow { addr_range, data });
+    }
+
+    /// Only what the waveform needs, kept until the next update so that every cycle reaches it
+    pub fn send_waveform_sample(&self) {
+        self.push_waveform_sample(self.get_datapath_components());
+    }
+
+    fn push_waveform_sample(&self, datapath_components: DatapathComponentMap) {
+        let mut last_sampled_components = self.last_sampled_components.borrow_mut();
+        let changed_components = datapath_components
+            .into_iter()
+            .filter(|(component_type, datapath_component)| {
+                last_sampled_components.get(component_type) != Some(datapath_component)
+            })
+            .collect::<DatapathComponentMap>();
+        last_sampled_components.extend(changed_components.clone());
+        let mut waveform_samples = self.waveform_samples.borrow_mut();
+        waveform_samples.push((self.core.get_cycles_ran(), changed_components));
+        // the waveform would drop most of what runs between updates anyway
+        if waveform_samples.len() >= 2 * WINDOW_CYCLES {
+            *waveform_samples =
+                merge_waveform_samples(std::mem::take(&mut *waveform_samples), Vec::new());
+        }
+    }
+
+    fn get_datapath_components(&self) -> DatapathComponentMap {
         let mut datapath_components = DatapathComponentMap::default();
         datapath_components.insert(Alu, self.core.alu.lock().unwrap().get_datapath_component());
         datapath_components.insert(
@@ -110,10 +276,18 @@ impl CoreGuiWrapper {
                 .unwrap()
                 .get_datapath_component(),
         );
+        datapath_components.insert(
+            CsrFile,
+            self.core.csr_file.lock().unwrap().get_datapath_component(),
+        );
         datapath_components.insert(
             MemCtl,
             self.core.mem_ctl.lock().unwrap().get_datapath_component(),
         );
+        datapath_components.insert(
+            MulDiv,
+            self.core.muldiv.lock().unwrap().get_datapath_component(),
+        );
         datapath_components.insert(Pc, self.core.pc.lock().unwrap().get_datapath_component());
         datapath_components.insert(
             PcMux,
@@ -131,30 +305,80 @@ impl CoreGuiWrapper {
                 .unwrap()
                 .get_datapath_component(),
         );
-
-        self.datapath_component_sender
-            .try_send(datapath_components)
-            .unwrap();
+        datapath_components
     }
 
     pub fn receive_misc_updates(&mut self) {
+        while let Ok(cycles_per_second) = self.clock_speed_receiver.try_recv() {
+            self.cycles_per_second = cycles_per_second;
+        }
         while let Ok(breakpoint) = self.breakpoints_receiver.try_recv() {
             self.breakpoints = breakpoint;
         }
-        if let Ok(data) = self.load_elf_receiver.try_recv() {
-            self.core.load_elf(data.as_slice());
+        while let Ok(watchpoints) = self.watchpoints_receiver.try_recv() {
+            self.core.mem_ctl.lock().unwrap().watchpoints = watchpoints;
+        }
+        while let Ok(mem_map_type) = self.mem_map_type_receiver.try_recv() {
+            self.core.set_mem_map_type(mem_map_type);
+        }
+        if !self.cache_config_receiver.is_empty() {
+            while let Ok((cache_type, config)) = self.cache_config_receiver.try_recv() {
+                // the cache tab only sends configs that are valid
+                self.core.set_cache_config(cache_type, config).unwrap();
+            }
+            self.send_update();
+        }
+        if !self.memory_window_receiver.is_empty() {
+            self.send_memory_window();
+        }
+        if let Ok(load_image) = self.load_image_receiver.try_recv() {
+            let result = self.core.load_image(
+                &load_image.file_name,
+                load_image.data.as_slice(),
+                load_image.base,
+            );
+            self.load_error_sender
+                .try_send(
+                    result
+                        .err()
+                        .map(|e| format!("Failed to load {}: {}", load_image.file_name, e)),
+                )
+                .unwrap();
+            self.send_update();
+        }
+        if let Ok(program) = self.load_program_receiver.try_recv() {
+            self.core.load_program(&program);
+            self.send_update();
+        }
+        if let Ok(data) = self.load_checkpoint_receiver.try_recv() {
+            let result = self.core.load_checkpoint(data.as_slice());
+            self.load_error_sender
+                .try_send(
+                    result
+                        .err()
+                        .map(|e| format!("Failed to load checkpoint: {}", e)),
+                )
+                .unwrap();
             self.send_update();
         }
+        if self.save_checkpoint_request_receiver.try_recv().is_ok() {
+            self.save_checkpoint_sender
+                .try_send(self.core.save_checkpoint())
+                .unwrap();
+        }
     }
 
     // this function is decoupled from `receive_misc_updates` so that it is Fn instead of FnMut to implement Copy
     pub fn receive_io_updates(&self) {
         if let Ok(byte) = self.console_keyboard_buffer_receiver.try_recv() {
-            self.core
-                .keyboard_mmio_ctl
-                .lock()
-                .unwrap()
-                .append_to_buffer(byte);
+            self.core.send_input(byte);
+        }
+    }
+
+    /// Asks the core to stop if the pause button was pressed
+    fn receive_pause(&self) {
+        if self.pause_receiver.try_recv().is_ok() {
+            self.core.pause();
         }
     }
 
@@ -163,10 +387,46 @@ impl CoreGuiWrapper {
             self.receive_misc_updates();
             self.receive_io_updates();
             if let Ok(command) = self.control_command_receiver.try_recv() {
+                // pressed after the previous command had already finished
+                while self.pause_receiver.try_recv().is_ok() {}
+
+                let last_update = Cell::new(Instant::now());
                 let hook = || {
                     self.receive_io_updates();
-                    self.send_update();
+                    self.receive_pause();
+                    if last_update.get().elapsed() >= UPDATE_INTERVAL {
+                        self.send_update();
+                        last_update.set(Instant::now());
+                    } else {
+                        self.send_waveform_sample();
+                    }
                 };
+
+                // (when the current speed was set, cycles ran at that point)
+                let clock_start = Cell::new((Instant::now(), self.core.get_cycles_ran()));
+                let cycles_per_second = Cell::new(self.cycles_per_second);
+                let paced_hook = || {
+                    hook();
+                    while let Ok(new_cycles_per_second) = self.clock_speed_receiver.try_recv() {
+                        cycles_per_second.set(new_cycles_per_second);
+                        clock_start.set((Instant::now(), self.core.get_cycles_ran()));
+                    }
+                    let (start, start_cycles) = clock_start.get();
+                    let target = Duration::from_secs_f64(
+                        (self.core.get_cycles_ran() - start_cycles) as f64
+                            / cycles_per_second.get().max(1) as f64,
+                    );
+                    // sleep in short steps so that pausing a slow clock does not lag
+                    while let Some(remaining) = target.checked_sub(start.elapsed()) {
+                        self.receive_io_updates();
+                        if self.pause_receiver.try_recv().is_ok() {
+                            self.core.pause();
+                            break;
+                        }
+                        std::thread::sleep(remaining.min(PAUSE_POLL_INTERVAL));
+                    }
+                };
+
                 match command {
                     RunCycle => {
                         self.core.run_cycle(Some(hook));
@@ -180,29 +440,76 @@ impl CoreGuiWrapper {
                     RunEnd => {
                         self.core.run_end(Some(hook));
                     }
+                    Run => {
+                        self.core.run_free(&self.breakpoints, Some(paced_hook));
+                    }
+                    FastForward => {
+                        self.core.fast_forward(&self.breakpoints, None, Some(hook));
+                    }
+                    StepBackCycle => {
+                        self.core.step_back_cycle();
+                    }
+                    StepBackInstruction => {
+                        self.core.step_back_instruction();
+                    }
                     Reset => {
                         self.core.reset();
-                        self.send_update();
                     }
                 }
+                self.cycles_per_second = cycles_per_second.get();
+                self.send_update();
                 self.control_ack_sender.try_send(()).unwrap();
             }
         }
     }
 }
 
+pub const DEFAULT_CYCLES_PER_SECOND: u32 = 10;
+
 pub enum ControlCommand {
     RunCycle,
     RunInstructions,
     RunUntilAddr,
     RunEnd,
+    /// Free running at `CoreGuiWrapper::cycles_per_second` until paused
+    Run,
+    FastForward,
+    StepBackCycle,
+    StepBackInstruction,
     Reset,
 }
 
-#[derive(Clone)]
+/// The bytes of `addr_range` as of the last update
+#[derive(Clone, Default, PartialEq)]
+pub struct MemoryWindow {
+    pub addr_range: Range<u32>,
+    pub data: Vec<Byte>,
+}
+
+impl MemoryWindow {
+    /// Unknown outside of `addr_range`
+    pub fn get(&self, addr: u32) -> Byte {
+        if self.addr_range.contains(&addr) {
+            self.data[(addr - self.addr_range.start) as usize]
+        } else {
+            Byte::unknown()
+        }
+    }
+}
+
+/// A file picked in the memory tab, see `Core::load_image`
+pub struct LoadImage {
+    pub file_name: String,
+    pub data: Vec<u8>,
+    /// Where flat binaries and readmemh images start
+    pub base: u32,
+}
+
+#[derive(Clone, PartialEq)]
 pub struct RegisterData {
     pub state: String,
     pub ir: String,
     pub pc: String,
     pub regfile: [String; 32],
+    pub csrs: Vec<(&'static str, String)>,
 }
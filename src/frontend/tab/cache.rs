@@ -0,0 +1,309 @@
+use crate::backend::component::cache::{
+    self, CacheConfig, CacheType, ReplacementPolicy, WritePolicy,
+};
+use crate::backend::util::types::{Byte, Word};
+use crate::frontend::tab::Tab;
+use crossbeam_channel::{Receiver, Sender};
+use egui::{Context, Ui};
+use egui_extras::{Column, Size, StripBuilder, TableBuilder};
+use strum::IntoEnumIterator;
+
+const MIN_SIZE: u32 = 0x40;
+
+pub struct Cache {
+    cache_config_sender: Sender<(CacheType, CacheConfig)>,
+    /// The instruction cache and the data cache
+    cache_receiver: Receiver<(cache::Cache, cache::Cache)>,
+    caches: Option<(cache::Cache, cache::Cache)>,
+    cache_type: CacheType,
+    /// What is being edited, it takes effect on apply
+    config: CacheConfig,
+}
+
+impl Tab for Cache {
+    fn name(&self) -> &'static str {
+        "▦ Cache"
+    }
+
+    fn show(&mut self, ctx: &Context, open: &mut bool) {
+        while let Ok(caches) = self.cache_receiver.try_recv() {
+            if self.caches.is_none() {
+                self.config = Self::get_cache(&caches, self.cache_type).get_config();
+            }
+            self.caches = Some(caches);
+        }
+
+        egui::Window::new(self.name())
+            .open(open)
+            .resizable([false, true])
+            .default_width(400.0)
+            .default_height(500.0)
+            .show(ctx, |ui| {
+                self.ui(ctx, ui);
+            });
+    }
+
+    fn ui(&mut self, _ctx: &Context, ui: &mut Ui) {
+        let Some(caches) = &self.caches else {
+            return;
+        };
+        let cache = Self::get_cache(caches, self.cache_type).clone();
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Cache: ");
+                for cache_type in CacheType::iter() {
+                    if ui
+                        .radio_value(&mut self.cache_type, cache_type, cache_type.to_string())
+                        .clicked()
+                    {
+                        self.config =
+                            Self::get_cache(self.caches.as_ref().unwrap(), cache_type).get_config();
+                    }
+                }
+            });
+
+            ui.separator();
+
+            self.config_ui(ui, &cache);
+
+            ui.separator();
+
+            ui.label(cache.get_stats().to_string());
+
+            ui.separator();
+
+            StripBuilder::new(ui)
+                .size(Size::remainder().at_least(200.0))
+                .vertical(|mut strip| {
+                    strip.cell(|ui| {
+                        // long lines do not fit in the window
+                        egui::ScrollArea::horizontal().show(ui, |ui| {
+                            Self::table_ui(ui, &cache);
+                        });
+                    });
+                });
+        });
+    }
+}
+
+impl Cache {
+    pub fn new(
+        cache_config_sender: Sender<(CacheType, CacheConfig)>,
+        cache_receiver: Receiver<(cache::Cache, cache::Cache)>,
+    ) -> Self {
+        Self {
+            cache_config_sender,
+            cache_receiver,
+            caches: None,
+            cache_type: CacheType::Instruction,
+            config: Default::default(),
+        }
+    }
+
+    fn get_cache(caches: &(cache::Cache, cache::Cache), cache_type: CacheType) -> &cache::Cache {
+        match cache_type {
+            CacheType::Instruction => &caches.0,
+            CacheType::Data => &caches.1,
+        }
+    }
+
+    fn config_ui(&mut self, ui: &mut Ui, cache: &cache::Cache) {
+        egui::Grid::new("cache_config_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Enabled");
+                ui.checkbox(&mut self.config.is_enabled, "");
+                ui.end_row();
+
+                ui.label("Size");
+                Self::power_of_two_ui(
+                    ui,
+                    "cache_size",
+                    &mut self.config.size,
+                    MIN_SIZE..=cache::MAX_SIZE,
+                );
+                ui.end_row();
+
+                ui.label("Line Size");
+                Self::power_of_two_ui(
+                    ui,
+                    "cache_line_size",
+                    &mut self.config.line_size,
+                    4..=cache::MAX_LINE_SIZE,
+                );
+                ui.end_row();
+
+                ui.label("Associativity");
+                ui.add(
+                    egui::DragValue::new(&mut self.config.associativity)
+                        .range(1..=cache::MAX_SIZE / 4)
+                        .suffix("-way"),
+                );
+                ui.end_row();
+
+                ui.label("Replacement");
+                egui::ComboBox::from_id_salt("cache_replacement_policy")
+                    .selected_text(self.config.replacement_policy.to_string())
+                    .show_ui(ui, |ui| {
+                        for t in ReplacementPolicy::iter() {
+                            ui.selectable_value(
+                                &mut self.config.replacement_policy,
+                                t,
+                                t.to_string(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Write Policy");
+                egui::ComboBox::from_id_salt("cache_write_policy")
+                    .selected_text(self.config.write_policy.to_string())
+                    .show_ui(ui, |ui| {
+                        for t in WritePolicy::iter() {
+                            ui.selectable_value(&mut self.config.write_policy, t, t.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Write-back: stores only go to the line, memory gets them when the line \
+                         is evicted\n\
+                         Write-through: stores go to memory as well, a store that misses does not \
+                         fill a line",
+                    );
+                ui.end_row();
+
+                ui.label("Miss Penalty");
+                ui.add(
+                    egui::DragValue::new(&mut self.config.miss_penalty)
+                        .range(0..=1000)
+                        .suffix(" cycles"),
+                )
+                .on_hover_text(
+                    "Cycles memory takes to start filling a line, writing one back or writing a \
+                     word through, on top of moving the words",
+                );
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            let error = self.config.validate().err();
+            let has_changed = self.config != cache.get_config();
+            if ui
+                .add_enabled(error.is_none() && has_changed, egui::Button::new("Apply"))
+                .on_hover_text("Starts the cache over cold, stepping back stops here")
+                .clicked()
+            {
+                self.cache_config_sender
+                    .try_send((self.cache_type, self.config))
+                    .unwrap();
+            }
+            if ui
+                .add_enabled(has_changed, egui::Button::new("Revert"))
+                .clicked()
+            {
+                self.config = cache.get_config();
+            }
+            if let Some(error) = error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+        });
+    }
+
+    fn power_of_two_ui(
+        ui: &mut Ui,
+        id_salt: &str,
+        value: &mut u32,
+        range: std::ops::RangeInclusive<u32>,
+    ) {
+        egui::ComboBox::from_id_salt(id_salt)
+            .selected_text(format!("{} B", value))
+            .show_ui(ui, |ui| {
+                let mut choice = *range.start();
+                while choice <= *range.end() {
+                    ui.selectable_value(value, choice, format!("{} B", choice));
+                    choice *= 2;
+                }
+            });
+    }
+
+    /// The words of a line from the lowest address, unknown bytes as XX
+    fn format_data(data: &[Byte]) -> String {
+        data.chunks(4)
+            .map(|chunk| {
+                let mut word = Word::unknown();
+                for (i, byte) in chunk.iter().enumerate() {
+                    word[i] = (*byte).into();
+                }
+                format!("{:X}", word)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn table_ui(ui: &mut Ui, cache: &cache::Cache) {
+        let text_height = egui::TextStyle::Body
+            .resolve(ui.style())
+            .size
+            .max(ui.spacing().interact_size.y);
+        let sets = cache.get_sets();
+        let num_ways = sets.first().map_or(0, |set| set.len());
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(false)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto().at_least(40.0))
+            .column(Column::auto().at_least(40.0))
+            .column(Column::auto().at_least(20.0))
+            .column(Column::auto().at_least(20.0))
+            .column(Column::auto().at_least(90.0))
+            .column(Column::auto().at_least(90.0))
+            .column(Column::remainder().at_least(90.0))
+            .header(20.0, |mut header| {
+                for name in ["Set", "Way", "V", "D", "Tag", "Address", "Data"] {
+                    header.col(|ui| {
+                        ui.strong(name);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(text_height, sets.len() * num_ways, |mut row| {
+                    let set_idx = row.index() / num_ways;
+                    let way = row.index() % num_ways;
+                    let line = &sets[set_idx][way];
+                    row.col(|ui| {
+                        ui.label(set_idx.to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(way.to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(if line.is_valid { "1" } else { "0" });
+                    });
+                    row.col(|ui| {
+                        ui.label(if line.is_dirty { "1" } else { "0" });
+                    });
+                    if line.is_valid {
+                        row.col(|ui| {
+                            ui.monospace(format!("0x{:X}", line.tag));
+                        });
+                        row.col(|ui| {
+                            let addr = cache.get_line_addr(set_idx, line);
+                            ui.monospace(format!(
+                                "0x{:08X}..0x{:08X}",
+                                addr,
+                                addr as u64 + cache.get_config().line_size as u64
+                            ));
+                        });
+                        row.col(|ui| {
+                            ui.monospace(Self::format_data(&line.data));
+                        });
+                    } else {
+                        row.col(|_| {});
+                        row.col(|_| {});
+                        row.col(|_| {});
+                    }
+                })
+            });
+    }
+}
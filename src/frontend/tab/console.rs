@@ -14,6 +14,8 @@ pub struct Console {
     vga_buffer_receiver: Receiver<[u8; VgaMmioCtl::NUM_BYTES]>,
     vga_buffer: Option<[u8; VgaMmioCtl::NUM_BYTES]>,
     keyboard_buffer_sender: Sender<u8>,
+    htif_output_receiver: Receiver<Vec<u8>>,
+    htif_output: String,
     input_buffer: String,
     last_vga_update_instant: Instant,
     texture_handle: Option<TextureHandle>,
@@ -23,11 +25,14 @@ impl Console {
     pub fn new(
         vga_buffer_receiver: Receiver<[u8; VgaMmioCtl::NUM_BYTES]>,
         keyboard_buffer_sender: Sender<u8>,
+        htif_output_receiver: Receiver<Vec<u8>>,
     ) -> Self {
         Console {
             vga_buffer_receiver,
             vga_buffer: None,
             keyboard_buffer_sender,
+            htif_output_receiver,
+            htif_output: String::new(),
             input_buffer: String::new(),
             last_vga_update_instant: Instant::now(),
             texture_handle: None,
@@ -44,6 +49,10 @@ impl Tab for Console {
         while let Ok(vga_buffer) = self.vga_buffer_receiver.try_recv() {
             self.vga_buffer = Some(vga_buffer);
         }
+        while let Ok(htif_output) = self.htif_output_receiver.try_recv() {
+            self.htif_output
+                .push_str(&String::from_utf8_lossy(&htif_output));
+        }
 
         egui::Window::new(self.name())
             .open(open)
@@ -77,11 +86,23 @@ impl Tab for Console {
             )));
         }
 
+        ui.strong("HTIF Output").on_hover_ui(|ui| {
+            ui.label("Characters the program putchar-ed through tohost.\nA pending getchar takes the next text input instead of the keyboard.");
+        });
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(120.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                ui.monospace(&self.htif_output);
+            });
+
         ui.strong("Text Input").on_hover_ui(|ui| {
             ui.label(format!(
-                "Status Address @ 0x{:08X}\nData Address @ 0x{:08X}",
+                "Status Address @ 0x{:08X}\nData Address @ 0x{:08X}\nInterrupt Enable Address @ 0x{:08X}",
                 KeyboardMmioCtl::STATUS_ADDR,
-                KeyboardMmioCtl::DATA_ADDR
+                KeyboardMmioCtl::DATA_ADDR,
+                KeyboardMmioCtl::INTERRUPT_ENABLE_ADDR
             ));
         });
         ui.separator();
@@ -1,5 +1,5 @@
-use crate::frontend::core_gui_wrapper::ControlCommand;
 use crate::frontend::core_gui_wrapper::ControlCommand::*;
+use crate::frontend::core_gui_wrapper::{ControlCommand, DEFAULT_CYCLES_PER_SECOND};
 use crate::frontend::tab::Tab;
 use crossbeam_channel::{Receiver, Sender};
 use egui::{Context, Ui};
@@ -7,14 +7,25 @@ use egui::{Context, Ui};
 pub struct Control {
     command_sender: Sender<ControlCommand>,
     ack_receiver: Receiver<()>,
+    pause_sender: Sender<()>,
+    clock_speed_sender: Sender<u32>,
+    cycles_per_second: u32,
     ready: bool,
 }
 
 impl Control {
-    pub fn new(command_sender: Sender<ControlCommand>, ack_receiver: Receiver<()>) -> Self {
+    pub fn new(
+        command_sender: Sender<ControlCommand>,
+        ack_receiver: Receiver<()>,
+        pause_sender: Sender<()>,
+        clock_speed_sender: Sender<u32>,
+    ) -> Self {
         Control {
             command_sender,
             ack_receiver,
+            pause_sender,
+            clock_speed_sender,
+            cycles_per_second: DEFAULT_CYCLES_PER_SECOND,
             ready: true,
         }
     }
@@ -43,6 +54,12 @@ impl Tab for Control {
             ui.horizontal(|ui| {
                 if self.ready {
                     let mut core_command = None;
+                    if ui.button("Previous Instruction").clicked() {
+                        core_command = Some(StepBackInstruction)
+                    }
+                    if ui.button("Previous Cycle").clicked() {
+                        core_command = Some(StepBackCycle)
+                    }
                     if ui.button("Next Cycle").clicked() {
                         core_command = Some(RunCycle)
                     }
@@ -55,6 +72,20 @@ impl Tab for Control {
                     if ui.button("Finish").clicked() {
                         core_command = Some(RunEnd)
                     }
+                    if ui
+                        .button("Run")
+                        .on_hover_text("Run at the clock speed below until paused or a breakpoint")
+                        .clicked()
+                    {
+                        core_command = Some(Run)
+                    }
+                    if ui
+                        .button("Fast Forward")
+                        .on_hover_text("Run to the next breakpoint without simulating the datapath")
+                        .clicked()
+                    {
+                        core_command = Some(FastForward)
+                    }
                     if ui.button("Reset").clicked() {
                         core_command = Some(Reset)
                     }
@@ -64,6 +95,22 @@ impl Tab for Control {
                     }
                 } else {
                     ui.spinner();
+                    if ui.button("Pause").clicked() {
+                        self.pause_sender.try_send(()).unwrap();
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Clock Speed: ");
+                let response = ui.add(
+                    egui::Slider::new(&mut self.cycles_per_second, 1..=1_000_000)
+                        .logarithmic(true)
+                        .suffix(" cycles/s"),
+                );
+                if response.changed() {
+                    self.clock_speed_sender
+                        .try_send(self.cycles_per_second)
+                        .unwrap();
                 }
             });
         });
@@ -0,0 +1,165 @@
+use crate::backend::assembler::{AsmError, assemble};
+use crate::backend::loader::Program;
+use crate::frontend::tab::Tab;
+use crossbeam_channel::Sender;
+use egui::text::LayoutJob;
+use egui::{Context, RichText, TextFormat, TextStyle, Ui};
+use std::collections::BTreeSet;
+
+const EXAMPLE: &str = "# a0 = 1 + 2 + ... + 10, stored to result
+.text
+_start:
+    li a0, 10
+    call sum
+    la t0, result
+    sw a0, 0(t0)
+    slti zero, zero, -256   # ends the simulation
+
+sum:
+    mv t0, a0
+    li a0, 0
+loop:
+    add a0, a0, t0
+    addi t0, t0, -1
+    bne t0, zero, loop
+    ret
+
+.data
+result:
+    .word 0
+message:
+    .asciz \"Hello\"
+";
+
+pub struct Editor {
+    source: String,
+    errors: Vec<AsmError>,
+    is_loaded: bool,
+    load_program_sender: Sender<Program>,
+}
+
+impl Editor {
+    pub fn new(load_program_sender: Sender<Program>) -> Self {
+        Editor {
+            source: EXAMPLE.to_string(),
+            errors: Vec::new(),
+            is_loaded: false,
+            load_program_sender,
+        }
+    }
+
+    fn assemble(&mut self) {
+        match assemble(&self.source) {
+            Ok(program) => {
+                self.errors.clear();
+                self.is_loaded = true;
+                self.load_program_sender.try_send(program).unwrap();
+            }
+            Err(errors) => {
+                self.errors = errors;
+                self.is_loaded = false;
+            }
+        }
+    }
+
+    /// Line numbers, with a marker on the lines that failed to assemble
+    fn gutter_ui(&self, ui: &mut Ui, error_lines: &BTreeSet<usize>) {
+        let num_lines = self.source.split('\n').count();
+        let gutter = (0..num_lines)
+            .map(|line| {
+                let marker = if error_lines.contains(&line) {
+                    "✖"
+                } else {
+                    " "
+                };
+                format!("{}{:>4}", marker, line + 1)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        ui.label(
+            RichText::new(gutter)
+                .monospace()
+                .color(ui.visuals().weak_text_color()),
+        );
+    }
+}
+
+impl Tab for Editor {
+    fn name(&self) -> &'static str {
+        "✏ Editor"
+    }
+
+    fn show(&mut self, ctx: &Context, open: &mut bool) {
+        egui::Window::new(self.name())
+            .open(open)
+            .default_width(500.0)
+            .default_height(600.0)
+            .show(ctx, |ui| {
+                self.ui(ctx, ui);
+            });
+    }
+
+    fn ui(&mut self, _ctx: &Context, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Assemble & Load").clicked() {
+                self.assemble();
+            }
+            if self.is_loaded {
+                ui.label("Loaded, run it from the Control tab");
+            } else if !self.errors.is_empty() {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("{} errors", self.errors.len()),
+                );
+            }
+        })
+        .response
+        .on_hover_ui(|ui| {
+            ui.label("Loading resets the core.\n.text starts at 0x40000000, .data at 0x50000000 and execution at _start if it is defined");
+        });
+        ui.separator();
+
+        for error in self.errors.iter() {
+            ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+        }
+        if !self.errors.is_empty() {
+            ui.separator();
+        }
+
+        let error_lines = self
+            .errors
+            .iter()
+            .map(|error| error.line)
+            .collect::<BTreeSet<_>>();
+        let mut layouter = |ui: &Ui, text: &str, _wrap_width: f32| {
+            let font_id = TextStyle::Monospace.resolve(ui.style());
+            let mut job = LayoutJob::default();
+            for (line, line_text) in text.split_inclusive('\n').enumerate() {
+                let mut format = TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+                if error_lines.contains(&line) {
+                    format.background = ui.visuals().error_fg_color.gamma_multiply(0.3);
+                }
+                job.append(line_text, 0.0, format);
+            }
+            // lines do not wrap so that they stay next to their line number
+            job.wrap.max_width = f32::INFINITY;
+            ui.fonts(|fonts| fonts.layout_job(job))
+        };
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            ui.horizontal_top(|ui| {
+                self.gutter_ui(ui, &error_lines);
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut self.source)
+                        .code_editor()
+                        .desired_rows(30)
+                        .desired_width(f32::INFINITY)
+                        .layouter(&mut layouter),
+                );
+                if response.changed() {
+                    self.is_loaded = false;
+                }
+            });
+        });
+    }
+}
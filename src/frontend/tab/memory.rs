@@ -1,5 +1,11 @@
+use crate::backend::assembler::TEXT_BASE;
+use crate::backend::component::mem_ctl::{
+    AccessFault, MemMapType, Watchpoint, WatchpointHit, WatchpointType,
+};
+use crate::backend::disassembler::disassemble;
 use crate::backend::util::byte::Bytes;
 use crate::backend::util::types::{Byte, Word};
+use crate::frontend::core_gui_wrapper::{LoadImage, MemoryWindow};
 use crate::frontend::tab::Tab;
 use crossbeam_channel::{Receiver, Sender};
 use egui::{Context, Ui};
@@ -8,6 +14,8 @@ use egui_extras::TableBuilder;
 use egui_extras::{Column, StripBuilder};
 use std::collections::{BTreeMap, BTreeSet};
 use std::future::Future;
+use std::ops::Range;
+use strum::IntoEnumIterator;
 
 const NUM_ROWS: usize = 0x200;
 
@@ -18,37 +26,97 @@ enum AlignmentType {
     Word,
 }
 
+#[derive(PartialEq)]
+enum ViewType {
+    Memory,
+    /// Words as instructions with the pc highlighted, follows the pc as it runs
+    Disassembly,
+}
+
 pub struct Memory {
     offset: usize,
     offset_str: String,
     alignment_type: AlignmentType,
+    view_type: ViewType,
     breakpoints_sender: Sender<BTreeSet<Word>>,
     breakpoints: BTreeSet<Word>,
-    memory_receiver: Receiver<BTreeMap<Word, Byte>>,
-    memory: BTreeMap<Word, Byte>,
+    watchpoints_sender: Sender<Vec<Watchpoint>>,
+    watchpoints: Vec<Watchpoint>,
+    watchpoint_hit_receiver: Receiver<Option<WatchpointHit>>,
+    watchpoint_hit: Option<WatchpointHit>,
+    mem_map_type_sender: Sender<MemMapType>,
+    mem_map_type: MemMapType,
+    access_fault_receiver: Receiver<Option<AccessFault>>,
+    access_fault: Option<AccessFault>,
+    memory_window_sender: Sender<Range<u32>>,
+    /// The last window asked for
+    memory_window: Range<u32>,
+    memory_receiver: Receiver<MemoryWindow>,
+    memory: MemoryWindow,
     label_receiver: Receiver<BTreeMap<Word, String>>,
     label: BTreeMap<Word, String>,
-    load_elf_sender: Sender<Vec<u8>>,
+    pc_receiver: Receiver<Word>,
+    pc: Word,
+    scroll_to_pc: bool,
+    load_image_sender: Sender<LoadImage>,
+    load_base: u32,
+    load_base_str: String,
+    load_checkpoint_sender: Sender<Vec<u8>>,
+    load_error_receiver: Receiver<Option<String>>,
+    load_error: Option<String>,
+    save_checkpoint_request_sender: Sender<()>,
+    save_checkpoint_receiver: Receiver<Vec<u8>>,
 }
 
 impl Memory {
     pub fn new(
         breakpoints_sender: Sender<BTreeSet<Word>>,
-        memory_receiver: Receiver<BTreeMap<Word, Byte>>,
+        watchpoints_sender: Sender<Vec<Watchpoint>>,
+        watchpoint_hit_receiver: Receiver<Option<WatchpointHit>>,
+        mem_map_type_sender: Sender<MemMapType>,
+        access_fault_receiver: Receiver<Option<AccessFault>>,
+        memory_window_sender: Sender<Range<u32>>,
+        memory_receiver: Receiver<MemoryWindow>,
         label_receiver: Receiver<BTreeMap<Word, String>>,
-        load_elf_sender: Sender<Vec<u8>>,
+        pc_receiver: Receiver<Word>,
+        load_image_sender: Sender<LoadImage>,
+        load_checkpoint_sender: Sender<Vec<u8>>,
+        load_error_receiver: Receiver<Option<String>>,
+        save_checkpoint_request_sender: Sender<()>,
+        save_checkpoint_receiver: Receiver<Vec<u8>>,
     ) -> Memory {
         Memory {
             offset: 0x40000000usize,
             offset_str: "0x40000000".to_string(),
             alignment_type: AlignmentType::Word,
+            view_type: ViewType::Memory,
             breakpoints_sender,
             breakpoints: BTreeSet::new(),
+            watchpoints_sender,
+            watchpoints: Vec::new(),
+            watchpoint_hit_receiver,
+            watchpoint_hit: None,
+            mem_map_type_sender,
+            mem_map_type: Default::default(),
+            access_fault_receiver,
+            access_fault: None,
+            memory_window_sender,
+            memory_window: 0..0,
             memory_receiver,
-            memory: BTreeMap::new(),
+            memory: Default::default(),
             label_receiver,
             label: BTreeMap::new(),
-            load_elf_sender,
+            pc_receiver,
+            pc: Word::unknown(),
+            scroll_to_pc: false,
+            load_image_sender,
+            load_base: TEXT_BASE,
+            load_base_str: format!("0x{:X}", TEXT_BASE),
+            load_checkpoint_sender,
+            load_error_receiver,
+            load_error: None,
+            save_checkpoint_request_sender,
+            save_checkpoint_receiver,
         }
     }
 
@@ -58,14 +126,30 @@ impl Memory {
             .size
             .max(ui.spacing().interact_size.y);
 
-        let table = TableBuilder::new(ui)
+        let is_disassembly_view = self.view_type == ViewType::Disassembly;
+        let byte_width = self.get_byte_width();
+        let has_disassembly = byte_width == 4;
+
+        let mut table = TableBuilder::new(ui)
             .striped(true)
             .resizable(false)
             .animate_scrolling(true)
             .column(Column::auto().at_least(100.0))
             .column(Column::auto().at_least(100.0))
-            .column(Column::auto().at_least(100.0))
-            .column(Column::auto().at_least(90.0));
+            .column(Column::auto().at_least(100.0));
+        if has_disassembly {
+            table = table.column(Column::auto().at_least(200.0));
+        }
+        table = table.column(Column::auto().at_least(90.0));
+        if !is_disassembly_view {
+            table = table.column(Column::auto().at_least(90.0));
+        }
+        if self.scroll_to_pc {
+            self.scroll_to_pc = false;
+            if let Some(row) = self.get_row(self.pc) {
+                table = table.scroll_to_row(row, Some(egui::Align::Center));
+            }
+        }
 
         table
             .header(20.0, |mut header| {
@@ -84,26 +168,31 @@ impl Memory {
                 header.col(|ui| {
                     ui.strong("Value");
                 });
+                if has_disassembly {
+                    header.col(|ui| {
+                        ui.strong("Disassembly");
+                    });
+                }
                 header.col(|ui| {
                     ui.strong("Breakpoint");
                 });
+                if !is_disassembly_view {
+                    header.col(|ui| {
+                        ui.strong("Watchpoint");
+                    });
+                }
             })
             .body(|body| {
                 body.rows(text_height, NUM_ROWS, |mut row| {
-                    let byte_width = match self.alignment_type {
-                        AlignmentType::Byte => 1u8,
-                        AlignmentType::HalfWord => 2u8,
-                        AlignmentType::Word => 4u8,
-                    };
-
-                    let row_index =
-                        Word::from((row.index() * byte_width as usize + self.offset) as u32);
+                    let raw_row_index = (row.index() * byte_width as usize + self.offset) as u32;
+                    let row_index = Word::from(raw_row_index);
                     let mut value = Word::unknown();
                     for i in 0..byte_width {
-                        let addr = row_index + Byte::from(i);
-                        if let Some(byte_value) = self.memory.get(&addr) {
-                            value[i as usize] = (*byte_value).into();
-                        }
+                        value[i as usize] =
+                            self.memory.get(raw_row_index.wrapping_add(i as u32)).into();
+                    }
+                    if is_disassembly_view {
+                        row.set_selected(row_index == self.pc);
                     }
 
                     row.col(|ui| {
@@ -116,21 +205,29 @@ impl Memory {
                         ui.label(format!("{}", row_index));
                     });
 
-                    match self.alignment_type {
-                        AlignmentType::Byte => {
+                    match byte_width {
+                        1 => {
                             row.col(|ui| {
                                 ui.label(format!("{}", Byte::from(0xFFu8) & value));
                             });
                         }
-                        AlignmentType::HalfWord => {
+                        2 => {
                             row.col(|ui| {
                                 ui.label(format!("{}", Bytes::<2>::from(0xFFFFu16) & value));
                             });
                         }
-                        AlignmentType::Word => {
+                        _ => {
                             row.col(|ui| {
                                 ui.label(format!("{}", value));
                             });
+                            row.col(|ui| {
+                                if let (Some(inst), Some(pc)) = (
+                                    Into::<Option<u32>>::into(value),
+                                    Into::<Option<u32>>::into(row_index),
+                                ) {
+                                    ui.monospace(disassemble(inst, pc));
+                                }
+                            });
                         }
                     };
 
@@ -148,27 +245,167 @@ impl Memory {
                             .try_send(self.breakpoints.clone())
                             .unwrap();
                     });
+
+                    if is_disassembly_view {
+                        return;
+                    }
+
+                    row.col(|ui| {
+                        let addr_range = raw_row_index..raw_row_index + byte_width as u32;
+                        let old_watchpoint_type = self
+                            .watchpoints
+                            .iter()
+                            .find(|watchpoint| watchpoint.addr_range == addr_range)
+                            .map(|watchpoint| watchpoint.watchpoint_type);
+                        let mut watchpoint_type = old_watchpoint_type;
+                        egui::ComboBox::from_id_salt(raw_row_index)
+                            .selected_text(
+                                watchpoint_type.map_or("".to_string(), |t| t.to_string()),
+                            )
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut watchpoint_type, None, "None");
+                                for t in WatchpointType::iter() {
+                                    ui.selectable_value(
+                                        &mut watchpoint_type,
+                                        Some(t),
+                                        t.to_string(),
+                                    );
+                                }
+                            });
+                        if watchpoint_type != old_watchpoint_type {
+                            self.watchpoints
+                                .retain(|watchpoint| watchpoint.addr_range != addr_range);
+                            if let Some(watchpoint_type) = watchpoint_type {
+                                self.watchpoints.push(Watchpoint {
+                                    addr_range,
+                                    watchpoint_type,
+                                });
+                            }
+                            self.watchpoints_sender
+                                .try_send(self.watchpoints.clone())
+                                .unwrap();
+                        }
+                    });
                 });
             });
     }
 
+    /// The addresses of the rows, the core only sends the memory in it
+    fn get_memory_window(&self) -> Range<u32> {
+        let start = self.offset as u32;
+        start..start.saturating_add((NUM_ROWS * self.get_byte_width() as usize) as u32)
+    }
+
+    /// Disassembly always shows words
+    fn get_byte_width(&self) -> u8 {
+        if self.view_type == ViewType::Disassembly {
+            return 4;
+        }
+        match self.alignment_type {
+            AlignmentType::Byte => 1,
+            AlignmentType::HalfWord => 2,
+            AlignmentType::Word => 4,
+        }
+    }
+
+    /// The table row showing `addr`, if it is within the rows shown
+    fn get_row(&self, addr: Word) -> Option<usize> {
+        let addr = Into::<Option<u32>>::into(addr)? as usize;
+        let byte_width = self.get_byte_width() as usize;
+        addr.checked_sub(self.offset)
+            .map(|diff| diff / byte_width)
+            .filter(|row| *row < NUM_ROWS)
+    }
+
+    /// Moves the rows so that the pc is shown and scrolls to it
+    fn follow_pc(&mut self) {
+        let Some(pc) = Into::<Option<u32>>::into(self.pc) else {
+            return;
+        };
+        if self.get_row(self.pc).is_none() {
+            self.offset = (pc & !0x3) as usize;
+            self.offset_str = format!("0x{:X}", self.offset);
+        }
+        self.scroll_to_pc = true;
+    }
+
     fn file_picker_ui(&mut self, _ctx: &Context, ui: &mut Ui) {
         ui.horizontal(|ui| {
-            if ui.button("Load ELF").clicked() {
+            if ui
+                .button("Load Program")
+                .on_hover_text(
+                    "ELF, flat binary (.bin), Intel HEX or $readmemh hex, detected from the file.\n\
+                     Binaries and readmemh images are loaded at the base address",
+                )
+                .clicked()
+            {
                 let task = rfd::AsyncFileDialog::new().pick_file();
                 let ctx = ui.ctx().clone();
-                let load_bin_sender = self.load_elf_sender.clone();
+                let load_image_sender = self.load_image_sender.clone();
+                let base = self.load_base;
+                execute(async move {
+                    let file = task.await;
+                    if let Some(file) = file {
+                        let data = file.read().await;
+                        load_image_sender
+                            .try_send(LoadImage {
+                                file_name: file.file_name(),
+                                data,
+                                base,
+                            })
+                            .unwrap();
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            ui.label("Base Address: ");
+            if ui
+                .add(egui::TextEdit::singleline(&mut self.load_base_str).desired_width(90.0))
+                .lost_focus()
+            {
+                let trimmed_load_base_str = self.load_base_str.trim_start_matches("0x");
+                self.load_base =
+                    u32::from_str_radix(trimmed_load_base_str, 16).unwrap_or(self.load_base);
+                self.load_base_str = format!("0x{:X}", self.load_base);
+            }
+            if ui.button("Load State").clicked() {
+                let task = rfd::AsyncFileDialog::new()
+                    .add_filter("checkpoint", &["rsim"])
+                    .pick_file();
+                let ctx = ui.ctx().clone();
+                let load_checkpoint_sender = self.load_checkpoint_sender.clone();
                 execute(async move {
                     let file = task.await;
                     if let Some(file) = file {
                         let bytes = file.read().await;
-                        load_bin_sender.try_send(bytes).unwrap();
+                        load_checkpoint_sender.try_send(bytes).unwrap();
                         ctx.request_repaint();
                     }
                 });
             }
+            if ui.button("Save State").clicked() {
+                self.save_checkpoint_request_sender.try_send(()).unwrap();
+            }
         });
     }
+
+    /// Asks where to save the checkpoint once the core thread has serialized it
+    fn save_checkpoint(&mut self) {
+        while let Ok(bytes) = self.save_checkpoint_receiver.try_recv() {
+            let task = rfd::AsyncFileDialog::new()
+                .add_filter("checkpoint", &["rsim"])
+                .set_file_name("checkpoint.rsim")
+                .save_file();
+            execute(async move {
+                let file = task.await;
+                if let Some(file) = file {
+                    if let Err(err) = file.write(&bytes).await {
+                        log::error!("Failed to save checkpoint: {}", err);
+                    }
+                }
+            });
+        }
+    }
 }
 
 impl Tab for Memory {
@@ -180,9 +417,34 @@ impl Tab for Memory {
         while let Ok(memory) = self.memory_receiver.try_recv() {
             self.memory = memory;
         }
+        let memory_window = self.get_memory_window();
+        if memory_window != self.memory_window {
+            self.memory_window = memory_window;
+            self.memory_window_sender
+                .try_send(self.memory_window.clone())
+                .unwrap();
+        }
         while let Ok(label) = self.label_receiver.try_recv() {
             self.label = label;
         }
+        while let Ok(watchpoint_hit) = self.watchpoint_hit_receiver.try_recv() {
+            self.watchpoint_hit = watchpoint_hit;
+        }
+        while let Ok(access_fault) = self.access_fault_receiver.try_recv() {
+            self.access_fault = access_fault;
+        }
+        while let Ok(load_error) = self.load_error_receiver.try_recv() {
+            self.load_error = load_error;
+        }
+        while let Ok(pc) = self.pc_receiver.try_recv() {
+            if pc != self.pc {
+                self.pc = pc;
+                if self.view_type == ViewType::Disassembly {
+                    self.follow_pc();
+                }
+            }
+        }
+        self.save_checkpoint();
 
         egui::Window::new(self.name())
             .open(open)
@@ -206,7 +468,7 @@ impl Tab for Memory {
                                 format!("Failed to parse \"{}\"", self.offset_str).to_string();
                             self.offset
                         });
-                    if self.alignment_type == AlignmentType::Word {
+                    if self.get_byte_width() == 4 {
                         self.offset -= self.offset % 4;
                     }
                     self.offset_str = format!("0x{:X}", self.offset);
@@ -216,18 +478,81 @@ impl Tab for Memory {
             ui.separator();
 
             ui.horizontal(|ui| {
-                ui.label("Alignment: ");
-                ui.radio_value(&mut self.alignment_type, AlignmentType::Byte, "Byte");
-                ui.radio_value(
-                    &mut self.alignment_type,
-                    AlignmentType::HalfWord,
-                    "HalfWord",
-                );
-                ui.radio_value(&mut self.alignment_type, AlignmentType::Word, "Word");
+                ui.label("View: ");
+                ui.radio_value(&mut self.view_type, ViewType::Memory, "Memory");
+                if ui
+                    .radio_value(&mut self.view_type, ViewType::Disassembly, "Disassembly")
+                    .clicked()
+                {
+                    self.offset -= self.offset % 4;
+                    self.offset_str = format!("0x{:X}", self.offset);
+                    self.follow_pc();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(self.view_type == ViewType::Memory, |ui| {
+                    ui.label("Alignment: ");
+                    ui.radio_value(&mut self.alignment_type, AlignmentType::Byte, "Byte");
+                    ui.radio_value(
+                        &mut self.alignment_type,
+                        AlignmentType::HalfWord,
+                        "HalfWord",
+                    );
+                    ui.radio_value(&mut self.alignment_type, AlignmentType::Word, "Word");
+                });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Memory Map: ");
+                let old_mem_map_type = self.mem_map_type;
+                egui::ComboBox::from_id_salt("mem_map_type")
+                    .selected_text(self.mem_map_type.to_string())
+                    .show_ui(ui, |ui| {
+                        for t in MemMapType::iter() {
+                            ui.selectable_value(&mut self.mem_map_type, t, t.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Fetches outside of ROM, writes to ROM and accesses to unmapped addresses \
+                         raise access faults.\nLinker Script: ROM at 0x40000000 and RAM at 0x50000000 \
+                         like tests/link.ld\nELF Segments: the loaded ELF's segment flags, \
+                         then the linker script layout",
+                    );
+                if self.mem_map_type != old_mem_map_type {
+                    self.mem_map_type_sender
+                        .try_send(self.mem_map_type)
+                        .unwrap();
+                }
             });
 
             ui.separator();
 
+            if let Some(watchpoint_hit) = &self.watchpoint_hit {
+                ui.label(format!(
+                    "{} watchpoint hit @ 0x{:08X}: {} -> {}",
+                    watchpoint_hit.access_type,
+                    watchpoint_hit.addr,
+                    watchpoint_hit.old_value,
+                    watchpoint_hit.new_value
+                ));
+                ui.separator();
+            }
+
+            if let Some(access_fault) = &self.access_fault {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("Halted on an unhandled {}", access_fault),
+                );
+                ui.separator();
+            }
+
+            if let Some(load_error) = &self.load_error {
+                ui.colored_label(ui.visuals().error_fg_color, load_error);
+                ui.separator();
+            }
+
             StripBuilder::new(ui)
                 .size(Size::remainder())
                 .size(Size::exact(10.0))
@@ -1,12 +1,15 @@
 use egui::Context;
 
 pub mod about;
+pub mod cache;
 pub mod console;
 pub mod control;
 pub mod datapath;
+pub mod editor;
 pub mod memory;
 pub mod register;
 pub mod setting;
+pub mod waveform;
 
 pub trait Tab {
     fn name(&self) -> &'static str;
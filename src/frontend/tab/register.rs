@@ -6,6 +6,7 @@ use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 
 const NUM_ROWS: usize = 8;
 const NUM_COLUMNS: usize = 4;
+const CSR_COLUMNS: usize = 4;
 
 pub struct Register {
     data_receiver: Receiver<RegisterData>,
@@ -61,6 +62,23 @@ impl Tab for Register {
 
                 ui.separator();
 
+                ui.strong("CSRs");
+                ui.separator();
+                egui::Grid::new("csr_grid")
+                    .num_columns(2 * CSR_COLUMNS)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (i, (name, value)) in data.csrs.iter().enumerate() {
+                            ui.strong(*name);
+                            ui.label(value);
+                            if i % CSR_COLUMNS == CSR_COLUMNS - 1 {
+                                ui.end_row();
+                            }
+                        }
+                    });
+
+                ui.separator();
+
                 ui.strong("RegFile");
                 ui.separator();
                 StripBuilder::new(ui)
@@ -0,0 +1,469 @@
+use crate::backend::core::ComponentType;
+use crate::frontend::tab::Tab;
+use crate::frontend::tab::datapath::DatapathComponentMap;
+use crossbeam_channel::Receiver;
+use egui::epaint::{PathShape, PathStroke};
+use egui::{Align2, Color32, Context, FontId, Pos2, Rect, Sense, Stroke, Ui, Vec2};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// Cycles kept, older ones are dropped
+pub const WINDOW_CYCLES: usize = 1024;
+const ROW_HEIGHT: f32 = 24.0;
+const WAVE_HEIGHT: f32 = 16.0;
+const NAME_WIDTH: f32 = 220.0;
+const HEADER_HEIGHT: f32 = 20.0;
+
+/// The datapath after a cycle, along with the cycle
+///
+/// Only the components that changed since the previous sample are in it
+pub type WaveformSample = (u128, DatapathComponentMap);
+
+/// Appends `new` to the samples the waveform has not taken yet, keeping `WINDOW_CYCLES` of them
+///
+/// What a dropped sample changed is carried over to the one after it
+pub fn merge_waveform_samples(
+    mut old: Vec<WaveformSample>,
+    new: Vec<WaveformSample>,
+) -> Vec<WaveformSample> {
+    old.extend(new);
+    let num_dropped = old.len().saturating_sub(WINDOW_CYCLES);
+    let mut samples = old.split_off(num_dropped);
+    if let Some((_, first_components)) = samples.first_mut() {
+        for (_, dropped_components) in old.into_iter().rev() {
+            for (component_type, datapath_component) in dropped_components {
+                first_components
+                    .entry(component_type)
+                    .or_insert(datapath_component);
+            }
+        }
+    }
+    samples
+}
+
+#[derive(Display, EnumIter, Copy, Clone, PartialEq)]
+enum Radix {
+    Hex,
+    Decimal,
+    Binary,
+}
+
+impl Radix {
+    fn format(&self, value: Option<u32>) -> String {
+        match (self, value) {
+            (_, None) => "x".to_string(),
+            (Radix::Hex, Some(value)) => format!("0x{:X}", value),
+            (Radix::Decimal, Some(value)) => format!("{}", value),
+            (Radix::Binary, Some(value)) => format!("0b{:b}", value),
+        }
+    }
+}
+
+struct Sample {
+    cycle: u128,
+    /// None if any bit is unknown
+    values: HashMap<String, Option<u32>>,
+}
+
+pub struct Waveform {
+    sample_receiver: Receiver<Vec<WaveformSample>>,
+    /// The samples so far applied on top of each other
+    datapath_components: DatapathComponentMap,
+    samples: VecDeque<Sample>,
+    /// Every signal, in datapath order
+    signals: Vec<String>,
+    shown_signals: BTreeSet<String>,
+    /// Width of a cycle in points
+    zoom: f32,
+    cursor: Option<u128>,
+    radix: Radix,
+}
+
+impl Waveform {
+    pub fn new(sample_receiver: Receiver<Vec<WaveformSample>>) -> Self {
+        Self {
+            sample_receiver,
+            datapath_components: Default::default(),
+            samples: VecDeque::new(),
+            signals: Vec::new(),
+            // what happens around a memory access
+            shown_signals: [
+                "PC.out",
+                "PC.load",
+                "IR.load",
+                "IR.data",
+                "MemCtl.addr",
+                "MemCtl.resp",
+            ]
+            .map(String::from)
+            .into(),
+            zoom: 24.0,
+            cursor: None,
+            radix: Radix::Hex,
+        }
+    }
+
+    /// Parses a value as `DatapathComponentDisplayer` formats it, None if any bit is unknown
+    fn parse_value(value: &str) -> Option<u32> {
+        if let Some(digits) = value.strip_prefix("0x") {
+            u32::from_str_radix(digits, 16).ok()
+        } else if let Some(digits) = value.strip_prefix("0b") {
+            u32::from_str_radix(digits, 2).ok()
+        } else {
+            None
+        }
+    }
+
+    fn receive_sample(&mut self, (cycle, changed_components): WaveformSample) {
+        self.datapath_components.extend(changed_components);
+        let mut signals = Vec::new();
+        let mut values = HashMap::new();
+        for component_type in ComponentType::iter() {
+            let Some(datapath_component) = self.datapath_components.get(&component_type) else {
+                continue;
+            };
+            let port_values = &datapath_component.values;
+            for port_value in port_values.inputs.iter().chain(port_values.outputs.iter()) {
+                let name = format!("{}.{}", datapath_component.name, port_value.name);
+                if port_value.value.contains('\n') {
+                    // the immediates are shown together, one per line
+                    for line in port_value.value.lines() {
+                        if let Some((sub_name, value)) = line.split_once(": ") {
+                            let sub_name = format!("{}.{}", name, sub_name.trim());
+                            signals.push(sub_name.clone());
+                            values.insert(sub_name, Self::parse_value(value.trim()));
+                        }
+                    }
+                } else {
+                    signals.push(name.clone());
+                    values.insert(name, Self::parse_value(&port_value.value));
+                }
+            }
+        }
+        if self.signals.is_empty() {
+            self.signals = signals;
+        }
+
+        // stepping back or resetting rewrites what came after
+        while self
+            .samples
+            .back()
+            .is_some_and(|sample| sample.cycle >= cycle)
+        {
+            self.samples.pop_back();
+        }
+        self.samples.push_back(Sample { cycle, values });
+        while self.samples.len() > WINDOW_CYCLES {
+            self.samples.pop_front();
+        }
+    }
+
+    fn get_values(&self, signal: &str) -> Vec<Option<u32>> {
+        self.samples
+            .iter()
+            .map(|sample| sample.values.get(signal).copied().flatten())
+            .collect()
+    }
+
+    fn get_value_at_cursor(&self, signal: &str) -> Option<u32> {
+        let cursor = self.cursor?;
+        self.samples
+            .iter()
+            .find(|sample| sample.cycle == cursor)
+            .and_then(|sample| sample.values.get(signal).copied().flatten())
+    }
+
+    fn controls_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.zoom, 4.0..=96.0).text("Zoom"));
+            egui::ComboBox::from_label("Radix")
+                .selected_text(self.radix.to_string())
+                .show_ui(ui, |ui| {
+                    for radix in Radix::iter() {
+                        ui.selectable_value(&mut self.radix, radix, radix.to_string());
+                    }
+                });
+            if let Some(cursor) = self.cursor {
+                ui.label(format!("Cursor: cycle {}", cursor));
+            }
+        });
+        ui.collapsing("Signals", |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for signal in self.signals.iter() {
+                        let mut is_shown = self.shown_signals.contains(signal);
+                        if ui.checkbox(&mut is_shown, signal).changed() {
+                            if is_shown {
+                                self.shown_signals.insert(signal.clone());
+                            } else {
+                                self.shown_signals.remove(signal);
+                            }
+                        }
+                    }
+                });
+        });
+    }
+
+    fn names_ui(&self, ui: &mut Ui, shown_signals: &[String]) {
+        let size = Vec2::new(
+            NAME_WIDTH,
+            HEADER_HEIGHT + ROW_HEIGHT * shown_signals.len() as f32,
+        );
+        let (resp, painter) = ui.allocate_painter(size, Sense::hover());
+        let origin = resp.rect.left_top();
+        let text_color = ui.visuals().text_color();
+
+        for (row, signal) in shown_signals.iter().enumerate() {
+            let y = origin.y + HEADER_HEIGHT + ROW_HEIGHT * (row as f32 + 0.5);
+            painter.text(
+                Pos2::new(origin.x, y),
+                Align2::LEFT_CENTER,
+                signal,
+                FontId::monospace(12.0),
+                text_color,
+            );
+            if self.cursor.is_some() {
+                painter.text(
+                    Pos2::new(origin.x + NAME_WIDTH - 5.0, y),
+                    Align2::RIGHT_CENTER,
+                    self.radix.format(self.get_value_at_cursor(signal)),
+                    FontId::monospace(12.0),
+                    Color32::LIGHT_BLUE,
+                );
+            }
+        }
+    }
+
+    fn waves_ui(&mut self, ui: &mut Ui, shown_signals: &[String]) {
+        let size = Vec2::new(
+            self.zoom * self.samples.len().max(1) as f32,
+            HEADER_HEIGHT + ROW_HEIGHT * shown_signals.len() as f32,
+        );
+        let (resp, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+        let origin = resp.rect.left_top();
+        let text_color = ui.visuals().text_color();
+        let grid_color = ui.visuals().weak_text_color();
+
+        if let Some(pos) = resp.interact_pointer_pos() {
+            let idx = ((pos.x - origin.x) / self.zoom).max(0.0) as usize;
+            self.cursor = self.samples.get(idx).map(|sample| sample.cycle);
+        }
+
+        // label a cycle every so often, without them running into each other
+        let label_every = (60.0 / self.zoom).ceil().max(1.0) as usize;
+        for (idx, sample) in self.samples.iter().enumerate() {
+            let x = origin.x + self.zoom * idx as f32;
+            if idx % label_every == 0 {
+                painter.text(
+                    Pos2::new(x + 2.0, origin.y + HEADER_HEIGHT / 2.0),
+                    Align2::LEFT_CENTER,
+                    sample.cycle.to_string(),
+                    FontId::monospace(10.0),
+                    grid_color,
+                );
+                painter.line_segment(
+                    [
+                        Pos2::new(x, origin.y + HEADER_HEIGHT),
+                        Pos2::new(x, resp.rect.bottom()),
+                    ],
+                    Stroke::new(0.5, grid_color),
+                );
+            }
+        }
+
+        for (row, signal) in shown_signals.iter().enumerate() {
+            let top = origin.y
+                + HEADER_HEIGHT
+                + ROW_HEIGHT * row as f32
+                + (ROW_HEIGHT - WAVE_HEIGHT) / 2.0;
+            let values = self.get_values(signal);
+            // a signal that has only been 0 or 1 is drawn as a single bit
+            let is_bit = values.iter().flatten().all(|value| *value <= 1);
+
+            let mut start = 0;
+            while start < values.len() {
+                let mut end = start + 1;
+                while end < values.len() && values[end] == values[start] {
+                    end += 1;
+                }
+                let rect = Rect::from_min_max(
+                    Pos2::new(origin.x + self.zoom * start as f32, top),
+                    Pos2::new(origin.x + self.zoom * end as f32, top + WAVE_HEIGHT),
+                );
+                if is_bit {
+                    self.draw_bit(&painter, rect, values[start], start > 0);
+                } else {
+                    self.draw_bus(&painter, rect, values[start], text_color);
+                }
+                start = end;
+            }
+        }
+
+        if let Some(idx) = self.cursor.and_then(|cursor| {
+            self.samples
+                .iter()
+                .position(|sample| sample.cycle == cursor)
+        }) {
+            let x = origin.x + self.zoom * (idx as f32 + 0.5);
+            painter.line_segment(
+                [Pos2::new(x, origin.y), Pos2::new(x, resp.rect.bottom())],
+                Stroke::new(1.0, Color32::LIGHT_BLUE),
+            );
+        }
+    }
+
+    fn draw_bit(&self, painter: &egui::Painter, rect: Rect, value: Option<u32>, has_edge: bool) {
+        let stroke = Stroke::new(1.5, Color32::LIGHT_GREEN);
+        match value {
+            Some(value) => {
+                let y = if value == 1 {
+                    rect.top()
+                } else {
+                    rect.bottom()
+                };
+                painter.line_segment(
+                    [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+                    stroke,
+                );
+                if has_edge {
+                    painter.line_segment([rect.left_top(), rect.left_bottom()], stroke);
+                }
+            }
+            None => painter.rect_filled(rect, 0.0, Color32::DARK_RED),
+        }
+    }
+
+    fn draw_bus(
+        &self,
+        painter: &egui::Painter,
+        rect: Rect,
+        value: Option<u32>,
+        text_color: Color32,
+    ) {
+        let slant = (self.zoom / 4.0).min(4.0);
+        let (fill, stroke_color) = match value {
+            Some(_) => (Color32::TRANSPARENT, Color32::LIGHT_GREEN),
+            None => (Color32::DARK_RED, Color32::RED),
+        };
+        painter.add(PathShape {
+            points: vec![
+                rect.left_center(),
+                rect.left_top() + Vec2::new(slant, 0.0),
+                rect.right_top() - Vec2::new(slant, 0.0),
+                rect.right_center(),
+                rect.right_bottom() - Vec2::new(slant, 0.0),
+                rect.left_bottom() + Vec2::new(slant, 0.0),
+            ],
+            closed: true,
+            fill,
+            stroke: PathStroke::new(1.0, stroke_color),
+        });
+
+        let text = self.radix.format(value);
+        // roughly the width of a monospace character
+        if rect.width() - 2.0 * slant > 7.0 * text.len() as f32 {
+            painter.text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                text,
+                FontId::monospace(11.0),
+                text_color,
+            );
+        }
+    }
+}
+
+impl Tab for Waveform {
+    fn name(&self) -> &'static str {
+        "📈 Waveform"
+    }
+
+    fn show(&mut self, ctx: &Context, open: &mut bool) {
+        while let Ok(samples) = self.sample_receiver.try_recv() {
+            for sample in samples {
+                self.receive_sample(sample);
+            }
+        }
+
+        egui::Window::new(self.name())
+            .open(open)
+            .default_width(900.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                self.ui(ctx, ui);
+            });
+    }
+
+    fn ui(&mut self, _ctx: &Context, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            self.controls_ui(ui);
+            ui.separator();
+
+            let shown_signals = self
+                .signals
+                .iter()
+                .filter(|signal| self.shown_signals.contains(*signal))
+                .cloned()
+                .collect::<Vec<_>>();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal_top(|ui| {
+                    self.names_ui(ui, &shown_signals);
+                    egui::ScrollArea::horizontal()
+                        .stick_to_right(true)
+                        .show(ui, |ui| {
+                            self.waves_ui(ui, &shown_signals);
+                        });
+                });
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::util::datapath_component::{DatapathComponent, PortValues};
+
+    fn new_component(name: &str) -> DatapathComponent {
+        DatapathComponent {
+            name: name.to_string(),
+            values: PortValues::new(Vec::new(), Vec::new()),
+        }
+    }
+
+    fn new_sample(cycle: u128, components: &[(ComponentType, &str)]) -> WaveformSample {
+        let components = components
+            .iter()
+            .map(|&(component_type, name)| (component_type, new_component(name)))
+            .collect();
+        (cycle, components)
+    }
+
+    #[test]
+    fn test_merge_waveform_samples() {
+        let old = vec![
+            new_sample(0, &[
+                (ComponentType::Alu, "alu 0"),
+                (ComponentType::Pc, "pc 0"),
+            ]),
+            new_sample(1, &[
+                (ComponentType::Alu, "alu 1"),
+                (ComponentType::Ir, "ir 1"),
+            ]),
+        ];
+        let new = (2..WINDOW_CYCLES as u128 + 2)
+            .map(|cycle| new_sample(cycle, &[(ComponentType::Ir, "ir")]))
+            .collect();
+
+        let samples = merge_waveform_samples(old, new);
+        assert_eq!(samples.len(), WINDOW_CYCLES);
+        let (cycle, first_components) = &samples[0];
+        assert_eq!(*cycle, 2);
+        // the newest dropped value of each component is carried over, the sample's own wins
+        assert_eq!(first_components[&ComponentType::Alu].name, "alu 1");
+        assert_eq!(first_components[&ComponentType::Pc].name, "pc 0");
+        assert_eq!(first_components[&ComponentType::Ir].name, "ir");
+        assert_eq!(samples[1].1.len(), 1);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::backend::component::alu::{Alu, AluMux1, AluMux2};
 use crate::backend::component::cmp::{Cmp, CmpMux};
+use crate::backend::component::csr_file::CsrFile;
 use crate::backend::component::ir::IR;
 use crate::backend::component::mem_addr_mux::MemAddrMux;
 use crate::backend::component::mem_ctl::MemCtl;
+use crate::backend::component::muldiv::MulDiv;
 use crate::backend::component::pc::{Pc, PcMux};
 use crate::backend::component::regfile::{RegFile, RegFileMux};
 use crate::backend::core::ComponentType;
@@ -14,7 +16,7 @@ use egui::{Color32, Frame, Painter, Response, Stroke, Ui, Widget};
 use egui::{Layout, Pos2, Vec2};
 use std::collections::HashSet;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PortValue {
     pub name: String,
     pub value: String,
@@ -31,7 +33,7 @@ impl PortValue {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PortValues {
     pub inputs: Vec<PortValue>,
     pub outputs: Vec<PortValue>,
@@ -43,7 +45,7 @@ impl PortValues {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct DatapathComponent {
     pub name: String,
     pub values: PortValues,
@@ -60,7 +62,6 @@ impl Widget for DatapathComponentWidget {
         let name = self.datapath_component.name;
         let port_values = self.datapath_component.values;
         let on_hover = |ui: &mut Ui, port_value: &PortValue| {
-
             ui.label(port_value.name.clone()).on_hover_ui(|ui| {
                 if port_value.value.contains('\n') {
                     ui.add_sized(Vec2::new(135.0, 10.0), |ui: &mut Ui| {
@@ -131,9 +132,11 @@ impl ComponentType {
             ComponentType::AluMux2 => Pos2::new(780.0, 120.0),
             ComponentType::Cmp => Pos2::new(910.0, 430.0),
             ComponentType::CmpMux => Pos2::new(780.0, 480.0),
+            ComponentType::CsrFile => Pos2::new(410.0, 390.0),
             ComponentType::Ir => Pos2::new(180.0, 120.0),
             ComponentType::MemAddrMux => Pos2::new(50.0, 430.0),
             ComponentType::MemCtl => Pos2::new(50.0, 120.0),
+            ComponentType::MulDiv => Pos2::new(570.0, 450.0),
             ComponentType::Pc => Pos2::new(180.0, 550.0),
             ComponentType::PcMux => Pos2::new(50.0, 550.0),
             ComponentType::RegFile => Pos2::new(570.0, 120.0),
@@ -147,9 +150,11 @@ impl ComponentType {
             ComponentType::AluMux2 => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
             ComponentType::Cmp => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
             ComponentType::CmpMux => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
+            ComponentType::CsrFile => Vec2::new(GLOBAL_FRAME_WIDTH, 50.0),
             ComponentType::Ir => Vec2::new(GLOBAL_FRAME_WIDTH, 75.0),
             ComponentType::MemAddrMux => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
             ComponentType::MemCtl => Vec2::new(GLOBAL_FRAME_WIDTH, 75.0),
+            ComponentType::MulDiv => Vec2::new(GLOBAL_FRAME_WIDTH, 50.0),
             ComponentType::Pc => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
             ComponentType::PcMux => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
             ComponentType::RegFile => Vec2::new(GLOBAL_FRAME_WIDTH, 25.0),
@@ -239,18 +244,19 @@ impl DatapathComponentDisplayer for AluMux2 {
                 vec![
                     PortValue::new(
                         "imm".to_string(),
-                        format!("\
+                        format!(
+                            "\
                         i_imm: 0x{:X}\n\
                         u_imm: 0x{:X}\n\
                         b_imm: 0x{:X}\n\
                         s_imm: 0x{:X}\n\
                         j_imm: 0x{:X}\
                         ",
-                                self.i_imm.get_value(),
-                                self.u_imm.get_value(),
-                                self.b_imm.get_value(),
-                                self.s_imm.get_value(),
-                                self.j_imm.get_value(),
+                            self.i_imm.get_value(),
+                            self.u_imm.get_value(),
+                            self.b_imm.get_value(),
+                            self.s_imm.get_value(),
+                            self.j_imm.get_value(),
                         ),
                         [Ir_imm_AluMux2_imm].into(),
                     ),
@@ -293,7 +299,7 @@ impl DatapathComponentDisplayer for CmpMux {
                     ),
                     PortValue::new(
                         "sel".to_string(),
-                        format!("0x{:2b}", self.sel.get_value()),
+                        format!("0b{:2b}", self.sel.get_value()),
                         [].into(),
                     ),
                 ],
@@ -325,7 +331,7 @@ impl DatapathComponentDisplayer for Cmp {
                     ),
                     PortValue::new(
                         "op".to_string(),
-                        format!("0x{:3b}", self.op.get_value()),
+                        format!("0b{:3b}", self.op.get_value()),
                         [].into(),
                     ),
                 ],
@@ -359,20 +365,26 @@ impl DatapathComponentDisplayer for IR {
                 vec![
                     PortValue::new(
                         "imm".to_string(),
-                        format!("\
+                        format!(
+                            "\
                         i_imm: 0x{:X}\n\
                         u_imm: 0x{:X}\n\
                         b_imm: 0x{:X}\n\
                         s_imm: 0x{:X}\n\
                         j_imm: 0x{:X}\
                         ",
-                                self.i_imm.get_value(),
-                                self.u_imm.get_value(),
-                                self.b_imm.get_value(),
-                                self.s_imm.get_value(),
-                                self.j_imm.get_value(),
+                            self.i_imm.get_value(),
+                            self.u_imm.get_value(),
+                            self.b_imm.get_value(),
+                            self.s_imm.get_value(),
+                            self.j_imm.get_value(),
                         ),
-                        [Ir_imm_CmpMux_i_imm, Ir_imm_AluMux2_imm].into(),
+                        [
+                            Ir_imm_CmpMux_i_imm,
+                            Ir_imm_AluMux2_imm,
+                            Ir_imm_CsrFile_i_imm,
+                        ]
+                        .into(),
                     ),
                     PortValue::new(
                         "rs1_idx".to_string(),
@@ -469,7 +481,12 @@ impl DatapathComponentDisplayer for MemCtl {
                     ),
                     PortValue::new(
                         "wmask".to_string(),
-                        format!("0x{:4b}", self.cpu_wmask.get_value()),
+                        format!("0b{:4b}", self.cpu_wmask.get_value()),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "addr_sel".to_string(),
+                        format!("0b{:1b}", self.mem_addr_mux_sel.get_value()),
                         [].into(),
                     ),
                 ],
@@ -484,6 +501,11 @@ impl DatapathComponentDisplayer for MemCtl {
                         format!("0b{:1b}", self.cpu_resp.get_value()),
                         [].into(),
                     ),
+                    PortValue::new(
+                        "fault".to_string(),
+                        format!("0b{:1b}", self.cpu_fault.get_value()),
+                        [].into(),
+                    ),
                 ],
             ),
         }
@@ -515,6 +537,7 @@ impl DatapathComponentDisplayer for Pc {
                         Pc_out_AluMux1_pc,
                         Pc_out_PcMux_pc,
                         Pc_out_RegFileMux_pc,
+                        Pc_out_CsrFile_pc,
                     ]
                     .into(),
                 )],
@@ -539,9 +562,19 @@ impl DatapathComponentDisplayer for PcMux {
                         format!("0x{:X}", self.alu_out.get_value()),
                         [Alu_out_PcMux_alu_out].into(),
                     ),
+                    PortValue::new(
+                        "mtvec".to_string(),
+                        format!("0x{:X}", self.mtvec.get_value()),
+                        [CsrFile_mtvec_PcMux_mtvec].into(),
+                    ),
+                    PortValue::new(
+                        "mepc".to_string(),
+                        format!("0x{:X}", self.mepc.get_value()),
+                        [CsrFile_mepc_PcMux_mepc].into(),
+                    ),
                     PortValue::new(
                         "sel".to_string(),
-                        format!("0b{:1b}", self.sel.get_value()),
+                        format!("0b{:3b}", self.sel.get_value()),
                         [].into(),
                     ),
                 ],
@@ -591,7 +624,13 @@ impl DatapathComponentDisplayer for RegFile {
                     PortValue::new(
                         "rs1_dat".to_string(),
                         format!("0x{:X}", self.rs1_data.get_value()),
-                        [RegFile_rs1_data_Cmp_a, RegFile_rs1_data_AluMux1_rs1_data].into(),
+                        [
+                            RegFile_rs1_data_Cmp_a,
+                            RegFile_rs1_data_AluMux1_rs1_data,
+                            RegFile_rs1_data_MulDiv_a,
+                            RegFile_rs1_data_CsrFile_rs1_data,
+                        ]
+                        .into(),
                     ),
                     PortValue::new(
                         "rs2_dat".to_string(),
@@ -600,6 +639,7 @@ impl DatapathComponentDisplayer for RegFile {
                             RegFile_rs2_data_CmpMux_rs2_data,
                             RegFile_rs2_data_AluMux2_rs2_data,
                             RegFile_rs2_data_MemCtl_wdata,
+                            RegFile_rs2_data_MulDiv_b,
                         ]
                         .into(),
                     ),
@@ -645,9 +685,19 @@ impl DatapathComponentDisplayer for RegFileMux {
                         format!("0x{:X}", self.pc.get_value()),
                         [Pc_out_RegFileMux_pc].into(),
                     ),
+                    PortValue::new(
+                        "muldiv".to_string(),
+                        format!("0x{:X}", self.muldiv_out.get_value()),
+                        [MulDiv_out_RegFileMux_muldiv_out].into(),
+                    ),
+                    PortValue::new(
+                        "csr".to_string(),
+                        format!("0x{:X}", self.csr_out.get_value()),
+                        [CsrFile_out_RegFileMux_csr_out].into(),
+                    ),
                     PortValue::new(
                         "sel".to_string(),
-                        format!("0b{:3b}", self.sel.get_value()),
+                        format!("0b{:04b}", self.sel.get_value()),
                         [].into(),
                     ),
                 ],
@@ -660,3 +710,126 @@ impl DatapathComponentDisplayer for RegFileMux {
         }
     }
 }
+
+impl DatapathComponentDisplayer for MulDiv {
+    fn get_datapath_component(&self) -> DatapathComponent {
+        DatapathComponent {
+            name: "MulDiv".to_string(),
+            values: PortValues::new(
+                vec![
+                    PortValue::new(
+                        "a".to_string(),
+                        format!("0x{:X}", self.a.get_value()),
+                        [RegFile_rs1_data_MulDiv_a].into(),
+                    ),
+                    PortValue::new(
+                        "b".to_string(),
+                        format!("0x{:X}", self.b.get_value()),
+                        [RegFile_rs2_data_MulDiv_b].into(),
+                    ),
+                    PortValue::new(
+                        "op".to_string(),
+                        format!("0b{:3b}", self.op.get_value()),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "start".to_string(),
+                        format!("0b{:1b}", self.start.get_value()),
+                        [].into(),
+                    ),
+                ],
+                vec![
+                    PortValue::new(
+                        "out".to_string(),
+                        format!("0x{:X}", self.out.get_value()),
+                        [MulDiv_out_RegFileMux_muldiv_out].into(),
+                    ),
+                    PortValue::new(
+                        "resp".to_string(),
+                        format!("0b{:1b}", self.resp.get_value()),
+                        [].into(),
+                    ),
+                ],
+            ),
+        }
+    }
+}
+
+impl DatapathComponentDisplayer for CsrFile {
+    fn get_datapath_component(&self) -> DatapathComponent {
+        DatapathComponent {
+            name: "CsrFile".to_string(),
+            values: PortValues::new(
+                vec![
+                    PortValue::new(
+                        "i_imm".to_string(),
+                        format!("0x{:X}", self.i_imm.get_value()),
+                        [Ir_imm_CsrFile_i_imm].into(),
+                    ),
+                    PortValue::new(
+                        "rs1_dat".to_string(),
+                        format!("0x{:X}", self.rs1_data.get_value()),
+                        [RegFile_rs1_data_CsrFile_rs1_data].into(),
+                    ),
+                    PortValue::new(
+                        "pc".to_string(),
+                        format!("0x{:X}", self.pc.get_value()),
+                        [Pc_out_CsrFile_pc].into(),
+                    ),
+                    PortValue::new(
+                        "op".to_string(),
+                        format!("0b{:3b}", self.op.get_value()),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "wr".to_string(),
+                        format!("0b{:1b}", self.wr.get_value()),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "trap".to_string(),
+                        format!(
+                            "trap: 0b{:1b}\ncause: 0x{:X}\nval: 0x{:X}",
+                            self.trap.get_value(),
+                            self.trap_cause.get_value(),
+                            self.trap_val.get_value()
+                        ),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "mret".to_string(),
+                        format!("0b{:1b}", self.mret.get_value()),
+                        [].into(),
+                    ),
+                ],
+                vec![
+                    PortValue::new(
+                        "out".to_string(),
+                        format!("0x{:X}", self.out.get_value()),
+                        [CsrFile_out_RegFileMux_csr_out].into(),
+                    ),
+                    PortValue::new(
+                        "mtvec".to_string(),
+                        format!("0x{:X}", self.mtvec_out.get_value()),
+                        [CsrFile_mtvec_PcMux_mtvec].into(),
+                    ),
+                    PortValue::new(
+                        "mepc".to_string(),
+                        format!("0x{:X}", self.mepc_out.get_value()),
+                        [CsrFile_mepc_PcMux_mepc].into(),
+                    ),
+                    PortValue::new(
+                        "illegal".to_string(),
+                        format!("0b{:1b}", self.illegal.get_value()),
+                        [].into(),
+                    ),
+                    PortValue::new(
+                        "irq".to_string(),
+                        format!("0x{:X}", self.interrupt.get_value()),
+                        [].into(),
+                    ),
+                ],
+            ),
+        }
+    }
+}
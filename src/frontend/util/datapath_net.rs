@@ -1,6 +1,7 @@
 use crate::backend::core::ComponentType;
 use crate::backend::core::ComponentType::{
-    Alu, AluMux1, AluMux2, Cmp, CmpMux, Ir, MemAddrMux, MemCtl, Pc, PcMux, RegFile, RegFileMux,
+    Alu, AluMux1, AluMux2, Cmp, CmpMux, CsrFile, Ir, MemAddrMux, MemCtl, MulDiv, Pc, PcMux,
+    RegFile, RegFileMux,
 };
 use crate::frontend::util::datapath_component::GLOBAL_FRAME_WIDTH;
 use crate::frontend::util::datapath_net::DatapathNet::*;
@@ -26,6 +27,15 @@ pub enum DatapathNet {
     RegFile_rs2_data_AluMux2_rs2_data,
     RegFile_rs2_data_CmpMux_rs2_data,
     RegFile_rs2_data_MemCtl_wdata,
+    RegFile_rs1_data_MulDiv_a,
+    RegFile_rs2_data_MulDiv_b,
+    MulDiv_out_RegFileMux_muldiv_out,
+    Ir_imm_CsrFile_i_imm,
+    RegFile_rs1_data_CsrFile_rs1_data,
+    Pc_out_CsrFile_pc,
+    CsrFile_out_RegFileMux_csr_out,
+    CsrFile_mtvec_PcMux_mtvec,
+    CsrFile_mepc_PcMux_mepc,
     Pc_out_AluMux1_pc,
     Pc_out_RegFileMux_pc,
     Pc_out_MemAddrMux_pc,
@@ -243,6 +253,138 @@ impl DatapathNetDispalyer for DatapathNet {
                     end,
                 ]
             }
+            RegFile_rs1_data_MulDiv_a => {
+                let start = self.get_nth_port_pos(&RegFile, 0, false);
+                let end = self.get_nth_port_pos(&MulDiv, 0, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(10.0, 0.0)).x,
+                    MulDiv.get_frame_offset().y - 10.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(10.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-10.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-10.0, 0.0),
+                    end,
+                ]
+            }
+            RegFile_rs2_data_MulDiv_b => {
+                let start = self.get_nth_port_pos(&RegFile, 1, false);
+                let end = self.get_nth_port_pos(&MulDiv, 1, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(30.0, 0.0)).x,
+                    MulDiv.get_frame_offset().y - 20.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(30.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-15.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-15.0, 0.0),
+                    end,
+                ]
+            }
+            MulDiv_out_RegFileMux_muldiv_out => {
+                let start = self.get_nth_port_pos(&MulDiv, 0, false);
+                let end = self.get_nth_port_pos(&RegFileMux, 6, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(15.0, 0.0)).x,
+                    MulDiv.get_frame_offset().y + 160.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(15.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-40.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-40.0, 0.0),
+                    end,
+                ]
+            }
+            Ir_imm_CsrFile_i_imm => {
+                let start = self.get_nth_port_pos(&Ir, 0, false);
+                let end = self.get_nth_port_pos(&CsrFile, 0, true);
+                vec![
+                    start,
+                    start + Vec2::new(110.0, 0.0),
+                    end + Vec2::new(-10.0, 0.0),
+                    end,
+                ]
+            }
+            RegFile_rs1_data_CsrFile_rs1_data => {
+                let start = self.get_nth_port_pos(&RegFile, 0, false);
+                let end = self.get_nth_port_pos(&CsrFile, 1, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(20.0, 0.0)).x,
+                    CsrFile.get_frame_offset().y - 20.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(20.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-20.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-20.0, 0.0),
+                    end,
+                ]
+            }
+            Pc_out_CsrFile_pc => {
+                let start = self.get_nth_port_pos(&Pc, 0, false);
+                let end = self.get_nth_port_pos(&CsrFile, 2, true);
+                vec![
+                    start,
+                    start + Vec2::new(100.0, 0.0),
+                    end + Vec2::new(-20.0, 0.0),
+                    end,
+                ]
+            }
+            CsrFile_out_RegFileMux_csr_out => {
+                let start = self.get_nth_port_pos(&CsrFile, 0, false);
+                let end = self.get_nth_port_pos(&RegFileMux, 7, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(10.0, 0.0)).x,
+                    CsrFile.get_frame_offset().y - 10.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(10.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-50.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-50.0, 0.0),
+                    end,
+                ]
+            }
+            CsrFile_mtvec_PcMux_mtvec => {
+                let start = self.get_nth_port_pos(&CsrFile, 1, false);
+                let end = self.get_nth_port_pos(&PcMux, 2, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(20.0, 0.0)).x,
+                    PcMux.get_frame_offset().y - 20.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(20.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-20.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-20.0, 0.0),
+                    end,
+                ]
+            }
+            CsrFile_mepc_PcMux_mepc => {
+                let start = self.get_nth_port_pos(&CsrFile, 2, false);
+                let end = self.get_nth_port_pos(&PcMux, 3, true);
+                let mid = Pos2::new(
+                    (start + Vec2::new(30.0, 0.0)).x,
+                    PcMux.get_frame_offset().y - 30.0,
+                );
+                vec![
+                    start,
+                    start + Vec2::new(30.0, 0.0),
+                    mid,
+                    Pos2::new((end + Vec2::new(-30.0, 0.0)).x, mid.y),
+                    end + Vec2::new(-30.0, 0.0),
+                    end,
+                ]
+            }
             Pc_out_AluMux1_pc => {
                 let start = self.get_nth_port_pos(&Pc, 0, false);
                 let end = self.get_nth_port_pos(&AluMux1, 1, true);
@@ -1,3 +1,4 @@
 pub mod datapath_component;
 pub mod datapath_net;
+pub mod state_channel;
 pub mod vga;
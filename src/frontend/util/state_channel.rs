@@ -0,0 +1,81 @@
+use crossbeam_channel::{Receiver, Sender, bounded};
+use std::cell::RefCell;
+
+/// A channel for state the ui shows, as opposed to events it has to handle every one of
+///
+/// It holds at most one value so that it does not grow while the ui is not drawn,
+/// sending replaces the value the ui has not taken yet
+pub fn state_channel<T>() -> (StateSender<T>, Receiver<T>) {
+    let (sender, receiver) = bounded(1);
+    (
+        StateSender {
+            sender,
+            receiver: receiver.clone(),
+            last_sent: RefCell::new(None),
+        },
+        receiver,
+    )
+}
+
+/// Only the core thread may send, which is what makes replacing the value race free
+pub struct StateSender<T> {
+    sender: Sender<T>,
+    /// To take back the value the ui has not taken yet
+    receiver: Receiver<T>,
+    last_sent: RefCell<Option<T>>,
+}
+
+impl<T: Clone + PartialEq> StateSender<T> {
+    /// Does nothing if `value` is the same as the last one sent, the ui still has that
+    pub fn send(&self, value: T) {
+        if self.last_sent.borrow().as_ref() == Some(&value) {
+            return;
+        }
+        *self.last_sent.borrow_mut() = Some(value.clone());
+        self.replace(value);
+    }
+}
+
+impl<T> StateSender<T> {
+    /// Combines `value` with the value the ui has not taken yet, for batches that must not be lost
+    pub fn send_merged(&self, value: T, merge: impl FnOnce(T, T) -> T) {
+        let value = match self.receiver.try_recv() {
+            Ok(old_value) => merge(old_value, value),
+            Err(_) => value,
+        };
+        self.replace(value);
+    }
+
+    fn replace(&self, value: T) {
+        while self.receiver.try_recv().is_ok() {}
+        self.sender.try_send(value).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_channel_send() {
+        let (sender, receiver) = state_channel();
+        sender.send(1);
+        sender.send(2);
+        assert_eq!(receiver.try_recv(), Ok(2));
+        // the ui still has the last value
+        sender.send(2);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_state_channel_send_merged() {
+        let (sender, receiver) = state_channel();
+        let concat = |old: Vec<i32>, new: Vec<i32>| [old, new].concat();
+        sender.send_merged(vec![1, 2], concat);
+        sender.send_merged(vec![3], concat);
+        assert_eq!(receiver.try_recv(), Ok(vec![1, 2, 3]));
+        sender.send_merged(vec![4], concat);
+        assert_eq!(receiver.try_recv(), Ok(vec![4]));
+        assert!(receiver.try_recv().is_err());
+    }
+}
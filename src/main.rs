@@ -1,8 +1,9 @@
 #![feature(let_chains)]
 
 use crate::frontend::core_app::CoreApp;
+// shared with the library instead of being built again, what only the cli uses is not dead code
+use rsim_rv32i::backend;
 
-mod backend;
 mod frontend;
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,190 @@
+mod common;
+
+use common::get_register;
+use rsim_rv32i::backend::component::cache::{CacheConfig, CacheType, WritePolicy};
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::history::History;
+use rsim_rv32i::backend::util::types::Byte;
+
+const MAX_CYCLES: u128 = 100000;
+
+/// Stores 0 to 15 to consecutive words, then sums them back into a0
+const SUM_ARRAY: &str = "
+_start:
+    li t0, 0x50000000
+    li t1, 0
+    li t2, 16
+store:
+    slli t3, t1, 2
+    add t3, t3, t0
+    sw t1, 0(t3)
+    addi t1, t1, 1
+    blt t1, t2, store
+    li a0, 0
+    li t1, 0
+load:
+    slli t3, t1, 2
+    add t3, t3, t0
+    lw t4, 0(t3)
+    add a0, a0, t4
+    addi t1, t1, 1
+    blt t1, t2, load
+    slti zero, zero, -256
+";
+
+/// Adds the index to each of 32 words a few times over, 128 bytes do not fit in a small cache
+const ACCUMULATE_ARRAY: &str = "
+_start:
+    li t0, 0x50000000
+    li t1, 0
+    li t2, 32
+clear:
+    slli t3, t1, 2
+    add t3, t3, t0
+    sw zero, 0(t3)
+    addi t1, t1, 1
+    blt t1, t2, clear
+    li t5, 4
+outer:
+    li t1, 0
+inner:
+    slli t3, t1, 2
+    add t3, t3, t0
+    lw t4, 0(t3)
+    add t4, t4, t1
+    sw t4, 0(t3)
+    addi t1, t1, 1
+    blt t1, t2, inner
+    addi t5, t5, -1
+    bne t5, zero, outer
+    slti zero, zero, -256
+";
+
+fn new_core(source: &str, config: Option<CacheConfig>) -> Core {
+    let core = common::new_core(source);
+    if let Some(config) = config {
+        core.set_cache_config(CacheType::Instruction, config)
+            .unwrap();
+        core.set_cache_config(CacheType::Data, config).unwrap();
+    }
+    core
+}
+
+#[test]
+fn misses_stall_without_changing_results() {
+    let config = CacheConfig {
+        is_enabled: true,
+        ..Default::default()
+    };
+    let uncached_core = new_core(SUM_ARRAY, None);
+    uncached_core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+    let cached_core = new_core(SUM_ARRAY, Some(config));
+    cached_core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+
+    assert!(uncached_core.has_ended() && cached_core.has_ended());
+    assert_eq!(get_register(&uncached_core, 10), Some(120));
+    assert_eq!(get_register(&cached_core, 10), Some(120));
+    assert_eq!(
+        cached_core.get_instructions_ran(),
+        uncached_core.get_instructions_ran()
+    );
+
+    let (icache, dcache) = cached_core.get_caches();
+    let (icache_stats, dcache_stats) = (icache.get_stats(), dcache.get_stats());
+    assert!(icache_stats.get_hits() > 0);
+    assert_eq!(icache_stats.writes, 0);
+    // the stores allocate the 4 lines the loads then hit
+    assert_eq!(dcache_stats.writes, 16);
+    assert_eq!(dcache_stats.write_misses, 4);
+    assert_eq!(dcache_stats.reads, 16);
+    assert_eq!(dcache_stats.read_misses, 0);
+    assert!(cached_core.get_cycles_ran() > uncached_core.get_cycles_ran());
+}
+
+#[test]
+fn write_policy_decides_when_memory_has_the_stores() {
+    let write_back_core = new_core(
+        SUM_ARRAY,
+        Some(CacheConfig {
+            is_enabled: true,
+            ..Default::default()
+        }),
+    );
+    let write_through_core = new_core(
+        SUM_ARRAY,
+        Some(CacheConfig {
+            is_enabled: true,
+            write_policy: WritePolicy::WriteThrough,
+            ..Default::default()
+        }),
+    );
+    // the last word stored, 15
+    let addr = 0x5000003C;
+    for core in [&write_back_core, &write_through_core] {
+        core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+        assert!(core.has_ended());
+        assert_eq!(get_register(core, 10), Some(120));
+        assert_eq!(core.peek_byte(addr), Byte::from(15u8));
+    }
+
+    // the dirty line has not been evicted yet
+    let (_, dcache) = write_back_core.get_caches();
+    let line = dcache.get_line(addr).unwrap();
+    assert!(line.is_dirty);
+    assert_eq!(line.data[0xC], Byte::from(15u8));
+    let mem_ctl = write_back_core.mem_ctl.lock().unwrap();
+    assert_ne!(mem_ctl.backend_mem.get(addr), Byte::from(15u8));
+
+    // the stores that missed did not fill a line, so the loads did
+    let (_, dcache) = write_through_core.get_caches();
+    let line = dcache.get_line(addr).unwrap();
+    assert!(!line.is_dirty);
+    assert_eq!(dcache.get_stats().read_misses, 4);
+    let mem_ctl = write_through_core.mem_ctl.lock().unwrap();
+    assert_eq!(mem_ctl.backend_mem.get(addr), Byte::from(15u8));
+}
+
+#[test]
+fn caches_are_restored_with_the_state() {
+    // small enough to evict dirty lines, random so that the replacement state matters too
+    let config = CacheConfig::parse("size=64,ways=2,line=16,policy=random").unwrap();
+    let run_to = |core: &Core, cycles_ran: u128| {
+        while core.get_cycles_ran() < cycles_ran {
+            core.run_cycle(None::<fn()>);
+        }
+    };
+    let get_state = |core: &Core| {
+        (
+            core.get_cycles_ran(),
+            core.get_instructions_ran(),
+            core.get_caches(),
+        )
+    };
+    // past a settled snapshot, which runs a cycle to settle when it is restored
+    let target_cycle = 2 * History::SNAPSHOT_INTERVAL + 123;
+
+    let straight_core = new_core(ACCUMULATE_ARRAY, Some(config));
+    run_to(&straight_core, target_cycle);
+    let state = get_state(&straight_core);
+
+    let rewound_core = new_core(ACCUMULATE_ARRAY, Some(config));
+    run_to(&rewound_core, target_cycle + 1);
+    assert!(rewound_core.step_back_cycle());
+    assert_eq!(get_state(&rewound_core), state);
+
+    let loaded_core = Core::new(1, None, None);
+    loaded_core
+        .load_checkpoint(&straight_core.save_checkpoint())
+        .unwrap();
+    assert_eq!(get_state(&loaded_core), state);
+
+    for core in [&straight_core, &rewound_core, &loaded_core] {
+        core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+        assert!(core.has_ended());
+    }
+    let state = get_state(&straight_core);
+    let (_, _, (_, dcache)) = &state;
+    assert!(dcache.get_stats().writebacks > 0);
+    assert_eq!(get_state(&rewound_core), state);
+    assert_eq!(get_state(&loaded_core), state);
+}
@@ -0,0 +1,26 @@
+//! Fixtures shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use rsim_rv32i::backend::assembler::assemble;
+use rsim_rv32i::backend::core::Core;
+use std::fs::File;
+use std::path::Path;
+
+/// A core with `source` assembled and loaded
+pub fn new_core(source: &str) -> Core {
+    let core = Core::new(1, None, None);
+    core.load_program(&assemble(source).unwrap());
+    core
+}
+
+/// Like `new_core`, writing the commit log to `commit_path`
+pub fn new_logging_core(source: &str, commit_path: &Path) -> Core {
+    let core = Core::new(1, Some(File::create(commit_path).unwrap()), None);
+    core.load_program(&assemble(source).unwrap());
+    core
+}
+
+/// None if the register holds an unknown value
+pub fn get_register(core: &Core, idx: usize) -> Option<u32> {
+    core.regfile.lock().unwrap().registers.data[idx].into()
+}
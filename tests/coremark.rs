@@ -5,9 +5,9 @@ use std::fs::File;
 #[cfg_attr(feature = "reset_unknown_regfile", ignore)]
 fn coremark() {
     let commit_file = File::create("./tests/coremark.log").unwrap();
-    let core = Core::new(4, Some(commit_file));
+    let core = Core::new(4, Some(commit_file), None);
     let coremark = std::fs::read("./tests/coremark.elf").unwrap();
-    core.load_elf(coremark.as_slice());
+    core.load_elf(coremark.as_slice()).unwrap();
 
     core.run_end(Some(|| {}));
 }
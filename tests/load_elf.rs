@@ -0,0 +1,206 @@
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::util::types::Byte;
+
+const ENTRY: u32 = 0x40000000;
+const NOP: [u8; 4] = [0x13, 0x00, 0x00, 0x00];
+
+const PT_LOAD: u32 = 1;
+const SHT_PROGBITS: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+
+/// A `PT_LOAD` segment, `data` is what is in the file
+struct Segment {
+    vaddr: u32,
+    data: &'static [u8],
+    mem_size: u32,
+}
+
+/// `data` is ignored for `SHT_NOBITS`
+struct Section {
+    sh_type: u32,
+    flags: u32,
+    addr: u32,
+    data: &'static [u8],
+    size: u32,
+}
+
+fn push_u16(elf: &mut Vec<u8>, value: u16) {
+    elf.extend(value.to_le_bytes());
+}
+
+fn push_u32(elf: &mut Vec<u8>, value: u32) {
+    elf.extend(value.to_le_bytes());
+}
+
+/// A 32 bit RISC-V executable, the headers come first and the data of each follows in order
+fn build_elf(segments: &[Segment], sections: &[Section]) -> Vec<u8> {
+    let ph_offset = 52;
+    let sh_offset = ph_offset + 32 * segments.len() as u32;
+    // the section header table starts with the null section
+    let num_sections = if sections.is_empty() {
+        0
+    } else {
+        sections.len() + 1
+    };
+    let mut data_offset = sh_offset + 40 * num_sections as u32;
+
+    let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1];
+    elf.resize(16, 0);
+    push_u16(&mut elf, 2); // ET_EXEC
+    push_u16(&mut elf, 243); // EM_RISCV
+    push_u32(&mut elf, 1);
+    push_u32(&mut elf, ENTRY);
+    push_u32(&mut elf, if segments.is_empty() { 0 } else { ph_offset });
+    push_u32(&mut elf, if sections.is_empty() { 0 } else { sh_offset });
+    push_u32(&mut elf, 0);
+    push_u16(&mut elf, 52);
+    push_u16(&mut elf, 32);
+    push_u16(&mut elf, segments.len() as u16);
+    push_u16(&mut elf, 40);
+    push_u16(&mut elf, num_sections as u16);
+    push_u16(&mut elf, 0);
+
+    let mut data = Vec::<u8>::new();
+    for segment in segments {
+        let file_size = segment.data.len() as u32;
+        // rwx, aligned to a word
+        for value in [
+            PT_LOAD,
+            data_offset,
+            segment.vaddr,
+            segment.vaddr,
+            file_size,
+            segment.mem_size,
+            0x7,
+            4,
+        ] {
+            push_u32(&mut elf, value);
+        }
+        data_offset += file_size;
+        data.extend(segment.data);
+    }
+    if !sections.is_empty() {
+        elf.extend([0; 40]);
+    }
+    for section in sections {
+        let section_data = if section.sh_type == SHT_NOBITS {
+            &[]
+        } else {
+            section.data
+        };
+        for value in [
+            0,
+            section.sh_type,
+            section.flags,
+            section.addr,
+            data_offset,
+            section.size,
+            0,
+            0,
+            4,
+            0,
+        ] {
+            push_u32(&mut elf, value);
+        }
+        data_offset += section_data.len() as u32;
+        data.extend(section_data);
+    }
+    elf.extend(data);
+    elf
+}
+
+fn load_elf(elf: &[u8]) -> Core {
+    let core = Core::new(1, None, None);
+    core.load_elf(elf).unwrap();
+    core
+}
+
+#[test]
+fn segments_are_zero_filled_past_the_file() {
+    let core = load_elf(&build_elf(
+        &[Segment {
+            vaddr: ENTRY,
+            data: &NOP,
+            mem_size: 16,
+        }],
+        &[],
+    ));
+
+    let mem_ctl = core.mem_ctl.lock().unwrap();
+    for (i, byte) in NOP.iter().enumerate() {
+        assert_eq!(mem_ctl.backend_mem.get(ENTRY + i as u32), Byte::from(*byte));
+    }
+    for addr in ENTRY + 4..ENTRY + 16 {
+        assert_eq!(mem_ctl.backend_mem.get(addr), Byte::from(0u8));
+    }
+    assert!(!mem_ctl.backend_mem.contains(ENTRY + 16));
+}
+
+#[test]
+fn sections_without_alloc_are_skipped() {
+    let comment_addr = ENTRY + 0x100;
+    let bss_addr = ENTRY + 0x200;
+    let core = load_elf(&build_elf(&[], &[
+        Section {
+            sh_type: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            addr: ENTRY,
+            data: &NOP,
+            size: NOP.len() as u32,
+        },
+        // not loaded even though it has an address
+        Section {
+            sh_type: SHT_PROGBITS,
+            flags: 0,
+            addr: comment_addr,
+            data: b"GCC",
+            size: 3,
+        },
+        Section {
+            sh_type: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+            addr: bss_addr,
+            data: &[],
+            size: 8,
+        },
+    ]));
+
+    let mem_ctl = core.mem_ctl.lock().unwrap();
+    assert_eq!(mem_ctl.backend_mem.get(ENTRY), Byte::from(NOP[0]));
+    for addr in comment_addr..comment_addr + 3 {
+        assert!(!mem_ctl.backend_mem.contains(addr));
+    }
+    for addr in bss_addr..bss_addr + 8 {
+        assert_eq!(mem_ctl.backend_mem.get(addr), Byte::from(0u8));
+    }
+}
+
+#[test]
+fn loading_replaces_the_program_unless_it_fails() {
+    let core = load_elf(&build_elf(
+        &[Segment {
+            vaddr: ENTRY,
+            data: &NOP,
+            mem_size: 4,
+        }],
+        &[],
+    ));
+    let other_addr = ENTRY + 0x100;
+    core.load_elf(&build_elf(
+        &[Segment {
+            vaddr: other_addr,
+            data: &NOP,
+            mem_size: 4,
+        }],
+        &[],
+    ))
+    .unwrap();
+    assert!(core.load_elf(b"not an ELF").is_err());
+
+    let mem_ctl = core.mem_ctl.lock().unwrap();
+    assert!(!mem_ctl.backend_mem.contains(ENTRY));
+    assert_eq!(mem_ctl.backend_mem.get(other_addr), Byte::from(NOP[0]));
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::get_register;
+use rsim_rv32i::backend::component::mem_ctl::{
+    AccessFault, AccessType, MemMapType, MemRegion, Permissions,
+};
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::util::types::trap_cause;
+
+const MAX_CYCLES: u128 = 1000;
+
+/// Stores to its own first instruction, the handler keeps mcause in a0 and mtval in a1
+const STORE_TO_ROM: &str = "
+_start:
+    la t0, handler
+    csrrw zero, mtvec, t0
+    li t1, 0x40000000
+    sw zero, 0(t1)
+handler:
+    csrrs a0, mcause, zero
+    csrrs a1, mtval, zero
+    slti zero, zero, -256
+";
+
+fn new_core(source: &str) -> Core {
+    let core = common::new_core(source);
+    core.set_mem_map_type(MemMapType::LinkerScript);
+    core
+}
+
+#[test]
+fn store_to_rom_traps() {
+    for fast_forward in [false, true] {
+        let core = new_core(STORE_TO_ROM);
+        let first_inst = core
+            .mem_ctl
+            .lock()
+            .unwrap()
+            .read_word(0x40000000, 0x0F, true);
+        if fast_forward {
+            core.fast_forward(&Default::default(), Some(MAX_CYCLES), None::<fn()>);
+        } else {
+            core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+        }
+
+        assert!(core.has_ended());
+        assert_eq!(
+            get_register(&core, 10),
+            Some(trap_cause::STORE_ACCESS_FAULT)
+        );
+        assert_eq!(get_register(&core, 11), Some(0x40000000));
+        assert_eq!(
+            core.mem_ctl
+                .lock()
+                .unwrap()
+                .read_word(0x40000000, 0x0F, true),
+            first_inst
+        );
+        assert_eq!(core.get_unhandled_access_fault(), None);
+    }
+}
+
+#[test]
+fn unhandled_load_from_unmapped_halts() {
+    for fast_forward in [false, true] {
+        let core = new_core("_start:\n    lw a0, 0(zero)\n");
+        if fast_forward {
+            core.fast_forward(&Default::default(), Some(MAX_CYCLES), None::<fn()>);
+        } else {
+            core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+        }
+
+        assert!(core.has_ended());
+        assert_eq!(
+            core.get_unhandled_access_fault(),
+            Some(AccessFault {
+                access_type: AccessType::Load,
+                addr: 0,
+                region: None,
+            })
+        );
+    }
+}
+
+#[test]
+fn checkpoints_keep_the_memory_map() {
+    let saved_core = new_core(STORE_TO_ROM);
+    let checkpoint = saved_core.save_checkpoint();
+
+    // regions left over from another ELF must not apply to the loaded program
+    let core = Core::new(1, None, None);
+    core.set_mem_map_type(MemMapType::ElfSegments);
+    core.mem_ctl.lock().unwrap().elf_regions = vec![MemRegion {
+        name: "segment 0".to_string(),
+        addr_range: 0x40000000..0x40001000,
+        permissions: Permissions::RWX,
+    }];
+    core.load_checkpoint(&checkpoint).unwrap();
+    {
+        let mem_ctl = core.mem_ctl.lock().unwrap();
+        assert_eq!(mem_ctl.mem_map_type, MemMapType::LinkerScript);
+        assert!(mem_ctl.elf_regions.is_empty());
+    }
+
+    core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+    assert!(core.has_ended());
+    assert_eq!(
+        get_register(&core, 10),
+        Some(trap_cause::STORE_ACCESS_FAULT)
+    );
+}
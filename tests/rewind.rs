@@ -0,0 +1,94 @@
+mod common;
+
+use common::{get_register, new_core, new_logging_core};
+use rsim_rv32i::backend::component::mem_ctl::ClintMmioCtl;
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::util::types::Byte;
+use std::fs::File;
+use std::path::Path;
+
+const MAX_CYCLES: u128 = 10000;
+const INPUT_CYCLE: u128 = 50;
+
+/// Polls the keyboard and reads the byte typed into a0
+const READ_KEYBOARD: &str = "
+_start:
+    li t0, 0x000A0000
+poll:
+    lbu t1, 0(t0)
+    beq t1, zero, poll
+    lbu a0, 1(t0)
+    slti zero, zero, -256
+";
+
+/// Runs to `cycles_ran`, typing on the keyboard at `INPUT_CYCLE` along the way
+fn run_typing(commit_path: &Path, cycles_ran: u128) -> Core {
+    let core = new_logging_core(READ_KEYBOARD, commit_path);
+    while core.get_cycles_ran() < cycles_ran {
+        if core.get_cycles_ran() == INPUT_CYCLE {
+            core.send_input(b'x');
+        }
+        core.run_cycle(None::<fn()>);
+    }
+    core
+}
+
+#[test]
+fn rewinding_keeps_inputs_and_the_commit_log() {
+    let commit_path = std::env::temp_dir().join(format!("rsim_rewind_{}.log", std::process::id()));
+    let core = run_typing(&commit_path, INPUT_CYCLE + 5);
+    let commits = std::fs::read_to_string(&commit_path).unwrap();
+
+    // running forward from the snapshot before the input neither writes the commits again
+    // nor misses the byte
+    assert!(core.step_back_cycle());
+    assert_eq!(std::fs::read_to_string(&commit_path).unwrap(), commits);
+    core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+    assert!(core.has_ended());
+    assert_eq!(get_register(&core, 10), Some(b'x' as u32));
+
+    std::fs::remove_file(commit_path).unwrap();
+}
+
+#[test]
+fn checkpoints_keep_inputs_and_the_commit_log() {
+    let commit_path =
+        std::env::temp_dir().join(format!("rsim_checkpoint_{}.log", std::process::id()));
+    let saved_core = run_typing(&commit_path, INPUT_CYCLE + 5);
+    let checkpoint = saved_core.save_checkpoint();
+
+    let core = Core::new(1, Some(File::create(&commit_path).unwrap()), None);
+    core.load_checkpoint(&checkpoint).unwrap();
+    assert_eq!(core.get_cycles_ran(), INPUT_CYCLE + 5);
+    assert!(std::fs::read_to_string(&commit_path).unwrap().is_empty());
+    core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>);
+    assert!(core.has_ended());
+    assert_eq!(get_register(&core, 10), Some(b'x' as u32));
+
+    std::fs::remove_file(commit_path).unwrap();
+}
+
+#[test]
+fn pokes_reach_devices_and_are_rewound() {
+    let data_addr = 0x50000000;
+    let core = new_core(READ_KEYBOARD);
+    for _ in 0..INPUT_CYCLE {
+        core.run_cycle(None::<fn()>);
+    }
+
+    {
+        let mut mem_ctl = core.mem_ctl.lock().unwrap();
+        mem_ctl.poke_byte(data_addr, Byte::from(0xABu8));
+        mem_ctl.poke_byte(ClintMmioCtl::MTIMECMP_ADDR, Byte::from(0x10u8));
+        assert_eq!(mem_ctl.peek_byte(data_addr), Byte::from(0xABu8));
+        // the clint is still there for the cpu
+        assert!(!mem_ctl.backend_mem.contains(ClintMmioCtl::MTIMECMP_ADDR));
+    }
+    assert_eq!(
+        core.clint_mmio_ctl.lock().unwrap().get_mtimecmp() & 0xFF,
+        0x10
+    );
+
+    assert!(core.step_back_cycle());
+    assert!(!core.mem_ctl.lock().unwrap().backend_mem.contains(data_addr));
+}
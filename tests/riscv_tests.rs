@@ -0,0 +1,203 @@
+//! Runs prebuilt riscv-tests and riscv-arch-test ELFs through `Core`
+//!
+//! The ELFs are not checked in, point the environment variables at them
+//! - `RISCV_TESTS_DIR`, defaults to `./tests/riscv-tests`, the `isa` build directory with `rv32ui-p-*`/`rv32um-p-*`
+//! - `RISCV_ARCH_TEST_DIR`, defaults to `./tests/riscv-arch-test`, searched recursively for `*.elf`
+//!
+//! The tests are ignored by default, run them with `cargo test --test riscv_tests -- --ignored`.
+//! For arch tests the signature is written to `riscv-arch-test/<path of the ELF>.signature`
+//! under cargo's temporary directory for tests in `target/`,
+//! and is compared against `<name>.reference_output` next to the ELF if there is one.
+
+use rsim_rv32i::backend::core::Core;
+use rsim_rv32i::backend::cosim::{LockstepResult, Reference, run_lockstep};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const MAX_CYCLES: u128 = 10_000_000;
+
+fn get_dir(var: &str, default: &str) -> PathBuf {
+    let dir = PathBuf::from(std::env::var(var).unwrap_or(default.to_string()));
+    assert!(
+        dir.is_dir(),
+        "{} does not exist, set {} to where the ELFs are",
+        dir.display(),
+        var
+    );
+    dir
+}
+
+fn find_files(dir: &Path, filter: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(find_files(&path, filter));
+        } else if filter(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Returns the core after the program has exited through htif, or an error on timeout
+///
+/// With `fast_forward` the whole program runs on the functional model instead
+fn run_elf(elf_path: &Path, fast_forward: bool) -> Result<(Core, u32), String> {
+    let core = Core::new(1, None, None);
+    core.load_elf(std::fs::read(elf_path).unwrap().as_slice())?;
+    if fast_forward {
+        core.fast_forward(&Default::default(), Some(MAX_CYCLES), None::<fn()>);
+        if !core.has_ended() {
+            return Err(format!("did not end within {} instructions", MAX_CYCLES));
+        }
+    } else if !core.run_end_with_limit(Some(MAX_CYCLES), None, None::<fn()>) {
+        return Err(format!("did not end within {} cycles", MAX_CYCLES));
+    }
+
+    let exit_code = core.htif_mmio_ctl.lock().unwrap().get_exit_code();
+    match exit_code {
+        Some(exit_code) => Ok((core, exit_code)),
+        None => Err("ended without writing tohost".to_string()),
+    }
+}
+
+fn get_symbol_addr(core: &Core, name: &str) -> Option<u32> {
+    core.mem_ctl
+        .lock()
+        .unwrap()
+        .label
+        .iter()
+        .find(|(_, label)| *label == name)
+        .and_then(|(addr, _)| (*addr).into())
+}
+
+/// One little endian word per line like the reference outputs, unknown bytes as xx
+fn dump_signature(core: &Core) -> Result<String, String> {
+    let begin = get_symbol_addr(core, "begin_signature").ok_or("no begin_signature")?;
+    let end = get_symbol_addr(core, "end_signature").ok_or("no end_signature")?;
+
+    let mem_ctl = core.mem_ctl.lock().unwrap();
+    let mut signature = String::new();
+    for word_addr in (begin..end).step_by(4) {
+        for i in (0..4).rev() {
+            match Into::<Option<u8>>::into(mem_ctl.backend_mem.get(word_addr + i)) {
+                Some(byte) => write!(signature, "{:02x}", byte).unwrap(),
+                None => signature.push_str("xx"),
+            }
+        }
+        signature.push('\n');
+    }
+    Ok(signature)
+}
+
+fn report(failures: Vec<String>, num_tests: usize) {
+    assert!(
+        failures.is_empty(),
+        "{} of {} failed:\n{}",
+        failures.len(),
+        num_tests,
+        failures.join("\n")
+    );
+}
+
+fn find_riscv_tests(dir: &Path) -> Vec<PathBuf> {
+    find_files(dir, &|path| {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        (name.starts_with("rv32ui-p-") || name.starts_with("rv32um-p-")) && !name.contains('.')
+    })
+}
+
+fn run_riscv_tests(fast_forward: bool) {
+    let dir = get_dir("RISCV_TESTS_DIR", "./tests/riscv-tests");
+    let elfs = find_riscv_tests(&dir);
+
+    let mut failures = Vec::new();
+    for elf in elfs.iter() {
+        match run_elf(elf, fast_forward) {
+            // riscv-tests report the failing test case number as the exit code
+            Ok((_, 0)) => {}
+            Ok((_, test_num)) => failures.push(format!("{}: test #{}", elf.display(), test_num)),
+            Err(e) => failures.push(format!("{}: {}", elf.display(), e)),
+        }
+    }
+    report(failures, elfs.len());
+}
+
+#[test]
+#[ignore = "needs the riscv-tests ELFs"]
+fn riscv_tests() {
+    run_riscv_tests(false);
+}
+
+#[test]
+#[ignore = "needs the riscv-tests ELFs"]
+fn riscv_tests_fast_forward() {
+    run_riscv_tests(true);
+}
+
+/// The datapath and the functional model have to agree on every retired instruction
+#[test]
+#[ignore = "needs the riscv-tests ELFs"]
+fn riscv_tests_lockstep() {
+    let dir = get_dir("RISCV_TESTS_DIR", "./tests/riscv-tests");
+    let elfs = find_riscv_tests(&dir);
+
+    let mut failures = Vec::new();
+    for elf_path in elfs.iter() {
+        let elf = std::fs::read(elf_path).unwrap();
+        let core = Core::new(1, None, None);
+        core.load_elf(elf.as_slice()).unwrap();
+        let mut reference = Reference::from_elf(elf.as_slice()).unwrap();
+        match run_lockstep(&core, &mut reference, Some(MAX_CYCLES), None, None::<fn()>) {
+            Ok(LockstepResult::Ended) => {}
+            Ok(LockstepResult::LimitReached) => failures.push(format!(
+                "{}: did not end within {} cycles",
+                elf_path.display(),
+                MAX_CYCLES
+            )),
+            Ok(LockstepResult::Diverged(divergence)) => {
+                failures.push(format!("{}: {}", elf_path.display(), divergence))
+            }
+            Err(e) => failures.push(format!("{}: {}", elf_path.display(), e)),
+        }
+    }
+    report(failures, elfs.len());
+}
+
+#[test]
+#[ignore = "needs the riscv-arch-test ELFs"]
+fn riscv_arch_test() {
+    let dir = get_dir("RISCV_ARCH_TEST_DIR", "./tests/riscv-arch-test");
+    let signature_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("riscv-arch-test");
+    let elfs = find_files(&dir, &|path| {
+        path.extension().is_some_and(|extension| extension == "elf")
+    });
+
+    let mut failures = Vec::new();
+    for elf in elfs.iter() {
+        let signature = run_elf(elf, false).and_then(|(core, _)| dump_signature(&core));
+        match signature {
+            Ok(signature) => {
+                let signature_path = signature_dir
+                    .join(elf.strip_prefix(&dir).unwrap())
+                    .with_extension("signature");
+                std::fs::create_dir_all(signature_path.parent().unwrap()).unwrap();
+                std::fs::write(signature_path, &signature).unwrap();
+                // without a reference nothing was compared, which must not pass
+                match std::fs::read_to_string(elf.with_extension("reference_output")) {
+                    Ok(reference) if reference.trim() != signature.trim() => {
+                        failures.push(format!("{}: signature mismatch", elf.display()))
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        failures.push(format!("{}: no reference output: {}", elf.display(), e))
+                    }
+                }
+            }
+            Err(e) => failures.push(format!("{}: {}", elf.display(), e)),
+        }
+    }
+    report(failures, elfs.len());
+}
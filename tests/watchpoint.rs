@@ -0,0 +1,31 @@
+mod common;
+
+use common::new_core;
+use rsim_rv32i::backend::assembler::TEXT_BASE;
+use rsim_rv32i::backend::component::mem_ctl::{Watchpoint, WatchpointType};
+
+/// The load reads the word of the instruction doing the load
+const LOAD_OWN_INST: &str = "
+_start:
+    auipc t0, 0
+    lw a0, 4(t0)
+    slti zero, zero, -256
+";
+
+#[test]
+fn load_from_pc_hits_read_watchpoint() {
+    let core = new_core(LOAD_OWN_INST);
+    let lw_addr = TEXT_BASE + 4;
+    core.mem_ctl.lock().unwrap().watchpoints = vec![Watchpoint {
+        addr_range: lw_addr..lw_addr + 4,
+        watchpoint_type: WatchpointType::Read,
+    }];
+
+    // fetching the lw does not count as a read
+    core.run_instruction(None::<fn()>);
+    assert_eq!(core.get_watchpoint_hit(), None);
+    core.run_instruction(None::<fn()>);
+    let watchpoint_hit = core.get_watchpoint_hit().unwrap();
+    assert_eq!(watchpoint_hit.access_type, WatchpointType::Read);
+    assert_eq!(watchpoint_hit.addr, lw_addr);
+}